@@ -1,9 +1,11 @@
 pub use cassowary::*;
+use serde::Deserialize;
 use specs::prelude::{
   Component,
   Entities,
   Entity,
   HashMapStorage,
+  ReadStorage,
   VecStorage,
   WriteStorage
 };
@@ -13,6 +15,8 @@ mod constraints;
 pub use constraints::*;
 use super::UI;
 use super::picture::Picture;
+pub use super::systems::aspect_ratio::AspectRatio;
+pub use super::systems::baseline::FontMetrics;
 pub use super::systems::shrinkwrap::ContentSize;
 pub use super::systems::event::{Event, Events};
 
@@ -59,6 +63,30 @@ impl ElementBox {
   pub fn z_index(&self) -> i32 {
     self.z
   }
+
+  /// Whether the point is inside the box, edges included.
+  pub fn contains(&self, x: i32, y: i32) -> bool {
+    self.left() <= x
+      && self.right() >= x
+      && self.top() <= y
+      && self.bottom() >= y
+  }
+
+  /// The overlap of the two boxes, with no area if they don't overlap. The z
+  /// index is this box's.
+  pub fn intersection(&self, other: &ElementBox) -> ElementBox {
+    let x = i32::max(self.left(), other.left());
+    let y = i32::max(self.top(), other.top());
+    let right = i32::min(self.right(), other.right());
+    let bottom = i32::min(self.bottom(), other.bottom());
+    ElementBox {
+      x,
+      y,
+      z: self.z,
+      width: i32::max(0, right - x) as u32,
+      height: i32::max(0, bottom - y) as u32
+    }
+  }
 }
 
 
@@ -70,13 +98,30 @@ pub struct Color {
   pub a: u8
 }
 
+/// What to do with text that doesn't fit in its solved ElementBox.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Hash, Eq)]
+pub enum TextOverflow {
+  /// Scale the text into the box, squashing or stretching it.
+  Stretch,
+  /// Draw the text at its natural size, cut off at the edges of the box.
+  Clip,
+  /// Drop characters from the end and add an ellipsis.
+  Ellipsis,
+  /// Drop characters from the middle and add an ellipsis.
+  EllipsisMiddle,
+  /// Use a smaller font size until the text fits.
+  ShrinkToFit
+}
+
+
 #[derive(Clone, Component, Debug, PartialEq, Hash, Eq)]
 #[storage(VecStorage)]
 pub struct Text {
   pub font_path: String,
   pub font_size: u16,
   pub text_color: Color,
-  pub text: String
+  pub text: String,
+  pub overflow: TextOverflow
 }
 
 
@@ -86,7 +131,8 @@ impl Text {
       font_path: "komika.ttf".to_string(),
       font_size: 16,
       text_color: Color{ r: 255, g: 255, b: 255, a: 255 },
-      text: s.to_string()
+      text: s.to_string(),
+      overflow: TextOverflow::Stretch
     }
   }
 
@@ -95,6 +141,19 @@ impl Text {
     t.text_color = Color{ r, g, b, a};
     t
   }
+
+  /// Use a font family by name (or a font file by path), see `fonts`.
+  pub fn font(self, family: &str) -> Self {
+    let mut t = self;
+    t.font_path = family.to_string();
+    t
+  }
+
+  pub fn overflow(self, overflow: TextOverflow) -> Self {
+    let mut t = self;
+    t.overflow = overflow;
+    t
+  }
 }
 
 
@@ -103,13 +162,95 @@ impl Text {
 pub struct Name(pub String);
 
 
+/// The entity this one belongs to, eg a button's label belongs to its button.
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct Parent(pub Entity);
+
+
+/// The descendants (by Parent) of an entity with ClipChildren are only drawn
+/// inside its ElementBox, and only get mouse events there. Clips nest.
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct ClipChildren;
+
+
+/// The region an entity is clipped to by its ancestors, or None if none of
+/// them clip their children.
+pub fn clip_box(
+  ent: Entity,
+  parents: &ReadStorage<Parent>,
+  clips: &ReadStorage<ClipChildren>,
+  element_boxes: &ReadStorage<ElementBox>
+) -> Option<ElementBox> {
+  let mut may_clip:Option<ElementBox> = None;
+  let mut seen = vec![ent];
+  let mut may_parent =
+    parents
+    .get(ent)
+    .map(|Parent(parent)| *parent);
+  while let Some(parent) = may_parent {
+    if seen.contains(&parent) {
+      break;
+    }
+    seen.push(parent);
+    if clips.contains(parent) {
+      let el =
+        element_boxes
+        .get(parent)
+        .cloned()
+        .unwrap_or(ElementBox::new());
+      may_clip =
+        Some(
+          may_clip
+            .map(|clip| clip.intersection(&el))
+            .unwrap_or(el)
+        );
+    }
+    may_parent =
+      parents
+      .get(parent)
+      .map(|Parent(parent)| *parent);
+  }
+  may_clip
+}
+
+
+/// Whether the entity or any of its ancestors is Invisible. Hidden entities
+/// don't get mouse events.
+pub fn is_hidden(
+  ent: Entity,
+  parents: &ReadStorage<Parent>,
+  invisibles: &ReadStorage<Invisible>
+) -> bool {
+  let mut seen = vec![];
+  let mut may_ent = Some(ent);
+  while let Some(ent) = may_ent {
+    if invisibles.contains(ent) {
+      return true;
+    }
+    if seen.contains(&ent) {
+      break;
+    }
+    seen.push(ent);
+    may_ent =
+      parents
+      .get(ent)
+      .map(|Parent(parent)| *parent);
+  }
+  false
+}
+
+
 type EntityBuildData<'a> = (
   Entities<'a>,
+  WriteStorage<'a, AspectRatio>,
   WriteStorage<'a, Constraints<VariableX>>,
   WriteStorage<'a, Constraints<VariableY>>,
   WriteStorage<'a, Constraints<VariableZ>>,
   WriteStorage<'a, ElementBox>,
   WriteStorage<'a, Name>,
+  WriteStorage<'a, Parent>,
   WriteStorage<'a, Picture>,
   WriteStorage<'a, Text>
 );
@@ -120,13 +261,22 @@ pub struct ElementBuilder {
   left: Option<Expression<VariableX>>,
   width: Option<Expression<VariableX>>,
   right: Option<Expression<VariableX>>,
+  center_x: Option<Expression<VariableX>>,
+  min_width: Option<Expression<VariableX>>,
+  max_width: Option<Expression<VariableX>>,
   top: Option<Expression<VariableY>>,
   z: Option<Expression<VariableZ>>,
   height: Option<Expression<VariableY>>,
   bottom: Option<Expression<VariableY>>,
+  center_y: Option<Expression<VariableY>>,
+  baseline: Option<Expression<VariableY>>,
+  min_height: Option<Expression<VariableY>>,
+  max_height: Option<Expression<VariableY>>,
+  aspect_ratio: Option<f64>,
   text: Option<Text>,
   picture: Option<Picture>,
   name: Option<Name>,
+  parent: Option<Parent>,
   x_constraints: Option<Vec<Constraint<VariableX>>>,
   y_constraints: Option<Vec<Constraint<VariableY>>>,
   z_constraints: Option<Vec<Constraint<VariableZ>>>,
@@ -140,13 +290,22 @@ impl ElementBuilder {
       left: None,
       width: None,
       right: None,
+      center_x: None,
+      min_width: None,
+      max_width: None,
       top: None,
       z: None,
       height: None,
       bottom: None,
+      center_y: None,
+      baseline: None,
+      min_height: None,
+      max_height: None,
+      aspect_ratio: None,
       picture: None,
       text: None,
       name: None,
+      parent: None,
       x_constraints: None,
       y_constraints: None,
       z_constraints: None,
@@ -166,6 +325,12 @@ impl ElementBuilder {
     eb
   }
 
+  pub fn parent(self, ent: Entity) -> Self {
+    let mut eb = self;
+    eb.parent = Some(Parent(ent));
+    eb
+  }
+
   pub fn text(self, t: &Text) -> Self {
     let mut eb = self;
     eb.text = Some(t.clone());
@@ -208,6 +373,81 @@ impl ElementBuilder {
     eb
   }
 
+  /// Size this element to a percentage of another entity's (or the stage's)
+  /// width. The stage's size is updated every maintain so this tracks the
+  /// window.
+  pub fn width_percent<T: HasXConstraints>(self, t: T, percent: f64) -> Self {
+    self.width(t.percent_width(percent))
+  }
+
+  /// Size this element to a percentage of another entity's (or the stage's)
+  /// height.
+  pub fn height_percent<T: HasYConstraints>(self, t: T, percent: f64) -> Self {
+    self.height(t.percent_height(percent))
+  }
+
+  /// Center this element horizontally on the given expression.
+  pub fn center_x<T: Into<Expression<VariableX>>>(self, t: T) -> Self {
+    let mut eb = self;
+    eb.center_x = Some(t.into());
+    eb
+  }
+
+  /// Center this element vertically on the given expression.
+  pub fn center_y<T: Into<Expression<VariableY>>>(self, t: T) -> Self {
+    let mut eb = self;
+    eb.center_y = Some(t.into());
+    eb
+  }
+
+  /// Put this element's text baseline on the given expression, eg
+  /// `.baseline(title.baseline())` to line a small label up with a big one.
+  pub fn baseline<T: Into<Expression<VariableY>>>(self, t: T) -> Self {
+    let mut eb = self;
+    eb.baseline = Some(t.into());
+    eb
+  }
+
+  /// Center this element within another entity (or the stage).
+  pub fn center_in<T: HasXConstraints + HasYConstraints>(self, t: T) -> Self {
+    self
+      .center_x(t.center_x())
+      .center_y(t.center_y())
+  }
+
+  pub fn min_width<T: Into<Expression<VariableX>>>(self, t: T) -> Self {
+    let mut eb = self;
+    eb.min_width = Some(t.into());
+    eb
+  }
+
+  pub fn max_width<T: Into<Expression<VariableX>>>(self, t: T) -> Self {
+    let mut eb = self;
+    eb.max_width = Some(t.into());
+    eb
+  }
+
+  pub fn min_height<T: Into<Expression<VariableY>>>(self, t: T) -> Self {
+    let mut eb = self;
+    eb.min_height = Some(t.into());
+    eb
+  }
+
+  pub fn max_height<T: Into<Expression<VariableY>>>(self, t: T) -> Self {
+    let mut eb = self;
+    eb.max_height = Some(t.into());
+    eb
+  }
+
+  /// Keep this element's width at `ratio` times its height.
+  /// The width follows the height, so constrain the height and let the width
+  /// float (shrinkwrapped widths are weak and will give way).
+  pub fn aspect_ratio(self, ratio: f64) -> Self {
+    let mut eb = self;
+    eb.aspect_ratio = Some(ratio);
+    eb
+  }
+
   pub fn z_index<T: Into<Expression<VariableZ>>>(self, t:T) -> Self {
     let mut eb = self;
     eb.z = Some(t.into());
@@ -238,6 +478,22 @@ impl ElementBuilder {
     eb
   }
 
+  /// Set the x, y and z constraints all at once, eg from `constraints!`.
+  pub fn constraints(self, cs: AxisConstraints) -> Self {
+    let AxisConstraints{ x, y, z } = cs;
+    let mut eb = self;
+    if !x.is_empty() {
+      eb.x_constraints = Some(x);
+    }
+    if !y.is_empty() {
+      eb.y_constraints = Some(y);
+    }
+    if !z.is_empty() {
+      eb.z_constraints = Some(z);
+    }
+    eb
+  }
+
   pub fn update(self, ui: &mut UI, ent:Entity) {
     ui.world
       .exec(|data:EntityBuildData| self.build_with(data, Some(ent)));
@@ -246,11 +502,13 @@ impl ElementBuilder {
   fn build_with(
     self,
     (entities,
+     mut aspect_ratios,
      mut constraints_x,
      mut constraints_y,
      mut constraints_z,
      mut element_boxes,
      mut names,
+     mut parents,
      mut pictures,
      mut texts
     ):EntityBuildData,
@@ -264,6 +522,9 @@ impl ElementBuilder {
       self.left.is_some()
       || self.width.is_some()
       || self.right.is_some()
+      || self.center_x.is_some()
+      || self.min_width.is_some()
+      || self.max_width.is_some()
       || self.x_constraints.is_some();
     if has_x_constraints {
       let may_xs:Vec<Option<Constraint<VariableX>>> =
@@ -271,6 +532,9 @@ impl ElementBuilder {
           self.left.map(|x| ent.left().is(x)),
           self.width.map(|x| ent.width().is(x)),
           self.right.map(|x| ent.right().is(x)),
+          self.center_x.map(|x| ent.center_x().is(x)),
+          self.min_width.map(|x| ent.width().is_ge(x)),
+          self.max_width.map(|x| ent.width().is_le(x)),
           // We need a concrete relationship between left, width, and right
           Some(ent.right().is(ent.left() + ent.width()))
         ];
@@ -295,6 +559,10 @@ impl ElementBuilder {
       self.top.is_some()
       || self.height.is_some()
       || self.bottom.is_some()
+      || self.center_y.is_some()
+      || self.baseline.is_some()
+      || self.min_height.is_some()
+      || self.max_height.is_some()
       || self.y_constraints.is_some();
     if has_y_constraints {
       let may_ys:Vec<Option<Constraint<VariableY>>> =
@@ -302,6 +570,10 @@ impl ElementBuilder {
           self.top.map(|y| ent.top().is(y)),
           self.height.map(|y| ent.height().is(y)),
           self.bottom.map(|y| ent.bottom().is(y)),
+          self.center_y.map(|y| ent.center_y().is(y)),
+          self.baseline.map(|y| ent.baseline().is(y)),
+          self.min_height.map(|y| ent.height().is_ge(y)),
+          self.max_height.map(|y| ent.height().is_le(y)),
           // We need a concrete relationship between top, height, and bottom
           Some(ent.bottom().is(ent.top() + ent.height()))
         ];
@@ -338,6 +610,10 @@ impl ElementBuilder {
         .expect("Could not insert z constraints in ElementBuilder::build");
     }
 
+    self
+      .aspect_ratio
+      .map(|ratio| aspect_ratios.insert(ent, AspectRatio(ratio)));
+
     self
       .text
       .map(|t| texts.insert(ent, t));
@@ -350,6 +626,10 @@ impl ElementBuilder {
       .name
       .map(|name| names.insert(ent, name));
 
+    self
+      .parent
+      .map(|parent| parents.insert(ent, parent));
+
     if !element_boxes.contains(ent) {
       element_boxes
         .insert(ent, ElementBox::new())
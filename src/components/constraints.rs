@@ -16,7 +16,7 @@ use super::Name;
 // TODO: Add MouseX and MouseY to constraint variables
 #[derive(Clone, Component, Debug, Eq, Hash, PartialEq)]
 pub enum VariableX {
-  Left(Option<Entity>), Width(Option<Entity>)
+  Left(Option<Entity>), Width(Option<Entity>), Scroll(Option<Entity>)
 }
 derive_syntax_for!(VariableX);
 
@@ -27,6 +27,7 @@ impl VariableX {
       match self {
         VariableX::Left(may_ent) => {("left", may_ent)}
         VariableX::Width(may_ent) => {("width", may_ent)}
+        VariableX::Scroll(may_ent) => {("scroll_x", may_ent)}
       };
     let me:String =
       may_ent
@@ -45,7 +46,8 @@ impl VariableX {
 
 #[derive(Clone, Component, Debug, Eq, Hash, PartialEq)]
 pub enum VariableY {
-  Top(Option<Entity>), Height(Option<Entity>)
+  Top(Option<Entity>), Height(Option<Entity>), Baseline(Option<Entity>),
+  Scroll(Option<Entity>)
 }
 derive_syntax_for!(VariableY);
 
@@ -56,6 +58,8 @@ impl VariableY {
       match self {
         VariableY::Top(may_ent) => {("top", may_ent)}
         VariableY::Height(may_ent) => {("height", may_ent)}
+        VariableY::Baseline(may_ent) => {("baseline", may_ent)}
+        VariableY::Scroll(may_ent) => {("scroll_y", may_ent)}
       };
     let me:String =
       may_ent
@@ -78,6 +82,20 @@ pub struct VariableZ(pub Entity);
 derive_syntax_for!(VariableZ);
 
 
+impl VariableZ {
+  pub fn to_pathy_string(&self, names: &ReadStorage<Name>) -> String {
+    let VariableZ(ent) = self;
+    let me:String =
+      names
+      .get(*ent)
+      .map(|Name(s)| s.clone())
+      .unwrap_or(format!("entity({:?})", ent.id()));
+
+    format!("{}.z", me)
+  }
+}
+
+
 #[derive(Clone, Debug)]
 pub struct Constraints<T>(pub Vec<Constraint<T>>);
 
@@ -87,12 +105,196 @@ impl<T: Send + Sync + Any> Component for Constraints<T> {
 }
 
 
+/// Constraints sorted by axis. This is what the `constraints!` macro
+/// produces.
+#[derive(Clone, Debug)]
+pub struct AxisConstraints {
+  pub x: Vec<Constraint<VariableX>>,
+  pub y: Vec<Constraint<VariableY>>,
+  pub z: Vec<Constraint<VariableZ>>
+}
+
+
+impl AxisConstraints {
+  pub fn new() -> AxisConstraints {
+    AxisConstraints {
+      x: vec![],
+      y: vec![],
+      z: vec![]
+    }
+  }
+
+  /// Push a constraint onto the vector for its axis.
+  pub fn push<C: IntoAxisConstraints>(&mut self, c: C) {
+    c.push_into(self);
+  }
+}
+
+
+/// Constraints that know which axis they belong to.
+pub trait IntoAxisConstraints {
+  fn push_into(self, axes: &mut AxisConstraints);
+}
+
+
+impl IntoAxisConstraints for Constraint<VariableX> {
+  fn push_into(self, axes: &mut AxisConstraints) {
+    axes.x.push(self);
+  }
+}
+
+
+impl IntoAxisConstraints for Constraint<VariableY> {
+  fn push_into(self, axes: &mut AxisConstraints) {
+    axes.y.push(self);
+  }
+}
+
+
+impl IntoAxisConstraints for Constraint<VariableZ> {
+  fn push_into(self, axes: &mut AxisConstraints) {
+    axes.z.push(self);
+  }
+}
+
+
+/// Write constraints as equations, sorted into their axes at compile time:
+///
+///```ignore
+/// let cs:AxisConstraints =
+///   constraints!{
+///     label.width == button.width - 12;
+///     label.top >= button.top + PADDING_H;
+///     button.width == 100 @ weak;
+///     label.z_index == button.z_index + 2;
+///   };
+///```
+///
+/// `entity.attribute` is shorthand for `entity.attribute()`, the relations
+/// are `==`, `>=` and `<=`, and a strength of `weak`, `medium`, `strong` or
+/// `required` (the default) can follow an `@`. The left hand side must
+/// mention an attribute. An equation that mixes attributes from different
+/// axes is a type error.
+///
+/// Each token is a step of macro recursion, so very long blocks may need a
+/// higher `#![recursion_limit]`.
+#[macro_export]
+macro_rules! constraints {
+  (@lhs $acc:ident [$($l:tt)*] == $($rest:tt)*) => {
+    $crate::constraints!(@rhs $acc [$($l)*] is [] $($rest)*)
+  };
+  (@lhs $acc:ident [$($l:tt)*] >= $($rest:tt)*) => {
+    $crate::constraints!(@rhs $acc [$($l)*] is_ge [] $($rest)*)
+  };
+  (@lhs $acc:ident [$($l:tt)*] <= $($rest:tt)*) => {
+    $crate::constraints!(@rhs $acc [$($l)*] is_le [] $($rest)*)
+  };
+  (@lhs $acc:ident [$($l:tt)*] $e:ident . $a:ident $($rest:tt)*) => {
+    $crate::constraints!(@lhs $acc [$($l)* $crate::components::macro_support::$a(&$e)] $($rest)*)
+  };
+  (@lhs $acc:ident [] ) => {};
+  (@lhs $acc:ident [$($l:tt)*] ; $($rest:tt)*) => {
+    compile_error!(concat!("Constraint is missing a relation: ", stringify!($($l)*)))
+  };
+  (@lhs $acc:ident [$($l:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::constraints!(@lhs $acc [$($l)* $t] $($rest)*)
+  };
+
+  (@rhs $acc:ident [$($l:tt)*] $rel:ident [$($r:tt)*] @ $s:ident $(; $($rest:tt)*)?) => {
+    $acc.push(
+      $crate::components::macro_support::with_strength(
+        $crate::components::macro_support::$rel($($l)*, $($r)*),
+        $crate::constraints!(@strength $s)
+      )
+    );
+    $crate::constraints!(@lhs $acc [] $($($rest)*)?)
+  };
+  (@rhs $acc:ident [$($l:tt)*] $rel:ident [$($r:tt)*] ; $($rest:tt)*) => {
+    $acc.push($crate::components::macro_support::$rel($($l)*, $($r)*));
+    $crate::constraints!(@lhs $acc [] $($rest)*)
+  };
+  (@rhs $acc:ident [$($l:tt)*] $rel:ident [$($r:tt)*] ) => {
+    $acc.push($crate::components::macro_support::$rel($($l)*, $($r)*));
+  };
+  (@rhs $acc:ident [$($l:tt)*] $rel:ident [$($r:tt)*] $e:ident . $a:ident $($rest:tt)*) => {
+    $crate::constraints!(@rhs $acc [$($l)*] $rel [$($r)* $crate::components::macro_support::$a(&$e)] $($rest)*)
+  };
+  (@rhs $acc:ident [$($l:tt)*] $rel:ident [$($r:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::constraints!(@rhs $acc [$($l)*] $rel [$($r)* $t] $($rest)*)
+  };
+
+  (@strength weak) => { $crate::components::strength::WEAK };
+  (@strength medium) => { $crate::components::strength::MEDIUM };
+  (@strength strong) => { $crate::components::strength::STRONG };
+  (@strength required) => { $crate::components::strength::REQUIRED };
+  (@strength $s:ident) => {
+    compile_error!(concat!("Unknown constraint strength: ", stringify!($s)))
+  };
+
+  ($($body:tt)*) => {{
+    let mut acc =
+      $crate::components::AxisConstraints::new();
+    $crate::constraints!(@lhs acc [] $($body)*);
+    acc
+  }};
+}
+
+
+/// What the `constraints!` macro expands to, so that it works without
+/// bringing anything into the caller's scope.
+#[doc(hidden)]
+pub mod macro_support {
+  use super::*;
+
+  pub fn left<T: HasXConstraints>(t: &T) -> Expression<VariableX> { t.left() }
+  pub fn width<T: HasXConstraints>(t: &T) -> Expression<VariableX> { t.width() }
+  pub fn right<T: HasXConstraints>(t: &T) -> Expression<VariableX> { t.right() }
+  pub fn center_x<T: HasXConstraints>(t: &T) -> Expression<VariableX> { t.center_x() }
+  pub fn scroll_x<T: HasXConstraints>(t: &T) -> Expression<VariableX> { t.scroll_x() }
+  pub fn top<T: HasYConstraints>(t: &T) -> Expression<VariableY> { t.top() }
+  pub fn height<T: HasYConstraints>(t: &T) -> Expression<VariableY> { t.height() }
+  pub fn bottom<T: HasYConstraints>(t: &T) -> Expression<VariableY> { t.bottom() }
+  pub fn center_y<T: HasYConstraints>(t: &T) -> Expression<VariableY> { t.center_y() }
+  pub fn baseline<T: HasYConstraints>(t: &T) -> Expression<VariableY> { t.baseline() }
+  pub fn scroll_y<T: HasYConstraints>(t: &T) -> Expression<VariableY> { t.scroll_y() }
+  pub fn z_index<T: HasZConstraints>(t: &T) -> Expression<VariableZ> { t.z_index() }
+
+  pub fn is<T: Clone, E: Into<Expression<T>>>(lhs: Expression<T>, rhs: E) -> Constraint<T> {
+    lhs.is(rhs)
+  }
+
+  pub fn is_ge<T: Clone, E: Into<Expression<T>>>(lhs: Expression<T>, rhs: E) -> Constraint<T> {
+    lhs.is_ge(rhs)
+  }
+
+  pub fn is_le<T: Clone, E: Into<Expression<T>>>(lhs: Expression<T>, rhs: E) -> Constraint<T> {
+    lhs.is_le(rhs)
+  }
+
+  pub fn with_strength<T: Clone>(c: Constraint<T>, strength: f64) -> Constraint<T> {
+    c.with_strength(strength)
+  }
+}
+
+
 pub trait HasXConstraints {
   fn left(&self) -> Expression<VariableX>;
   fn width(&self) -> Expression<VariableX>;
   fn right(&self) -> Expression<VariableX> {
     self.left() + self.width()
   }
+  fn center_x(&self) -> Expression<VariableX> {
+    self.left() + self.width() * 0.5
+  }
+  /// A percentage of this thing's width, eg `ui.stage().percent_width(30.0)`.
+  fn percent_width(&self, percent: f64) -> Expression<VariableX> {
+    self.width() * (percent / 100.0)
+  }
+  /// How far this thing's content is scrolled to the right. Scroll views
+  /// keep this as an edit variable, everything else is never scrolled.
+  fn scroll_x(&self) -> Expression<VariableX> {
+    Expression::from_constant(0.0)
+  }
 }
 
 
@@ -102,6 +304,24 @@ pub trait HasYConstraints {
   fn bottom(&self) -> Expression<VariableY> {
     self.top() + self.height()
   }
+  fn center_y(&self) -> Expression<VariableY> {
+    self.top() + self.height() * 0.5
+  }
+  /// A percentage of this thing's height, eg `ui.stage().percent_height(50.0)`.
+  fn percent_height(&self, percent: f64) -> Expression<VariableY> {
+    self.height() * (percent / 100.0)
+  }
+  /// Where this thing's text sits. An entity with Text has its baseline kept
+  /// at its font's ascent below its top by the BaselineSystem, other
+  /// entities' baselines are free. The stage's baseline is its top.
+  fn baseline(&self) -> Expression<VariableY> {
+    self.top()
+  }
+  /// How far this thing's content is scrolled down. Scroll views keep this
+  /// as an edit variable, everything else is never scrolled.
+  fn scroll_y(&self) -> Expression<VariableY> {
+    Expression::from_constant(0.0)
+  }
 }
 
 
@@ -117,6 +337,9 @@ impl HasXConstraints for Entity {
   fn width(&self) -> Expression<VariableX> {
     VariableX::Width(Some(*self)).into()
   }
+  fn scroll_x(&self) -> Expression<VariableX> {
+    VariableX::Scroll(Some(*self)).into()
+  }
 }
 
 
@@ -127,6 +350,12 @@ impl HasYConstraints for Entity {
   fn height(&self) -> Expression<VariableY> {
     VariableY::Height(Some(*self)).into()
   }
+  fn baseline(&self) -> Expression<VariableY> {
+    VariableY::Baseline(Some(*self)).into()
+  }
+  fn scroll_y(&self) -> Expression<VariableY> {
+    VariableY::Scroll(Some(*self)).into()
+  }
 }
 
 
@@ -135,3 +364,43 @@ impl HasZConstraints for Entity {
     VariableZ(*self).into()
   }
 }
+
+
+#[cfg(test)]
+mod tests {
+  use specs::prelude::{Builder, World, WorldExt};
+
+  // Nothing from the components module is in scope here, and the macro is
+  // used under another name
+  use crate::constraints as sorted;
+
+  const PADDING: f64 = 4.0;
+
+  #[test]
+  fn constraints_are_sorted_by_axis() {
+    let mut world = World::new();
+    let label = world.create_entity().build();
+    let button = world.create_entity().build();
+    let cs =
+      sorted!{
+        label.width == button.width - 12;
+        label.top >= button.top + PADDING;
+        button.width == 100 @ weak;
+        label.z_index == button.z_index + 2;
+        label.baseline <= button.bottom @ strong
+      };
+    assert_eq!((cs.x.len(), cs.y.len(), cs.z.len()), (2, 2, 1));
+    assert_eq!(cs.x[1].strength(), cassowary::strength::WEAK);
+    assert_eq!(cs.y[0].op(), cassowary::RelationalOperator::GreaterOrEqual);
+    assert_eq!(cs.y[0].expr().constant, -PADDING);
+    assert_eq!(cs.y[1].op(), cassowary::RelationalOperator::LessOrEqual);
+    assert_eq!(cs.y[1].strength(), cassowary::strength::STRONG);
+    assert_eq!(cs.z[0].strength(), cassowary::strength::REQUIRED);
+  }
+
+  #[test]
+  fn constraints_can_be_empty() {
+    let cs = sorted!{};
+    assert!(cs.x.is_empty() && cs.y.is_empty() && cs.z.is_empty());
+  }
+}
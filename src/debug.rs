@@ -0,0 +1,115 @@
+use specs::prelude::*;
+
+use super::components::*;
+use super::introspect::{constraints_on, ConstraintStatus};
+use super::query::Query;
+use super::systems::event::Mouse;
+
+
+/// One outlined box in the debug overlay.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugBox {
+  pub element_box: ElementBox,
+  /// Name, solved position, size and z.
+  pub label: String
+}
+
+
+/// Everything the rasterizer needs to draw the layout debug overlay.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugOverlay {
+  pub boxes: Vec<DebugBox>,
+  /// The topmost element under the mouse.
+  pub hovered: Option<DebugBox>,
+  /// The constraints that affect the hovered element.
+  pub constraints: Vec<String>
+}
+
+
+fn debug_box(names: &ReadStorage<Name>, ent: Entity, el: ElementBox) -> DebugBox {
+  let name =
+    names
+    .get(ent)
+    .map(|Name(s)| s.clone())
+    .unwrap_or(format!("entity({:?})", ent.id()));
+  DebugBox {
+    label:
+      format!(
+        "{} x:{} y:{} w:{} h:{} z:{}",
+        name, el.x, el.y, el.width, el.height, el.z
+      ),
+    element_box: el
+  }
+}
+
+
+impl DebugOverlay {
+  /// Gather the overlay from the current state of the world.
+  pub fn from_world(world: &World) -> DebugOverlay {
+    let names =
+      world
+      .read_storage::<Name>();
+    let mouse =
+      world
+      .read_resource::<Mouse>()
+      .clone();
+    // Entities with no area are just holders of constraints
+    let has_area = |_: &World, _, el: &ElementBox| el.width > 0 && el.height > 0;
+
+    let boxes =
+      Query::new(world)
+      .without::<Invisible>()
+      .filter(has_area)
+      .iter()
+      .map(|(ent, el)| debug_box(&names, ent, el))
+      .collect();
+
+    let may_hovered =
+      Query::new(world)
+      .without::<Invisible>()
+      .filter(has_area)
+      .containing(mouse.x, mouse.y)
+      .iter()
+      .last();
+
+    let constraints =
+      may_hovered
+      .as_ref()
+      .map(|(ent, _)| {
+        let mut lines = vec![];
+        let mut describe = |owner: Entity, description: String, status: ConstraintStatus| {
+          let owner_name =
+            names
+            .get(owner)
+            .map(|Name(s)| s.clone())
+            .unwrap_or(format!("entity({:?})", owner.id()));
+          let status =
+            match status {
+              ConstraintStatus::Satisfied => "",
+              ConstraintStatus::Unsatisfied => "UNSATISFIED ",
+              ConstraintStatus::Rejected => "REJECTED "
+            };
+          lines.push(format!("{}{} (in {})", status, description, owner_name));
+        };
+        constraints_on(world, &(ent.left() + ent.width()))
+          .into_iter()
+          .for_each(|info| describe(info.owner, info.description, info.status));
+        constraints_on(world, &(ent.top() + ent.height()))
+          .into_iter()
+          .for_each(|info| describe(info.owner, info.description, info.status));
+        constraints_on(world, &ent.z_index())
+          .into_iter()
+          .for_each(|info| describe(info.owner, info.description, info.status));
+        lines
+      })
+      .unwrap_or(vec![]);
+
+    DebugOverlay {
+      boxes,
+      hovered:
+        may_hovered
+        .map(|(ent, el)| debug_box(&names, ent, el)),
+      constraints
+    }
+  }
+}
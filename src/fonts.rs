@@ -0,0 +1,355 @@
+//! Fonts by family name.
+//!
+//! A `Text`'s `font_path` may be a path to a font file or the name of a font
+//! family. Families are either registered with a path explicitly or found by
+//! looking through the registry's search directories for a font file named
+//! after the family, so "Komika" finds `komika.ttf` and "Noto Sans" finds
+//! `NotoSans-Regular.ttf`. Each family can have a chain of fallbacks that are
+//! used for characters it doesn't have.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+
+const FONT_EXTENSIONS: [&str; 3] = ["ttf", "otf", "ttc"];
+
+
+/// Lowercase with everything but letters and digits removed.
+fn normalize(name: &str) -> String {
+  name
+    .chars()
+    .filter(|c| c.is_alphanumeric())
+    .flat_map(|c| c.to_lowercase())
+    .collect()
+}
+
+
+fn is_font_file(path: &Path) -> bool {
+  path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .map(|ext| FONT_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    .unwrap_or(false)
+}
+
+
+/// Every font file in a directory and its subdirectories.
+fn font_files(dir: &Path, files: &mut Vec<PathBuf>) {
+  let entries =
+    match std::fs::read_dir(dir) {
+      Ok(entries) => entries,
+      Err(_) => { return; }
+    };
+  for entry in entries.filter_map(|entry| entry.ok()) {
+    let path =
+      entry.path();
+    if path.is_dir() {
+      font_files(&path, files);
+    } else if is_font_file(&path) {
+      files.push(path);
+    }
+  }
+}
+
+
+/// Where fonts usually live on this platform.
+pub fn system_font_dirs() -> Vec<PathBuf> {
+  let mut dirs:Vec<PathBuf> =
+    vec![];
+  if cfg!(target_os = "windows") {
+    let windir =
+      std::env::var("WINDIR")
+      .unwrap_or("C:\\Windows".to_string());
+    dirs.push(Path::new(&windir).join("Fonts"));
+  } else if cfg!(target_os = "macos") {
+    dirs.push(PathBuf::from("/System/Library/Fonts"));
+    dirs.push(PathBuf::from("/Library/Fonts"));
+    if let Ok(home) = std::env::var("HOME") {
+      dirs.push(Path::new(&home).join("Library/Fonts"));
+    }
+  } else {
+    dirs.push(PathBuf::from("/usr/share/fonts"));
+    dirs.push(PathBuf::from("/usr/local/share/fonts"));
+    if let Ok(home) = std::env::var("HOME") {
+      dirs.push(Path::new(&home).join(".fonts"));
+      dirs.push(Path::new(&home).join(".local/share/fonts"));
+    }
+  }
+  dirs
+}
+
+
+/// A registry shared between whatever measures text and whatever draws it,
+/// so that both find the same fonts.
+pub type SharedFontRegistry = Arc<Mutex<FontRegistry>>;
+
+
+/// Named font families, their fallbacks and where to look for them.
+#[derive(Clone)]
+pub struct FontRegistry {
+  families: HashMap<String, PathBuf>,
+  fallbacks: HashMap<String, Vec<String>>,
+  default_fallbacks: Vec<String>,
+  search_dirs: Vec<PathBuf>,
+  discovered: Option<Vec<PathBuf>>
+}
+
+
+impl FontRegistry {
+  /// A registry that looks for fonts in the working directory, `fonts` and
+  /// `assets/fonts`.
+  pub fn new() -> FontRegistry {
+    FontRegistry {
+      families: HashMap::new(),
+      fallbacks: HashMap::new(),
+      default_fallbacks: vec![],
+      search_dirs: vec![
+        PathBuf::from("."),
+        PathBuf::from("fonts"),
+        PathBuf::from("assets/fonts")
+      ],
+      discovered: None
+    }
+  }
+
+  pub fn shared(self) -> SharedFontRegistry {
+    Arc::new(Mutex::new(self))
+  }
+
+  /// Also look for fonts where the system keeps them.
+  pub fn with_system_fonts(self) -> Self {
+    let mut fr = self;
+    system_font_dirs()
+      .into_iter()
+      .for_each(|dir| fr.add_search_dir(dir));
+    fr
+  }
+
+  pub fn add_search_dir<P: AsRef<Path>>(&mut self, dir: P) {
+    self
+      .search_dirs
+      .push(dir.as_ref().to_path_buf());
+    self.discovered = None;
+  }
+
+  /// Register a family with the font file that provides it.
+  pub fn register<P: AsRef<Path>>(&mut self, family: &str, path: P) {
+    self
+      .families
+      .insert(normalize(family), path.as_ref().to_path_buf());
+  }
+
+  /// Set the families to try, in order, for characters the given family
+  /// doesn't have, eg `registry.set_fallbacks("Komika", &["Noto Sans", "Noto Sans CJK JP"])`.
+  pub fn set_fallbacks(&mut self, family: &str, fallbacks: &[&str]) {
+    self
+      .fallbacks
+      .insert(
+        normalize(family),
+        fallbacks.iter().map(|s| s.to_string()).collect()
+      );
+  }
+
+  /// Set the families to try for any family without fallbacks of its own.
+  pub fn set_default_fallbacks(&mut self, fallbacks: &[&str]) {
+    self.default_fallbacks =
+      fallbacks
+      .iter()
+      .map(|s| s.to_string())
+      .collect();
+  }
+
+  /// Find the font file in the search directories that best matches the
+  /// family name.
+  fn discover(&mut self, family: &str) -> Option<PathBuf> {
+    if self.discovered.is_none() {
+      let mut files = vec![];
+      self
+        .search_dirs
+        .iter()
+        .for_each(|dir| font_files(dir, &mut files));
+      self.discovered = Some(files);
+    }
+    let wanted =
+      normalize(family);
+    let stem_of = |path: &PathBuf| -> String {
+      path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(normalize)
+        .unwrap_or_default()
+    };
+    let files =
+      self
+      .discovered
+      .as_ref()
+      .expect("Impossible missing font files");
+    files
+      .iter()
+      .find(|path| stem_of(path) == wanted)
+      .or(
+        files
+          .iter()
+          .find(|path| stem_of(path) == format!("{}regular", wanted))
+      )
+      .or(
+        files
+          .iter()
+          .filter(|path| stem_of(path).starts_with(&wanted))
+          .min_by_key(|path| stem_of(path).len())
+      )
+      .cloned()
+  }
+
+  /// The font file for a path or a family name.
+  pub fn resolve(&mut self, path_or_family: &str) -> Option<PathBuf> {
+    let path =
+      Path::new(path_or_family);
+    if is_font_file(path) && path.is_file() {
+      return Some(path.to_path_buf());
+    }
+    let registered =
+      self
+      .families
+      .get(&normalize(path_or_family))
+      .cloned();
+    registered
+      .or_else(|| self.discover(path_or_family))
+  }
+
+  /// The font files to try for a path or family name, the font itself first
+  /// followed by its fallbacks. Fonts that can't be found are left out.
+  pub fn chain(&mut self, path_or_family: &str) -> Vec<PathBuf> {
+    let key =
+      normalize(path_or_family);
+    let fallbacks =
+      self
+      .fallbacks
+      .get(&key)
+      .cloned()
+      .unwrap_or(self.default_fallbacks.clone());
+    let mut chain:Vec<PathBuf> =
+      vec![];
+    std::iter::once(path_or_family.to_string())
+      .chain(fallbacks.into_iter())
+      .for_each(|name| {
+        if let Some(path) = self.resolve(&name) {
+          if !chain.contains(&path) {
+            chain.push(path);
+          }
+        }
+      });
+    chain
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A fresh directory of empty font files, which is all the registry looks
+  /// at.
+  fn font_dir(name: &str, files: &[&str]) -> PathBuf {
+    let dir =
+      std::env::temp_dir()
+      .join(format!("berry-fonts-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("nested"))
+      .expect("Could not create font dir");
+    for file in files {
+      std::fs::write(dir.join(file), b"")
+        .expect("Could not write font file");
+    }
+    dir
+  }
+
+  fn registry_in(dir: &Path) -> FontRegistry {
+    let mut registry =
+      FontRegistry::new();
+    registry.search_dirs = vec![];
+    registry.add_search_dir(dir);
+    registry
+  }
+
+  #[test]
+  fn names_are_normalized() {
+    assert_eq!(normalize("Noto Sans CJK-JP"), "notosanscjkjp");
+    assert!(is_font_file(Path::new("a/b.TTF")));
+    assert!(is_font_file(Path::new("b.otf")));
+    assert!(!is_font_file(Path::new("b.txt")));
+    assert!(!is_font_file(Path::new("ttf")));
+  }
+
+  #[test]
+  fn families_resolve_to_files() {
+    let dir =
+      font_dir(
+        "resolve",
+        &["Komika.ttf", "NotoSans-Regular.ttf", "NotoSans-Bold.ttf", "nested/Mono.otf", "readme.txt"]
+      );
+    let mut registry =
+      registry_in(&dir);
+
+    assert_eq!(registry.resolve("komika"), Some(dir.join("Komika.ttf")));
+    assert_eq!(registry.resolve("Noto Sans"), Some(dir.join("NotoSans-Regular.ttf")));
+    assert_eq!(registry.resolve("noto-sans bold"), Some(dir.join("NotoSans-Bold.ttf")));
+    assert_eq!(registry.resolve("Mono"), Some(dir.join("nested/Mono.otf")));
+    assert_eq!(registry.resolve("readme"), None);
+    assert_eq!(registry.resolve("Nothing"), None);
+
+    // Existing font files are used as they are
+    let path =
+      dir.join("Komika.ttf");
+    assert_eq!(registry.resolve(&path.to_string_lossy()), Some(path.clone()));
+
+    // Registered families win over discovered ones
+    registry.register("Noto Sans", &path);
+    assert_eq!(registry.resolve("NOTO SANS"), Some(path));
+  }
+
+  #[test]
+  fn chains_follow_fallbacks() {
+    let dir =
+      font_dir("chain", &["Komika.ttf", "NotoSans-Regular.ttf", "Emoji.ttf"]);
+    let mut registry =
+      registry_in(&dir);
+
+    assert_eq!(registry.chain("Komika"), vec![dir.join("Komika.ttf")]);
+    assert!(registry.chain("Nothing").is_empty());
+
+    registry.set_default_fallbacks(&["Emoji"]);
+    assert_eq!(
+      registry.chain("Komika"),
+      vec![dir.join("Komika.ttf"), dir.join("Emoji.ttf")]
+    );
+
+    // A family's own fallbacks replace the defaults, missing and repeated
+    // fonts are left out
+    registry.set_fallbacks("Komika", &["Nothing", "Noto Sans", "Komika", "Emoji"]);
+    assert_eq!(
+      registry.chain("komika"),
+      vec![dir.join("Komika.ttf"), dir.join("NotoSans-Regular.ttf"), dir.join("Emoji.ttf")]
+    );
+    assert_eq!(
+      registry.chain("Noto Sans"),
+      vec![dir.join("NotoSans-Regular.ttf"), dir.join("Emoji.ttf")]
+    );
+
+    // Fallbacks are still found when the font itself isn't
+    assert_eq!(registry.chain("Nothing"), vec![dir.join("Emoji.ttf")]);
+  }
+
+  #[test]
+  fn new_search_dirs_are_looked_through() {
+    let first =
+      font_dir("first", &["Komika.ttf"]);
+    let second =
+      font_dir("second", &["Emoji.ttf"]);
+    let mut registry =
+      registry_in(&first);
+    assert_eq!(registry.resolve("Emoji"), None);
+    registry.add_search_dir(&second);
+    assert_eq!(registry.resolve("Emoji"), Some(second.join("Emoji.ttf")));
+  }
+}
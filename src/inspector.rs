@@ -0,0 +1,753 @@
+//! An inspector panel for poking at a live UI. The panel is made of berry
+//! elements itself - it lists the entity tree, lets you select an element by
+//! clicking its row (or by picking it out of the scene) and edit its Text,
+//! its Picture colors and the constants of the constraints that affect it.
+use specs::prelude::*;
+
+use std::any::Any;
+
+use super::components::*;
+use super::picture::{Picture, PictureCmd};
+use super::stack::{Container, HStack, VStack};
+use super::UI;
+
+
+const FONT_SIZE: u16 = 12;
+const MAX_ROWS: usize = 32;
+const PANEL_Z: i32 = 1000;
+const COLOR_STEP: i16 = 16;
+
+
+#[derive(Clone, Debug, PartialEq)]
+enum Control {
+  Pick,
+  Select(Entity),
+  Color { cmd: usize, channel: usize, delta: i16 },
+  ConstantX(Entity, Constraint<VariableX>, f64),
+  ConstantY(Entity, Constraint<VariableY>, f64),
+  ConstantZ(Entity, Constraint<VariableZ>, f64)
+}
+
+
+fn entity_name(ui: &UI, ent: Entity) -> String {
+  ui.get::<Name>(ent)
+    .map(|Name(s)| s)
+    .unwrap_or(format!("entity({:?})", ent.id()))
+}
+
+
+fn label_text(s: &str, (r, g, b): (u8, u8, u8)) -> Text {
+  let mut text =
+    Text::new(s)
+    .color(r, g, b, 255);
+  text.font_size = FONT_SIZE;
+  text
+}
+
+
+/// A label or a control on the panel.
+#[derive(Clone, Debug, PartialEq)]
+struct Cell {
+  text: String,
+  control: Option<Control>
+}
+
+
+impl Cell {
+  fn label(s: &str) -> Cell {
+    Cell {
+      text: s.to_string(),
+      control: None
+    }
+  }
+
+  fn control(s: &str, control: Control) -> Cell {
+    Cell {
+      text: s.to_string(),
+      control: Some(control)
+    }
+  }
+
+  fn to_text(&self) -> Text {
+    let color =
+      if self.control.is_some() {
+        (255, 200, 0x33)
+      } else {
+        (220, 220, 220)
+      };
+    label_text(&self.text, color)
+  }
+}
+
+
+/// A row of the panel, its cells side by side.
+#[derive(Clone, Debug, PartialEq)]
+struct Row {
+  cells: Vec<Cell>,
+  spacing: f64
+}
+
+
+impl Row {
+  fn new(spacing: f64, cells: Vec<Cell>) -> Row {
+    Row {
+      cells,
+      spacing
+    }
+  }
+
+  fn one(cell: Cell) -> Row {
+    Row::new(0.0, vec![cell])
+  }
+
+  /// Whether this row can be shown by changing the text and controls of the
+  /// other row's cells.
+  fn fits(&self, other: &Row) -> bool {
+    self.spacing == other.spacing
+      && self.cells.len() == other.cells.len()
+      && self
+        .cells
+        .iter()
+        .zip(other.cells.iter())
+        .all(|(a, b)| a.control.is_some() == b.control.is_some())
+  }
+}
+
+
+/// Order entities depth first by their Parent, paired with their depth.
+fn tree_order(ui: &UI, entities: &[Entity]) -> Vec<(Entity, usize)> {
+  let parents =
+    ui.world
+    .read_storage::<Parent>();
+  let parent_of = |ent: Entity| {
+    parents
+      .get(ent)
+      .map(|Parent(parent)| *parent)
+      .filter(|parent| entities.contains(parent))
+  };
+  let mut order = vec![];
+  let mut stack:Vec<(Entity, usize)> =
+    entities
+    .iter()
+    .rev()
+    .filter(|ent| parent_of(**ent).is_none())
+    .map(|ent| (*ent, 0))
+    .collect();
+  while let Some((ent, depth)) = stack.pop() {
+    if order.iter().any(|(seen, _)| *seen == ent) {
+      continue;
+    }
+    order.push((ent, depth));
+    entities
+      .iter()
+      .rev()
+      .filter(|child| parent_of(**child) == Some(ent))
+      .for_each(|child| stack.push((*child, depth + 1)));
+  }
+  order
+}
+
+
+/// The panel root, everything parented under it and the constraint holders
+/// of any containers in there.
+fn collect_parts(ui: &UI, root: Entity) -> Vec<Entity> {
+  let entities =
+    ui.world
+    .entities();
+  let parents =
+    ui.world
+    .read_storage::<Parent>();
+  let containers =
+    ui.world
+    .read_storage::<Container>();
+  let is_under_root = |ent: Entity| {
+    let mut may_ent = Some(ent);
+    while let Some(ent) = may_ent {
+      if ent == root {
+        return true;
+      }
+      may_ent =
+        parents
+        .get(ent)
+        .map(|Parent(parent)| *parent);
+    }
+    false
+  };
+  let mut parts:Vec<Entity> =
+    entities
+    .join()
+    .filter(|ent| is_under_root(*ent))
+    .collect();
+  let container_constraints:Vec<Entity> =
+    parts
+    .iter()
+    .filter_map(|ent| containers.get(*ent).map(|c| c.constraints))
+    .collect();
+  parts.extend(container_constraints);
+  parts
+}
+
+
+/// Add delta to the constant of the given constraint, wherever it lives in
+/// its owner's Constraints.
+fn nudge_constant<T>(ui: &mut UI, owner: Entity, constraint: &Constraint<T>, delta: f64)
+where
+  T: Clone + Send + Sync + Any,
+  Constraint<T>: PartialEq
+{
+  if let Some(Constraints(mut cs)) = ui.get::<Constraints<T>>(owner) {
+    if let Some(c) = cs.iter_mut().find(|c| *c == constraint) {
+      let mut expr =
+        c.expr()
+        .clone();
+      expr.constant += delta;
+      *c = Constraint::new(expr, c.op(), c.strength());
+      ui.update(owner, Some(Constraints(cs)));
+    }
+  }
+}
+
+
+/// The inspector panel. Create one, then call `update` once a frame after
+/// `UI::maintain`:
+///
+///```ignore
+/// let mut inspector = Inspector::new();
+/// loop {
+///   ui.maintain(&mut rasterizer);
+///   inspector.update(&mut ui);
+/// }
+///```
+pub struct Inspector {
+  parts: Vec<Entity>,
+  controls: Vec<(Entity, Control)>,
+  /// The rows on the panel and the elements of their cells.
+  shown: Vec<(Row, Vec<Entity>)>,
+  inspected: Vec<Entity>,
+  selected: Option<Entity>,
+  picking: bool,
+  dirty: bool
+}
+
+
+impl Inspector {
+  pub fn new() -> Inspector {
+    Inspector {
+      parts: vec![],
+      controls: vec![],
+      shown: vec![],
+      inspected: vec![],
+      selected: None,
+      picking: false,
+      dirty: true
+    }
+  }
+
+  pub fn selected(&self) -> Option<Entity> {
+    self.selected
+  }
+
+  pub fn select(&mut self, may_ent: Option<Entity>) {
+    self.selected = may_ent;
+    self.dirty = true;
+  }
+
+  /// Whether or not the given entity is part of the inspector's own panel.
+  pub fn is_part(&self, ent: Entity) -> bool {
+    self
+      .parts
+      .contains(&ent)
+  }
+
+  /// Handle clicks on the panel and in the scene, then update the panel if
+  /// anything it shows has changed. Rows are changed in place where they
+  /// can be, the panel is only built again when its rows come and go.
+  pub fn update(&mut self, ui: &mut UI) {
+    let clicked:Vec<Control> =
+      self
+      .controls
+      .iter()
+      .filter(|(ent, _)| ui.has_event(*ent, Event::MouseUp))
+      .map(|(_, control)| control.clone())
+      .collect();
+    let clicked_panel =
+      self
+      .parts
+      .iter()
+      .any(|ent| ui.has_event(*ent, Event::MouseUp));
+
+    if self.picking && !clicked_panel {
+      let parts =
+        &self.parts;
+      let may_picked =
+        ui.query()
+        .filter(move |_, ent, el| {
+          el.width > 0 && el.height > 0 && !parts.contains(&ent)
+        })
+        .filter(|world, ent, _| {
+          world
+            .read_storage::<Events>()
+            .get(ent)
+            .map(|Events(evs)| evs.contains(&Event::MouseUp))
+            .unwrap_or(false)
+        })
+        .iter()
+        .last();
+      if let Some((ent, _)) = may_picked {
+        self.picking = false;
+        self.select(Some(ent));
+      }
+    }
+
+    clicked
+      .into_iter()
+      .for_each(|control| self.apply(ui, control));
+
+    // Rebuild when entities come and go
+    let inspected:Vec<Entity> = {
+      let entities =
+        ui.world
+        .entities();
+      let element_boxes =
+        ui.world
+        .read_storage::<ElementBox>();
+      (&entities, &element_boxes)
+        .join()
+        .map(|(ent, _)| ent)
+        .filter(|ent| !self.parts.contains(ent))
+        .collect()
+    };
+    if inspected != self.inspected {
+      self.dirty = true;
+    }
+    if self.dirty {
+      self.refresh(ui, inspected);
+    }
+  }
+
+  /// Append to the selected element's Text.
+  pub fn type_text(&mut self, ui: &mut UI, s: &str) {
+    self.edit_text(ui, |text| text.push_str(s));
+  }
+
+  /// Remove the last character of the selected element's Text.
+  pub fn backspace(&mut self, ui: &mut UI) {
+    self.edit_text(ui, |text| { text.pop(); });
+  }
+
+  /// Remove the panel from the UI.
+  pub fn close(&mut self, ui: &mut UI) {
+    let _ =
+      ui.world
+      .delete_entities(&self.parts);
+    self.parts = vec![];
+    self.controls = vec![];
+    self.shown = vec![];
+    self.dirty = true;
+  }
+
+  fn edit_text<F: FnOnce(&mut String)>(&mut self, ui: &mut UI, f: F) {
+    let may_text =
+      self
+      .selected
+      .and_then(|ent| ui.get::<Text>(ent).map(|text| (ent, text)));
+    if let Some((ent, mut text)) = may_text {
+      f(&mut text.text);
+      ui.update(ent, Some(text));
+      ui.update::<ContentSize>(ent, None);
+      self.dirty = true;
+    }
+  }
+
+  fn apply(&mut self, ui: &mut UI, control: Control) {
+    match control {
+      Control::Pick => {
+        self.picking = !self.picking;
+      }
+      Control::Select(ent) => {
+        self.selected = Some(ent);
+      }
+      Control::Color { cmd, channel, delta } => {
+        let may_picture =
+          self
+          .selected
+          .and_then(|ent| ui.get::<Picture>(ent).map(|pic| (ent, pic)));
+        if let Some((ent, mut pic)) = may_picture {
+          if let Some(PictureCmd::SetColor(r, g, b, a)) = pic.0.get_mut(cmd) {
+            let value =
+              match channel {
+                0 => r,
+                1 => g,
+                2 => b,
+                _ => a
+              };
+            *value = i16::max(0, i16::min(255, *value as i16 + delta)) as u8;
+          }
+          ui.update(ent, Some(pic));
+        }
+      }
+      Control::ConstantX(owner, constraint, delta) => {
+        nudge_constant(ui, owner, &constraint, delta);
+      }
+      Control::ConstantY(owner, constraint, delta) => {
+        nudge_constant(ui, owner, &constraint, delta);
+      }
+      Control::ConstantZ(owner, constraint, delta) => {
+        nudge_constant(ui, owner, &constraint, delta);
+      }
+    }
+    self.dirty = true;
+  }
+
+  /// Build the cells of a row, returning the row's element and each
+  /// cell's.
+  fn build_row(&mut self, ui: &mut UI, row: &Row) -> (Entity, Vec<Entity>) {
+    let mut cells = vec![];
+    for cell in row.cells.iter() {
+      let name =
+        if cell.control.is_some() { "Inspector control" } else { "Inspector label" };
+      let ent =
+        ElementBuilder::new()
+        .name(name)
+        .text(&cell.to_text())
+        .build(ui);
+      if let Some(control) = &cell.control {
+        self
+          .controls
+          .push((ent, control.clone()));
+      }
+      cells.push(ent);
+    }
+    let ent =
+      if cells.len() == 1 {
+        cells[0]
+      } else {
+        HStack::new(cells.clone())
+          .spacing(row.spacing)
+          .build(ui)
+      };
+    (ent, cells)
+  }
+
+  /// What the panel should show.
+  fn rows(&self, ui: &UI, inspected: &[Entity]) -> Vec<Row> {
+    let mut rows = vec![];
+    let pick_label =
+      if self.picking { "[click an element]" } else { "[pick]" };
+    rows.push(
+      Row::new(8.0, vec![Cell::label("Inspector"), Cell::control(pick_label, Control::Pick)])
+    );
+
+    let order =
+      tree_order(ui, inspected);
+    for (ent, depth) in order.iter().take(MAX_ROWS) {
+      let marker =
+        if self.selected == Some(*ent) { "> " } else { "" };
+      let s =
+        format!("{}{}{}", "  ".repeat(*depth), marker, entity_name(ui, *ent));
+      rows.push(Row::one(Cell::control(&s, Control::Select(*ent))));
+    }
+    if order.len() > MAX_ROWS {
+      let s =
+        format!("... {} more", order.len() - MAX_ROWS);
+      rows.push(Row::one(Cell::label(&s)));
+    }
+
+    if let Some(ent) = self.selected {
+      rows.extend(details(ui, ent));
+    }
+    rows
+  }
+
+  /// Show what has changed, in place if the panel's rows still fit and by
+  /// building the panel again if they don't.
+  fn refresh(&mut self, ui: &mut UI, inspected: Vec<Entity>) {
+    if self.selected.map(|ent| !inspected.contains(&ent)).unwrap_or(false) {
+      self.selected = None;
+    }
+    let rows =
+      self.rows(ui, &inspected);
+    let fits =
+      !self.parts.is_empty()
+      && rows.len() == self.shown.len()
+      && rows
+        .iter()
+        .zip(self.shown.iter())
+        .all(|(row, (shown, _))| row.fits(shown));
+    if fits {
+      self.update_rows(ui, rows);
+    } else {
+      self.rebuild(ui, rows);
+    }
+    self.inspected = inspected;
+    self.dirty = false;
+  }
+
+  /// Change the text and controls of the cells on the panel that differ
+  /// from the given rows.
+  fn update_rows(&mut self, ui: &mut UI, rows: Vec<Row>) {
+    for (row, (shown, cells)) in rows.into_iter().zip(self.shown.iter_mut()) {
+      let changed =
+        row
+        .cells
+        .iter()
+        .zip(shown.cells.iter())
+        .zip(cells.iter());
+      for ((cell, shown_cell), ent) in changed {
+        if cell.text != shown_cell.text {
+          ui.update(*ent, Some(cell.to_text()));
+          ui.update::<ContentSize>(*ent, None);
+        }
+        if cell.control != shown_cell.control {
+          self
+            .controls
+            .iter_mut()
+            .filter(|(control_ent, _)| control_ent == ent)
+            .for_each(|(_, control)| {
+              *control =
+                cell
+                .control
+                .clone()
+                .expect("Impossible missing control");
+            });
+        }
+      }
+      *shown = row;
+    }
+  }
+
+  fn rebuild(&mut self, ui: &mut UI, rows: Vec<Row>) {
+    self.close(ui);
+
+    let mut row_ents = vec![];
+    for row in rows.into_iter() {
+      let (ent, cells) =
+        self.build_row(ui, &row);
+      row_ents.push(ent);
+      self.shown.push((row, cells));
+    }
+
+    let panel =
+      VStack::new(row_ents)
+      .padding(4.0)
+      .spacing(2.0)
+      .build(ui);
+    let _background =
+      ElementBuilder::new()
+      .name("Inspector background")
+      .picture(
+        &Picture::new()
+          .set_color(0, 0, 0, 208)
+          .fill_rect(0, 0, 1, 1)
+      )
+      .parent(panel)
+      .left(panel.left())
+      .top(panel.top())
+      .width(panel.width())
+      .height(panel.height())
+      .z_index(panel.z_index())
+      .build(ui);
+    ElementBuilder::new()
+      .name("Inspector")
+      .right(ui.stage().right())
+      .top(0)
+      .z_index(PANEL_Z)
+      .update(ui, panel);
+
+    self.parts = collect_parts(ui, panel);
+  }
+}
+
+
+/// A row of [-] [+] controls for a constraint's constant.
+fn constant_row<F: Fn(f64) -> Control>(description: &str, mk: F) -> Row {
+  Row::new(
+    4.0,
+    vec![
+      Cell::control("[-]", mk(-1.0)),
+      Cell::control("[+]", mk(1.0)),
+      Cell::label(description)
+    ]
+  )
+}
+
+
+/// The rows describing the selected entity.
+fn details(ui: &UI, ent: Entity) -> Vec<Row> {
+  let mut rows = vec![];
+  let el =
+    ui.get::<ElementBox>(ent)
+    .unwrap_or(ElementBox::new());
+  let summary =
+    format!(
+      "{} x:{} y:{} w:{} h:{} z:{}",
+      entity_name(ui, ent), el.x, el.y, el.width, el.height, el.z
+    );
+  rows.push(Row::one(Cell::label(&summary)));
+
+  if let Some(text) = ui.get::<Text>(ent) {
+    let s =
+      format!("text: {:?} (type to edit)", text.text);
+    rows.push(Row::one(Cell::label(&s)));
+  }
+
+  if let Some(Picture(cmds)) = ui.get::<Picture>(ent) {
+    for (cmd, pc) in cmds.iter().enumerate() {
+      if let PictureCmd::SetColor(r, g, b, a) = pc {
+        let mut row =
+          vec![Cell::label(&format!("color {}", cmd))];
+        let channels =
+          [("r", r), ("g", g), ("b", b), ("a", a)];
+        for (channel, (name, value)) in channels.iter().enumerate() {
+          row.push(Cell::label(&format!("{}:{}", name, value)));
+          row.push(Cell::control("[-]", Control::Color { cmd, channel, delta: -COLOR_STEP }));
+          row.push(Cell::control("[+]", Control::Color { cmd, channel, delta: COLOR_STEP }));
+        }
+        rows.push(Row::new(4.0, row));
+      }
+    }
+  }
+
+  for info in ui.constraints_on(ent.left() + ent.width()) {
+    let (owner, constraint) = (info.owner, info.constraint);
+    rows.push(
+      constant_row(&info.description, |delta| {
+        Control::ConstantX(owner, constraint.clone(), delta)
+      })
+    );
+  }
+  for info in ui.constraints_on(ent.top() + ent.height()) {
+    let (owner, constraint) = (info.owner, info.constraint);
+    rows.push(
+      constant_row(&info.description, |delta| {
+        Control::ConstantY(owner, constraint.clone(), delta)
+      })
+    );
+  }
+  for info in ui.constraints_on(ent.z_index()) {
+    let (owner, constraint) = (info.owner, info.constraint);
+    rows.push(
+      constant_row(&info.description, |delta| {
+        Control::ConstantZ(owner, constraint.clone(), delta)
+      })
+    );
+  }
+
+  rows
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn text_of(ui: &UI, ent: Entity) -> String {
+    ui.get::<Text>(ent)
+      .map(|text| text.text)
+      .unwrap_or_default()
+  }
+
+  /// The part of the panel showing text that starts with the prefix.
+  fn part_showing(ui: &UI, inspector: &Inspector, prefix: &str) -> Option<Entity> {
+    inspector
+      .parts
+      .iter()
+      .cloned()
+      .find(|ent| text_of(ui, *ent).trim_start().starts_with(prefix))
+  }
+
+  #[test]
+  fn entities_are_listed_in_tree_order() {
+    let mut ui =
+      UI::new();
+    let parent =
+      ElementBuilder::new()
+      .name("parent")
+      .build(&mut ui);
+    let child =
+      ElementBuilder::new()
+      .name("child")
+      .parent(parent)
+      .build(&mut ui);
+    let other =
+      ElementBuilder::new()
+      .name("other")
+      .build(&mut ui);
+    assert_eq!(
+      tree_order(&ui, &[child, other, parent]),
+      vec![(other, 0), (parent, 0), (child, 1)]
+    );
+    // Parents that aren't listed don't count
+    assert_eq!(tree_order(&ui, &[child]), vec![(child, 0)]);
+  }
+
+  #[test]
+  fn rows_are_changed_in_place() {
+    let mut ui =
+      UI::new();
+    let thing =
+      ElementBuilder::new()
+      .name("thing")
+      .text(&Text::new("hi"))
+      .build(&mut ui);
+    let mut inspector =
+      Inspector::new();
+    inspector.update(&mut ui);
+    assert!(part_showing(&ui, &inspector, "thing").is_some());
+
+    inspector.select(Some(thing));
+    inspector.update(&mut ui);
+    assert!(part_showing(&ui, &inspector, "> thing").is_some());
+    let parts =
+      inspector.parts.clone();
+    let text_row =
+      part_showing(&ui, &inspector, "text:")
+      .expect("Could not find the text row");
+    assert_eq!(text_of(&ui, text_row), "text: \"hi\" (type to edit)");
+
+    inspector.type_text(&mut ui, "!");
+    inspector.update(&mut ui);
+    assert_eq!(ui.get::<Text>(thing).map(|text| text.text), Some("hi!".to_string()));
+    assert_eq!(inspector.parts, parts);
+    assert_eq!(text_of(&ui, text_row), "text: \"hi!\" (type to edit)");
+
+    inspector.backspace(&mut ui);
+    inspector.update(&mut ui);
+    assert_eq!(inspector.parts, parts);
+    assert_eq!(text_of(&ui, text_row), "text: \"hi\" (type to edit)");
+  }
+
+  #[test]
+  fn the_panel_is_built_again_when_rows_come_and_go() {
+    let mut ui =
+      UI::new();
+    let _thing =
+      ElementBuilder::new()
+      .name("thing")
+      .build(&mut ui);
+    let mut inspector =
+      Inspector::new();
+    inspector.update(&mut ui);
+    let parts =
+      inspector.parts.clone();
+    assert!(part_showing(&ui, &inspector, "other").is_none());
+
+    let other =
+      ElementBuilder::new()
+      .name("other")
+      .build(&mut ui);
+    inspector.update(&mut ui);
+    assert!(parts.iter().all(|ent| !inspector.is_part(*ent)));
+    assert!(part_showing(&ui, &inspector, "other").is_some());
+
+    inspector.select(Some(other));
+    inspector.update(&mut ui);
+    ui.world
+      .delete_entity(other)
+      .expect("Could not delete entity");
+    inspector.update(&mut ui);
+    assert_eq!(inspector.selected(), None);
+    assert!(part_showing(&ui, &inspector, "other").is_none());
+    assert!(part_showing(&ui, &inspector, "thing").is_some());
+  }
+}
@@ -0,0 +1,365 @@
+use specs::prelude::*;
+
+use std::any::Any;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use specs::storage::MaskedStorage;
+
+use super::WindowSize;
+use super::components::*;
+use super::systems::layout::LayoutStats;
+use super::systems::scroll::ScrollView;
+
+
+/// Solved values are rounded into ElementBoxes, so a constraint counts as
+/// satisfied when it is within this distance of holding exactly.
+const TOLERANCE: f64 = 1.0;
+
+
+/// Layout variables that can report their current solved value.
+pub trait Introspect: Any + Clone + Debug + Eq + Hash + Send + Sync {
+  fn current_value(&self, world: &World) -> f64;
+  fn describe(&self, names: &ReadStorage<Name>) -> String;
+}
+
+
+fn element_box(world: &World, ent: Entity) -> ElementBox {
+  world
+    .read_storage::<ElementBox>()
+    .get(ent)
+    .cloned()
+    .unwrap_or(ElementBox::new())
+}
+
+
+fn scroll_offset(world: &World, ent: Entity) -> (f64, f64) {
+  // Scroll views are only registered once something uses them
+  if world.try_fetch::<MaskedStorage<ScrollView>>().is_none() {
+    return (0.0, 0.0);
+  }
+  world
+    .read_storage::<ScrollView>()
+    .get(ent)
+    .map(|view| view.offset())
+    .unwrap_or((0.0, 0.0))
+}
+
+
+impl Introspect for VariableX {
+  fn current_value(&self, world: &World) -> f64 {
+    match self {
+      VariableX::Left(Some(ent)) => element_box(world, *ent).x as f64,
+      VariableX::Width(Some(ent)) => element_box(world, *ent).width as f64,
+      VariableX::Scroll(Some(ent)) => scroll_offset(world, *ent).0,
+      VariableX::Left(None) | VariableX::Scroll(None) => 0.0,
+      VariableX::Width(None) => world.read_resource::<WindowSize>().width as f64
+    }
+  }
+
+  fn describe(&self, names: &ReadStorage<Name>) -> String {
+    self.to_pathy_string(names)
+  }
+}
+
+
+impl Introspect for VariableY {
+  fn current_value(&self, world: &World) -> f64 {
+    match self {
+      VariableY::Top(Some(ent)) => element_box(world, *ent).y as f64,
+      VariableY::Height(Some(ent)) => element_box(world, *ent).height as f64,
+      VariableY::Baseline(Some(ent)) => {
+        let el =
+          element_box(world, *ent);
+        let ratio =
+          if world.try_fetch::<MaskedStorage<FontMetrics>>().is_none() {
+            0.0
+          } else {
+            world
+              .read_storage::<FontMetrics>()
+              .get(*ent)
+              .map(|metrics| metrics.baseline_ratio())
+              .unwrap_or(0.0)
+          };
+        el.y as f64 + el.height as f64 * ratio
+      }
+      VariableY::Scroll(Some(ent)) => scroll_offset(world, *ent).1,
+      VariableY::Top(None) | VariableY::Baseline(None) | VariableY::Scroll(None) => 0.0,
+      VariableY::Height(None) => world.read_resource::<WindowSize>().height as f64
+    }
+  }
+
+  fn describe(&self, names: &ReadStorage<Name>) -> String {
+    self.to_pathy_string(names)
+  }
+}
+
+
+impl Introspect for VariableZ {
+  fn current_value(&self, world: &World) -> f64 {
+    element_box(world, self.0).z as f64
+  }
+
+  fn describe(&self, names: &ReadStorage<Name>) -> String {
+    self.to_pathy_string(names)
+  }
+}
+
+
+/// How a constraint is doing in the current layout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConstraintStatus {
+  /// The constraint holds.
+  Satisfied,
+  /// The solver holds the constraint but it doesn't hold, usually because
+  /// a stronger constraint wins out over it.
+  Unsatisfied,
+  /// The solver refused the constraint, so it has no effect on the layout.
+  Rejected
+}
+
+
+/// A constraint, where it lives and how it is doing.
+#[derive(Clone, Debug)]
+pub struct ConstraintInfo<T> {
+  /// The entity whose Constraints component holds the constraint.
+  pub owner: Entity,
+  pub constraint: Constraint<T>,
+  pub strength: f64,
+  pub status: ConstraintStatus,
+  /// The constraint written out with entity names, eg
+  /// `label.left - pic.left - pic.width == 0`.
+  pub description: String
+}
+
+
+pub fn strength_name(s: f64) -> String {
+  if s >= strength::REQUIRED {
+    "required".to_string()
+  } else if s == strength::STRONG {
+    "strong".to_string()
+  } else if s == strength::MEDIUM {
+    "medium".to_string()
+  } else if s == strength::WEAK {
+    "weak".to_string()
+  } else {
+    format!("{}", s)
+  }
+}
+
+
+/// Whether or not the constraint holds given the current layout.
+pub fn is_satisfied<T: Introspect>(constraint: &Constraint<T>, world: &World) -> bool {
+  let expr =
+    constraint.expr();
+  let value =
+    expr
+    .terms
+    .iter()
+    .fold(expr.constant, |acc, term| {
+      acc + term.coefficient * term.variable.current_value(world)
+    });
+  match constraint.op() {
+    RelationalOperator::Equal => value.abs() <= TOLERANCE,
+    RelationalOperator::GreaterOrEqual => value >= -TOLERANCE,
+    RelationalOperator::LessOrEqual => value <= TOLERANCE
+  }
+}
+
+
+/// Write out a constraint using entity names.
+pub fn describe<T: Introspect>(constraint: &Constraint<T>, names: &ReadStorage<Name>) -> String {
+  let expr =
+    constraint.expr();
+  let mut s =
+    expr
+    .terms
+    .iter()
+    .enumerate()
+    .map(|(i, term)| {
+      let path =
+        term.variable.describe(names);
+      let (sign, magnitude) =
+        if term.coefficient < 0.0 {
+          ("-", -term.coefficient)
+        } else {
+          ("+", term.coefficient)
+        };
+      let scaled =
+        if magnitude == 1.0 {
+          path
+        } else {
+          format!("{} * {}", magnitude, path)
+        };
+      match (i, sign) {
+        (0, "+") => scaled,
+        (0, _) => format!("-{}", scaled),
+        (_, sign) => format!(" {} {}", sign, scaled)
+      }
+    })
+    .collect::<String>();
+  if expr.constant != 0.0 {
+    let sign =
+      if expr.constant < 0.0 { "-" } else { "+" };
+    s = format!("{} {} {}", s, sign, expr.constant.abs());
+  }
+  let op =
+    match constraint.op() {
+      RelationalOperator::Equal => "==",
+      RelationalOperator::GreaterOrEqual => ">=",
+      RelationalOperator::LessOrEqual => "<="
+    };
+  format!("{} {} 0 @ {}", s, op, strength_name(constraint.strength()))
+}
+
+
+/// Find every constraint that mentions any of the variables in `expr`.
+pub fn constraints_on<T: Introspect>(world: &World, expr: &Expression<T>) -> Vec<ConstraintInfo<T>> {
+  let entities =
+    world
+    .entities();
+  let constraints =
+    world
+    .read_storage::<Constraints<T>>();
+  let names =
+    world
+    .read_storage::<Name>();
+  let stats =
+    world
+    .try_fetch::<LayoutStats<T>>();
+  let variables:Vec<&T> =
+    expr
+    .terms
+    .iter()
+    .map(|term| &term.variable)
+    .collect();
+
+  (&entities, &constraints)
+    .join()
+    .flat_map(|(owner, Constraints(cs))| {
+      cs
+        .iter()
+        .filter(|c| {
+          c.expr()
+            .terms
+            .iter()
+            .any(|term| variables.contains(&&term.variable))
+        })
+        .map(|c| {
+          let is_rejected =
+            stats
+            .as_ref()
+            .map(|stats| stats.is_rejected(owner, c))
+            .unwrap_or(false);
+          let status =
+            if is_rejected {
+              ConstraintStatus::Rejected
+            } else if is_satisfied(c, world) {
+              ConstraintStatus::Satisfied
+            } else {
+              ConstraintStatus::Unsatisfied
+            };
+          ConstraintInfo {
+            owner,
+            constraint: c.clone(),
+            strength: c.strength(),
+            status,
+            description: describe(c, &names)
+          }
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn world() -> World {
+    let mut world =
+      World::new();
+    world.register::<Name>();
+    world.register::<ElementBox>();
+    world.register::<Constraints<VariableX>>();
+    world.insert(WindowSize{ width: 100, height: 100 });
+    world
+  }
+
+  fn element(world: &mut World, name: &str, x: i32, width: u32) -> Entity {
+    world
+      .create_entity()
+      .with(Name(name.to_string()))
+      .with(ElementBox{ x, y: 0, z: 0, width, height: 10 })
+      .build()
+  }
+
+  #[test]
+  fn constraints_are_described_with_names() {
+    let mut world =
+      world();
+    let label =
+      element(&mut world, "label", 0, 10);
+    let pic =
+      element(&mut world, "pic", 0, 10);
+    let names =
+      world.read_storage::<Name>();
+    assert_eq!(
+      describe(&label.left().is(pic.right() + 4.0).with_strength(strength::WEAK), &names),
+      "label.left - pic.left - pic.width - 4 == 0 @ weak"
+    );
+    assert_eq!(strength_name(strength::REQUIRED), "required");
+  }
+
+  #[test]
+  fn constraints_on_report_their_status() {
+    let mut world =
+      world();
+    let pic =
+      element(&mut world, "pic", 10, 20);
+    let label =
+      element(&mut world, "label", 30, 20);
+    let next_to =
+      label.left().is(pic.right());
+    let narrow =
+      label.width().is(5).with_strength(strength::WEAK);
+    let scrolled =
+      label.left().is(label.scroll_x() + 30);
+    let impossible =
+      label.width().is(pic.width() * 2.0);
+    world
+      .write_storage::<Constraints<VariableX>>()
+      .insert(
+        label,
+        Constraints(vec![
+          next_to.clone(),
+          narrow.clone(),
+          scrolled.clone(),
+          impossible.clone()
+        ])
+      )
+      .expect("Could not insert constraints");
+    let mut stats =
+      LayoutStats::<VariableX>::default();
+    stats
+      .rejected
+      .insert(label.id(), vec![impossible.clone()]);
+    world.insert(stats);
+
+    let infos =
+      constraints_on(&world, &label.width());
+    assert_eq!(infos.len(), 2);
+    assert!(infos.iter().all(|info| info.owner == label));
+    assert_eq!(infos[0].constraint, narrow);
+    assert_eq!(infos[0].status, ConstraintStatus::Unsatisfied);
+    assert_eq!(infos[0].strength, strength::WEAK);
+    assert_eq!(infos[1].status, ConstraintStatus::Rejected);
+
+    // Scroll views aren't registered, so nothing is scrolled
+    let infos =
+      constraints_on(&world, &label.left());
+    assert_eq!(infos.len(), 2);
+    assert!(infos.iter().all(|info| info.status == ConstraintStatus::Satisfied));
+  }
+}
@@ -0,0 +1,455 @@
+//! A virtualized list for large data sets. Only the rows that are scrolled
+//! into view have entities, and rows that scroll out of view are reused for
+//! the rows that scroll in.
+use specs::prelude::*;
+
+use std::collections::HashMap;
+
+use super::components::*;
+use super::systems::scroll::{ScrollView, ScrollViewBuilder};
+use super::UI;
+
+
+/// Rows built beyond each edge of the viewport, so a little scrolling doesn't
+/// need new rows straight away.
+const OVERSCAN: usize = 2;
+
+
+/// The indices of the rows that should have entities when the list is
+/// scrolled `offset` down and is `height` tall.
+fn row_range(offset: f64, height: u32, row_height: u32, row_count: usize) -> std::ops::Range<usize> {
+  let row_height =
+    u32::max(1, row_height) as f64;
+  let offset =
+    f64::max(0.0, offset);
+  let first =
+    (offset / row_height).floor() as usize;
+  let last =
+    ((offset + height as f64) / row_height).ceil() as usize;
+  let start =
+    usize::min(row_count, first.saturating_sub(OVERSCAN));
+  let end =
+    usize::min(row_count, last + OVERSCAN);
+  start .. usize::max(start, end)
+}
+
+
+/// How far down the list's content the row at `index` starts. This is
+/// worked out in floating point, so lists with more rows than a u32 can
+/// count don't overflow.
+fn row_top(index: usize, row_height: u32) -> f64 {
+  index as f64 * row_height as f64
+}
+
+
+/// Where a VirtualList gets its rows from.
+pub trait ListDataSource {
+  /// The number of rows in the list.
+  fn row_count(&self) -> usize;
+
+  /// Build the entity for a row. This is only called when there isn't an
+  /// unused row to recycle, and the row is filled in with `update_row`
+  /// before it is shown.
+  fn build_row(&mut self, ui: &mut UI) -> Entity;
+
+  /// Fill in a row (new or recycled) with the data at `index`.
+  fn update_row(&mut self, ui: &mut UI, row: Entity, index: usize);
+}
+
+
+struct Row {
+  ent: Entity,
+  /// Holds the constraints that place the row in the list.
+  placement: Entity
+}
+
+
+pub struct VirtualListBuilder<D: ListDataSource> {
+  source: D,
+  row_height: u32,
+  kinetic: bool
+}
+
+
+impl<D: ListDataSource> VirtualListBuilder<D> {
+  /// A list of rows that are all `row_height` tall.
+  pub fn new(source: D, row_height: u32) -> VirtualListBuilder<D> {
+    VirtualListBuilder {
+      source,
+      row_height,
+      kinetic: false
+    }
+  }
+
+  /// Use kinetic wheel scrolling, see `ScrollViewBuilder::kinetic`.
+  pub fn kinetic(self, kinetic: bool) -> Self {
+    let mut lb = self;
+    lb.kinetic = kinetic;
+    lb
+  }
+
+  pub fn build(self, ui: &mut UI) -> VirtualList<D> {
+    let content =
+      ElementBuilder::new()
+      .name("VirtualList content")
+      .build(ui);
+    let view =
+      ScrollViewBuilder::new(content)
+      .horizontal(false)
+      .kinetic(self.kinetic)
+      .build(ui);
+    ElementBuilder::new()
+      .name("VirtualList")
+      .update(ui, view);
+    let mut list =
+      VirtualList {
+        source: self.source,
+        row_height: self.row_height,
+        view,
+        content,
+        row_count: None,
+        rows: HashMap::new(),
+        unused: vec![],
+        dirty: false
+      };
+    list.update(ui);
+    list
+  }
+}
+
+
+/// A list that only has entities for the rows in view. Position and size it
+/// by its `view` entity and call `update` once a frame, after
+/// `UI::maintain`.
+pub struct VirtualList<D: ListDataSource> {
+  source: D,
+  row_height: u32,
+  view: Entity,
+  content: Entity,
+  row_count: Option<usize>,
+  /// The rows in use, by index.
+  rows: HashMap<usize, Row>,
+  /// Rows waiting to be recycled, parked out of sight and hidden.
+  unused: Vec<Row>,
+  dirty: bool
+}
+
+
+impl<D: ListDataSource> VirtualList<D> {
+  /// The list's scroll view entity.
+  pub fn view(&self) -> Entity {
+    self.view
+  }
+
+  pub fn source(&self) -> &D {
+    &self.source
+  }
+
+  /// The data source. Call `refresh` after changing the data behind rows
+  /// that are already showing.
+  pub fn source_mut(&mut self) -> &mut D {
+    &mut self.source
+  }
+
+  /// Fill in every row in view again during the next update.
+  pub fn refresh(&mut self) {
+    self.dirty = true;
+  }
+
+  /// The row entity showing the given index, if it is in view.
+  pub fn row(&self, index: usize) -> Option<Entity> {
+    self
+      .rows
+      .get(&index)
+      .map(|row| row.ent)
+  }
+
+  /// The index of the row that the given entity (or one of its parts)
+  /// belongs to, if it is in view.
+  pub fn index_of(&self, ui: &UI, ent: Entity) -> Option<usize> {
+    let mut may_ent = Some(ent);
+    while let Some(ent) = may_ent {
+      let may_index =
+        self
+        .rows
+        .iter()
+        .find(|(_, row)| row.ent == ent)
+        .map(|(index, _)| *index);
+      if may_index.is_some() {
+        return may_index;
+      }
+      may_ent =
+        ui.get::<Parent>(ent)
+        .map(|Parent(parent)| parent)
+        .filter(|parent| *parent != self.content);
+    }
+    None
+  }
+
+  /// The indices of the rows that should have entities.
+  fn wanted_rows(&self, ui: &UI, row_count: usize) -> std::ops::Range<usize> {
+    let (_, offset) =
+      ui.get::<ScrollView>(self.view)
+      .map(|view| view.offset())
+      .unwrap_or((0.0, 0.0));
+    let (_, height) =
+      ui.get_size(self.view)
+      .unwrap_or((0, 0));
+    row_range(offset, height, self.row_height, row_count)
+  }
+
+  fn place(&self, ui: &mut UI, row: &Row, index: usize) {
+    let content =
+      self.content;
+    ui.update::<Invisible>(row.ent, None);
+    ElementBuilder::new()
+      .x_constraints(vec![
+        row.ent.left().is(content.left()),
+        row.ent.width().is(content.width())
+      ])
+      .y_constraints(vec![
+        row.ent.top().is(content.top() + row_top(index, self.row_height)),
+        row.ent.height().is(self.row_height)
+      ])
+      .z_constraints(vec![
+        row.ent.z_index().is(content.z_index() + 1)
+      ])
+      .update(ui, row.placement);
+  }
+
+  /// Keep rows that are out of the list above its content and hide them,
+  /// so they are neither drawn nor get mouse events along the edge of the
+  /// view. The rest of the row's placement is dropped, so a parked row
+  /// doesn't hold on to its old place in the list.
+  fn park(&self, ui: &mut UI, row: &Row) {
+    ui.update(row.ent, Some(Invisible));
+    ui.update::<Constraints<VariableX>>(row.placement, None);
+    ui.update::<Constraints<VariableZ>>(row.placement, None);
+    ElementBuilder::new()
+      .y_constraints(vec![
+        row.ent.bottom().is(self.content.top()),
+        row.ent.height().is(self.row_height)
+      ])
+      .update(ui, row.placement);
+  }
+
+  /// Build, recycle and fill in rows for whatever is scrolled into view.
+  pub fn update(&mut self, ui: &mut UI) {
+    let row_count =
+      self.source.row_count();
+    if self.row_count != Some(row_count) {
+      ElementBuilder::new()
+        .width(self.view.width())
+        .height(row_top(row_count, self.row_height))
+        .update(ui, self.content);
+      self.row_count = Some(row_count);
+    }
+
+    let wanted =
+      self.wanted_rows(ui, row_count);
+
+    // Free the rows that have gone out of view
+    let gone:Vec<usize> =
+      self
+      .rows
+      .keys()
+      .filter(|index| !wanted.contains(index))
+      .cloned()
+      .collect();
+    for index in gone {
+      let row =
+        self
+        .rows
+        .remove(&index)
+        .expect("Could not find a row that is in view");
+      self.park(ui, &row);
+      self.unused.push(row);
+    }
+
+    // Fill in the rows that have come into view
+    let dirty =
+      self.dirty;
+    self.dirty = false;
+    for index in wanted {
+      if self.rows.contains_key(&index) {
+        if dirty {
+          let ent = self.rows[&index].ent;
+          self.source.update_row(ui, ent, index);
+        }
+        continue;
+      }
+      let row =
+        match self.unused.pop() {
+          Some(row) => row,
+          None => {
+            let ent =
+              self.source.build_row(ui);
+            ElementBuilder::new()
+              .parent(self.content)
+              .update(ui, ent);
+            let placement =
+              ElementBuilder::new()
+              .name("VirtualList row placement")
+              .build(ui);
+            Row { ent, placement }
+          }
+        };
+      self.source.update_row(ui, row.ent, index);
+      self.place(ui, &row, index);
+      self.rows.insert(index, row);
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rows_in_view_are_wanted_with_overscan() {
+    // Rows 0 to 4 are in view, with two more below
+    assert_eq!(row_range(0.0, 100, 20, 1000), 0 .. 7);
+    // Rows 2 (partly) to 7 (partly) are in view
+    assert_eq!(row_range(50.0, 100, 20, 1000), 0 .. 10);
+    assert_eq!(row_range(500.0, 100, 20, 1000), 23 .. 32);
+  }
+
+  #[test]
+  fn wanted_rows_stay_within_the_list() {
+    assert_eq!(row_range(0.0, 100, 20, 3), 0 .. 3);
+    assert_eq!(row_range(0.0, 100, 20, 0), 0 .. 0);
+    // Scrolled past the end, eg while the list shrinks
+    assert_eq!(row_range(5000.0, 100, 20, 10), 10 .. 10);
+    // Overscrolled past the start
+    assert_eq!(row_range(-40.0, 100, 20, 1000), 0 .. 7);
+    // A list with no height still gets its overscan
+    assert_eq!(row_range(0.0, 0, 20, 1000), 0 .. 2);
+    // Rows with no height are treated as a pixel tall
+    assert_eq!(row_range(0.0, 10, 0, 1000), 0 .. 12);
+  }
+
+  #[test]
+  fn row_tops_do_not_overflow() {
+    assert_eq!(row_top(3, 20), 60.0);
+    assert_eq!(row_top(usize::MAX, u32::MAX), usize::MAX as f64 * u32::MAX as f64);
+  }
+
+  /// Numbered rows, counting how many were built.
+  struct Numbers {
+    count: usize,
+    built: usize,
+    shown: HashMap<Entity, usize>
+  }
+
+  impl ListDataSource for Numbers {
+    fn row_count(&self) -> usize {
+      self.count
+    }
+
+    fn build_row(&mut self, ui: &mut UI) -> Entity {
+      self.built += 1;
+      ElementBuilder::new()
+        .name("row")
+        .build(ui)
+    }
+
+    fn update_row(&mut self, _ui: &mut UI, row: Entity, index: usize) {
+      self.shown.insert(row, index);
+    }
+  }
+
+  /// A list of 20 pixel rows in a view that was laid out 100 pixels tall.
+  fn list_in_view(ui: &mut UI, count: usize) -> VirtualList<Numbers> {
+    let source =
+      Numbers {
+        count,
+        built: 0,
+        shown: HashMap::new()
+      };
+    let mut list =
+      VirtualListBuilder::new(source, 20)
+      .build(ui);
+    ui.update(list.view(), Some(ElementBox { x: 0, y: 0, z: 0, width: 100, height: 100 }));
+    list.update(ui);
+    list
+  }
+
+  fn scroll_to(ui: &mut UI, list: &VirtualList<Numbers>, y: f64) {
+    ui.world
+      .write_storage::<ScrollView>()
+      .get_mut(list.view())
+      .expect("Could not find the list's scroll view")
+      .scroll_to(0.0, y);
+  }
+
+  fn assert_rows_show(ui: &UI, list: &VirtualList<Numbers>, range: std::ops::Range<usize>) {
+    let mut indices:Vec<usize> =
+      list.rows.keys().cloned().collect();
+    indices.sort();
+    assert_eq!(indices, range.collect::<Vec<_>>());
+    for (index, row) in list.rows.iter() {
+      assert_eq!(list.source().shown.get(&row.ent), Some(index));
+      assert_eq!(list.index_of(ui, row.ent), Some(*index));
+      assert!(ui.get::<Invisible>(row.ent).is_none());
+      let ys =
+        ui.get::<Constraints<VariableY>>(row.placement)
+        .expect("Row has no placement");
+      assert!(ys.0.contains(&row.ent.top().is(list.content.top() + row_top(*index, 20))));
+    }
+  }
+
+  #[test]
+  fn rows_are_built_for_the_view() {
+    let mut ui = UI::new();
+    let list = list_in_view(&mut ui, 1000);
+    assert_rows_show(&ui, &list, 0 .. 7);
+    assert_eq!(list.source().built, 7);
+    assert!(list.unused.is_empty());
+  }
+
+  #[test]
+  fn rows_are_recycled_as_the_list_scrolls() {
+    let mut ui = UI::new();
+    let mut list = list_in_view(&mut ui, 1000);
+
+    scroll_to(&mut ui, &list, 500.0);
+    list.update(&mut ui);
+    // All seven rows were reused, and two more were needed
+    assert_rows_show(&ui, &list, 23 .. 32);
+    assert_eq!(list.source().built, 9);
+    assert!(list.unused.is_empty());
+
+    scroll_to(&mut ui, &list, 0.0);
+    list.update(&mut ui);
+    assert_rows_show(&ui, &list, 0 .. 7);
+    assert_eq!(list.source().built, 9);
+    assert_eq!(list.unused.len(), 2);
+    for row in list.unused.iter() {
+      assert!(ui.get::<Invisible>(row.ent).is_some());
+      assert!(list.index_of(&ui, row.ent).is_none());
+      assert!(ui.get::<Constraints<VariableX>>(row.placement).is_none());
+      assert!(ui.get::<Constraints<VariableZ>>(row.placement).is_none());
+      let ys =
+        ui.get::<Constraints<VariableY>>(row.placement)
+        .expect("Parked row has no placement");
+      assert!(ys.0.contains(&row.ent.bottom().is(list.content.top())));
+    }
+  }
+
+  #[test]
+  fn shrinking_lists_park_their_rows() {
+    let mut ui = UI::new();
+    let mut list = list_in_view(&mut ui, 1000);
+    list.source_mut().count = 3;
+    list.update(&mut ui);
+    assert_rows_show(&ui, &list, 0 .. 3);
+    assert_eq!(list.unused.len(), 4);
+
+    // Refreshing fills in the rows in view again
+    list.source_mut().shown.clear();
+    list.refresh();
+    list.update(&mut ui);
+    assert_rows_show(&ui, &list, 0 .. 3);
+  }
+}
@@ -1,3 +1,5 @@
+#[macro_use]
+extern crate log;
 extern crate sdl2;
 extern crate specs;
 #[macro_use]
@@ -12,19 +14,29 @@ use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
 use sdl2::render::{BlendMode, TextureCreator, WindowCanvas};
 
-use cassowary::*;
-
 pub mod components;
+pub mod debug;
+pub mod fonts;
+pub mod inspector;
+pub mod introspect;
+pub mod list;
+pub mod measure;
 pub mod systems;
 pub mod picture;
+pub mod query;
 pub mod rasterizer;
+pub mod screen;
+pub mod selector;
+pub mod stack;
+pub mod stats;
 pub mod ui;
 
 use components::*;
+use inspector::Inspector;
 use rasterizer::*;
 use ui::*;
 use picture::Picture;
-use systems::event::Mouse;
+use systems::event::{Key, Mouse};
 use systems::button::ButtonBuilder;
 
 
@@ -32,6 +44,12 @@ use systems::button::ButtonBuilder;
 #[derive(Debug, Clone, PartialEq)]
 pub enum Update {
   Mouse(Mouse),
+  Wheel(i32, i32),
+  Key(Key),
+  ToggleDebug,
+  ToggleInspector,
+  TextInput(String),
+  Backspace,
   Quit
 }
 
@@ -172,6 +190,9 @@ pub fn mk_update(event: &event::Event) -> Option<Update> {
         )
       )
     }
+    event::Event::MouseWheel { x, y, ..} => {
+      Some(Update::Wheel(*x, *y))
+    }
     event::Event::Quit {..} => {
       Some(Update::Quit)
     }
@@ -185,6 +206,39 @@ pub fn mk_update(event: &event::Event) -> Option<Update> {
         None
       }
     }
+    event::Event::KeyDown { keycode: Some(Keycode::F12), ..} => {
+      Some(Update::ToggleDebug)
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::F11), ..} => {
+      Some(Update::ToggleInspector)
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::Tab), ..} => {
+      Some(Update::Key(Key::Tab))
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::Space), ..} => {
+      Some(Update::Key(Key::Space))
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::Return), ..} => {
+      Some(Update::Key(Key::Enter))
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::Left), ..} => {
+      Some(Update::Key(Key::Left))
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::Right), ..} => {
+      Some(Update::Key(Key::Right))
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::Up), ..} => {
+      Some(Update::Key(Key::Up))
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::Down), ..} => {
+      Some(Update::Key(Key::Down))
+    }
+    event::Event::KeyDown { keycode: Some(Keycode::Backspace), ..} => {
+      Some(Update::Backspace)
+    }
+    event::Event::TextInput { text, ..} => {
+      Some(Update::TextInput(text.clone()))
+    }
     _ => {
       None
     }
@@ -230,9 +284,9 @@ fn banner_and_pics(ui: &mut UI, rasterizer: &mut Rasterizer) {
     Text::new("<- Look at this thing to the left!")
     .color(0, 0, 0, 255);
 
-  let (_, lw, lh) =
-    rasterizer
-    .get_text(&text_def);
+  let (lw, lh) =
+    ui.measure_text(&text_def)
+    .expect("Could not measure label");
 
   let label =
     ElementBuilder::new()
@@ -308,26 +362,19 @@ fn banner_and_pics(ui: &mut UI, rasterizer: &mut Rasterizer) {
     )
     .build(ui);
 
+  let stage =
+    ui.stage();
   let _box_relation =
     ElementBuilder::new()
-    .x_constraints(
-      vec![
-        box1.left().is(0),
-        box2.right().is(
-          ui.stage().right() - 10.0
-        ),
-        box2.left().is_ge(box1.right() + 10.0),
-
-        box1.width().is(50.0).with_strength(strength::WEAK),
-        box2.width().is(100.0).with_strength(strength::WEAK)
-      ]
-    )
-    .y_constraints(
-      vec![
-        box1.height().is(100),
-        box2.height().is(100)
-      ]
-    )
+    .constraints(constraints!{
+      box1.left == 0;
+      box2.right == stage.right - 10.0;
+      box2.left >= box1.right + 10.0;
+      box1.width == 50.0 @ weak;
+      box2.width == 100.0 @ weak;
+      box1.height == 100;
+      box2.height == 100;
+    })
     .build(ui);
 
   let _label_background =
@@ -352,6 +399,17 @@ fn sdl2_maintain(event_pump: &mut EventPump, rasterizer: &mut Rasterizer, ui: &m
         Update::Mouse(mouse) => {
           ui.update_mouse(mouse.clone());
         }
+        Update::Wheel(x, y) => {
+          ui.update_wheel(*x, *y);
+        }
+        Update::Key(key) => {
+          ui.update_key(*key);
+        }
+        Update::ToggleDebug => {
+          ui.toggle_debug();
+        }
+        // These are up to the owner of the UI
+        Update::ToggleInspector | Update::TextInput(_) | Update::Backspace => {}
       }
     });
 
@@ -381,7 +439,7 @@ fn main() {
 
   let button =
     ButtonBuilder::new("Press me!")
-    .build(&mut ui, &mut rasterizer);
+    .build(&mut ui);
 
   ElementBuilder::new()
     .left(10)
@@ -394,18 +452,41 @@ fn main() {
 
   let mut num_clicks = 0;
 
+  let mut may_inspector:Option<Inspector> = None;
+
   let mut event_pump =
     sdl
     .event_pump()
     .unwrap();
 
   'mainloop: loop {
-    let should_quit =
-      sdl2_maintain(&mut event_pump, &mut rasterizer, &mut ui)
-      == Some(Update::Quit);
-    if should_quit {
-      break 'mainloop;
+    let may_update =
+      sdl2_maintain(&mut event_pump, &mut rasterizer, &mut ui);
+    match may_update {
+      Some(Update::Quit) => {
+        break 'mainloop;
+      }
+      Some(Update::ToggleInspector) => {
+        match may_inspector.take() {
+          Some(mut inspector) => inspector.close(&mut ui),
+          None => may_inspector = Some(Inspector::new())
+        }
+      }
+      Some(Update::TextInput(s)) => {
+        may_inspector
+          .iter_mut()
+          .for_each(|inspector| inspector.type_text(&mut ui, &s));
+      }
+      Some(Update::Backspace) => {
+        may_inspector
+          .iter_mut()
+          .for_each(|inspector| inspector.backspace(&mut ui));
+      }
+      _ => {}
     }
+    may_inspector
+      .iter_mut()
+      .for_each(|inspector| inspector.update(&mut ui));
 
     if ui.has_event(button, Event::MouseUp) {
       println!("label_size: {:?}", ui.get_size(label));
@@ -0,0 +1,341 @@
+//! Measuring text straight from font files, without a canvas or a
+//! Rasterizer. The UI keeps a TextMeasurer as a resource, so widgets can
+//! size themselves at build time with `ui.measure_text(&text)` and systems
+//! can fetch it with `Write<TextMeasurer>`.
+//!
+//! Sizes come from the font's advances, each rounded to a whole pixel. The
+//! glyph atlas renders its glyphs with a TextMeasurer too, so text is drawn
+//! at the size it was measured.
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::components::{FontMetrics, Text};
+use super::fonts::{FontRegistry, SharedFontRegistry};
+
+
+/// A glyph rendered by a TextMeasurer, in white with its coverage as alpha.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphImage {
+  /// Where the image goes, relative to the pen at the top of the line.
+  pub x: i32,
+  pub y: i32,
+  pub width: u32,
+  pub height: u32,
+  /// ARGB8888 pixels, row by row.
+  pub pixels: Vec<u8>,
+  /// How far to move along before drawing the next glyph.
+  pub advance: i32
+}
+
+
+pub struct TextMeasurer {
+  /// The UI shares this with the Rasterizer it maintains with, so text is
+  /// drawn with the fonts it was measured with. Changes to the registry only
+  /// affect fonts that haven't been loaded yet.
+  pub registry: SharedFontRegistry,
+  fonts: HashMap<PathBuf, FontArc>,
+  chains: HashMap<String, Vec<PathBuf>>
+}
+
+
+impl Default for TextMeasurer {
+  fn default() -> TextMeasurer {
+    TextMeasurer::new()
+  }
+}
+
+
+/// SDL_ttf sizes fonts in points at 72dpi, so a point is a pixel per em.
+fn scale_for(font: &FontArc, font_size: u16) -> PxScale {
+  let units_per_em =
+    font
+    .units_per_em()
+    .unwrap_or(1000.0);
+  PxScale::from(font_size as f32 * font.height_unscaled() / units_per_em)
+}
+
+
+/// The first of the fonts that has the character, or the first font.
+fn font_for(fonts: &[FontArc], c: char) -> Option<&FontArc> {
+  fonts
+    .iter()
+    .find(|font| font.glyph_id(c).0 != 0)
+    .or(fonts.first())
+}
+
+
+impl TextMeasurer {
+  pub fn new() -> TextMeasurer {
+    TextMeasurer {
+      registry: FontRegistry::new().shared(),
+      fonts: HashMap::new(),
+      chains: HashMap::new()
+    }
+  }
+
+  pub fn with_registry(registry: FontRegistry) -> TextMeasurer {
+    let mut tm =
+      TextMeasurer::new();
+    tm.registry = registry.shared();
+    tm
+  }
+
+  /// The text's font followed by its fallbacks.
+  fn fonts_for(&mut self, text: &Text) -> Result<Vec<FontArc>, String> {
+    if !self.chains.contains_key(&text.font_path) {
+      let mut paths =
+        self
+        .registry
+        .lock()
+        .map_err(|e| format!("Could not use the font registry: {}", e))?
+        .chain(&text.font_path);
+      if paths.is_empty() {
+        paths.push(PathBuf::from(&text.font_path));
+      }
+      self
+        .chains
+        .insert(text.font_path.clone(), paths);
+    }
+    let paths =
+      self
+      .chains
+      .get(&text.font_path)
+      .cloned()
+      .unwrap_or(vec![]);
+    let mut fonts = vec![];
+    for path in paths.into_iter() {
+      if !self.fonts.contains_key(&path) {
+        let bytes =
+          std::fs::read(&path)
+          .map_err(|e| format!("Could not read font {:?}: {}", path, e))?;
+        let font =
+          FontArc::try_from_vec(bytes)
+          .map_err(|e| format!("Could not load font {:?}: {}", path, e))?;
+        self
+          .fonts
+          .insert(path.clone(), font);
+      }
+      fonts.push(self.fonts[&path].clone());
+    }
+    Ok(fonts)
+  }
+
+  /// The width and height the text takes up on one line.
+  pub fn measure(&mut self, text: &Text) -> Result<(u32, u32), String> {
+    let fonts =
+      self.fonts_for(text)?;
+    if fonts.is_empty() {
+      return Err(format!("No fonts for {:?}", text.font_path));
+    }
+    let mut width = 0.0;
+    for c in text.text.chars() {
+      let font =
+        font_for(&fonts, c)
+        .expect("Impossible missing font");
+      let scaled =
+        font.as_scaled(scale_for(font, text.font_size));
+      width += scaled.h_advance(scaled.glyph_id(c)).round();
+    }
+    let metrics =
+      self.metrics(text)?;
+    Ok((f32::max(0.0, width) as u32, metrics.line_height))
+  }
+
+  /// The metrics of the text's font.
+  pub fn metrics(&mut self, text: &Text) -> Result<FontMetrics, String> {
+    let fonts =
+      self.fonts_for(text)?;
+    let primary =
+      fonts
+      .first()
+      .ok_or(format!("No fonts for {:?}", text.font_path))?;
+    let scaled =
+      primary.as_scaled(scale_for(primary, text.font_size));
+    let ascent =
+      scaled.ascent().ceil() as i32;
+    let descent =
+      scaled.descent().floor() as i32;
+    let line_height =
+      i32::max(0, ascent - descent) as u32;
+    Ok(
+      FontMetrics {
+        ascent,
+        descent,
+        line_height,
+        line_skip: line_height + f32::max(0.0, scaled.line_gap()).round() as u32
+      }
+    )
+  }
+
+  /// Render one of the text's characters, from the first of its fonts that
+  /// has it. Whitespace comes back with an empty image.
+  pub fn glyph(&mut self, text: &Text, c: char) -> Result<GlyphImage, String> {
+    let fonts =
+      self.fonts_for(text)?;
+    let font =
+      font_for(&fonts, c)
+      .ok_or(format!("No fonts for {:?}", text.font_path))?;
+    let scaled =
+      font.as_scaled(scale_for(font, text.font_size));
+    let advance =
+      scaled.h_advance(scaled.glyph_id(c)).round() as i32;
+    // Sit the glyph on the baseline, the primary font's ascent down
+    let ascent =
+      self.metrics(text)?.ascent;
+    let mut glyph =
+      scaled.scaled_glyph(c);
+    glyph.position =
+      point(0.0, ascent as f32);
+    let outlined =
+      match font.outline_glyph(glyph) {
+        Some(outlined) => outlined,
+        None => {
+          return Ok(
+            GlyphImage { x: 0, y: 0, width: 0, height: 0, pixels: vec![], advance }
+          );
+        }
+      };
+    let bounds =
+      outlined.px_bounds();
+    let (width, height) =
+      (bounds.width() as u32, bounds.height() as u32);
+    let mut pixels =
+      vec![0; width as usize * height as usize * 4];
+    outlined
+      .draw(|x, y, coverage| {
+        let alpha =
+          (f32::min(1.0, coverage) * 255.0).round() as u32;
+        let i =
+          (y as usize * width as usize + x as usize) * 4;
+        pixels[i .. i + 4]
+          .copy_from_slice(&((alpha << 24) | 0x00ff_ffff).to_ne_bytes());
+      });
+    Ok(
+      GlyphImage {
+        x: bounds.min.x as i32,
+        y: bounds.min.y as i32,
+        width,
+        height,
+        pixels,
+        advance
+      }
+    )
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The font that ships with the crate, found wherever the tests run from.
+  const KOMIKA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/komika.ttf");
+
+  fn text(s: &str) -> Text {
+    Text::new(s).font(KOMIKA)
+  }
+
+  #[test]
+  fn text_is_measured_by_whole_pixel_advances() {
+    let mut measurer =
+      TextMeasurer::new();
+    let (w, h) =
+      measurer
+      .measure(&text("Hello"))
+      .expect("Could not measure text");
+    assert!(w > 0 && h > 0);
+    // Without kerning each character adds its own advance
+    let width_of = |measurer: &mut TextMeasurer, s: &str| {
+      measurer
+        .measure(&text(s))
+        .expect("Could not measure text")
+        .0
+    };
+    let (av, va) =
+      (width_of(&mut measurer, "AV"), width_of(&mut measurer, "VA"));
+    assert_eq!(av, va);
+    assert_eq!(av, width_of(&mut measurer, "A") + width_of(&mut measurer, "V"));
+    assert_eq!(width_of(&mut measurer, ""), 0);
+
+    let mut big =
+      text("Hello");
+    big.font_size = 32;
+    let (bw, bh) =
+      measurer
+      .measure(&big)
+      .expect("Could not measure big text");
+    assert!(bw > w && bh > h);
+  }
+
+  #[test]
+  fn metrics_add_up() {
+    let mut measurer =
+      TextMeasurer::new();
+    let metrics =
+      measurer
+      .metrics(&text("x"))
+      .expect("Could not get metrics");
+    assert!(metrics.ascent > 0);
+    assert!(metrics.descent <= 0);
+    assert_eq!(metrics.line_height as i32, metrics.ascent - metrics.descent);
+    assert!(metrics.line_skip >= metrics.line_height);
+    assert_eq!(
+      measurer.measure(&text("x")).expect("Could not measure text").1,
+      metrics.line_height
+    );
+  }
+
+  #[test]
+  fn families_come_from_the_shared_registry() {
+    let mut measurer =
+      TextMeasurer::new();
+    let missing =
+      text("Hello").font("No Such Family");
+    assert!(measurer.measure(&missing).is_err());
+
+    measurer
+      .registry
+      .lock()
+      .expect("Could not lock the registry")
+      .register("Comic", KOMIKA);
+    let by_family =
+      measurer
+      .measure(&text("Hello").font("Comic"))
+      .expect("Could not measure text by family");
+    let by_path =
+      measurer
+      .measure(&text("Hello"))
+      .expect("Could not measure text by path");
+    assert_eq!(by_family, by_path);
+  }
+
+  #[test]
+  fn glyphs_are_drawn_at_their_measured_size() {
+    let mut measurer =
+      TextMeasurer::new();
+    let mut width = 0;
+    for c in "Hello".chars() {
+      let image =
+        measurer
+        .glyph(&text("Hello"), c)
+        .expect("Could not render glyph");
+      assert!(image.width > 0 && image.height > 0);
+      assert_eq!(image.pixels.len(), (image.width * image.height * 4) as usize);
+      assert!(image.pixels.iter().any(|byte| *byte > 0));
+      width += image.advance;
+    }
+    assert_eq!(
+      measurer.measure(&text("Hello")).expect("Could not measure text").0,
+      width as u32
+    );
+
+    let space =
+      measurer
+      .glyph(&text(" "), ' ')
+      .expect("Could not render a space");
+    assert_eq!((space.width, space.height), (0, 0));
+    assert!(space.advance > 0);
+  }
+}
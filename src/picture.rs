@@ -1,8 +1,9 @@
+use serde::Deserialize;
 use specs::prelude::{Component, VecStorage};
 
 
 /// Primitive raster drawing commands.
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Hash, PartialEq, Eq)]
 pub enum PictureCmd {
   SetColor(u8, u8, u8, u8),
   FillRect(u32, u32, u32, u32),
@@ -0,0 +1,367 @@
+use specs::prelude::*;
+use specs::storage::MaskedStorage;
+
+use super::components::*;
+use super::selector::Selector;
+
+
+/// Match a string against a glob pattern, where `*` matches any run of
+/// characters and `?` matches any single character.
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+  let pattern:Vec<char> =
+    pattern
+    .chars()
+    .collect();
+  let s:Vec<char> =
+    s
+    .chars()
+    .collect();
+  // Classic backtracking matcher - remember the last star and where in `s`
+  // it started matching from.
+  let (mut p, mut i) = (0, 0);
+  let mut may_star:Option<(usize, usize)> = None;
+  while i < s.len() {
+    if p < pattern.len() && (pattern[p] == '?' || pattern[p] == s[i]) {
+      p += 1;
+      i += 1;
+    } else if p < pattern.len() && pattern[p] == '*' {
+      may_star = Some((p, i));
+      p += 1;
+    } else if let Some((star_p, star_i)) = may_star {
+      p = star_p + 1;
+      i = star_i + 1;
+      may_star = Some((star_p, star_i + 1));
+    } else {
+      return false;
+    }
+  }
+  pattern[p..]
+    .iter()
+    .all(|c| *c == '*')
+}
+
+
+fn clip_of(world: &World, ent: Entity) -> Option<ElementBox> {
+  clip_box(
+    ent,
+    &world.read_storage::<Parent>(),
+    &world.read_storage::<ClipChildren>(),
+    &world.read_storage::<ElementBox>()
+  )
+}
+
+
+/// The part of the entity's box that isn't clipped away.
+fn visible_box(world: &World, ent: Entity, el: &ElementBox) -> ElementBox {
+  clip_of(world, ent)
+    .map(|clip| el.intersection(&clip))
+    .unwrap_or(el.clone())
+}
+
+
+/// Whether or not the entity has a C component, without panicking if C was
+/// never registered.
+fn has_component<C: Component>(world: &World, ent: Entity) -> bool {
+  world.try_fetch::<MaskedStorage<C>>().is_some()
+    && world
+      .read_storage::<C>()
+      .contains(ent)
+}
+
+
+type Filter<'w> = Box<dyn Fn(&World, Entity, &ElementBox) -> bool + 'w>;
+
+
+/// A query over the UI's laid out entities. Each added filter narrows the
+/// results, which are `(Entity, ElementBox)` pairs ordered by z index.
+///
+///```ignore
+/// let labels_on_top =
+///   ui.query()
+///   .name_matches("label*")
+///   .with::<Text>()
+///   .z_range(1, 10)
+///   .iter();
+///```
+pub struct Query<'w> {
+  world: &'w World,
+  filters: Vec<Filter<'w>>
+}
+
+
+impl<'w> Query<'w> {
+  pub fn new(world: &'w World) -> Query<'w> {
+    Query {
+      world,
+      filters: vec![]
+    }
+  }
+
+  /// Add an arbitrary filter.
+  pub fn filter<F>(self, f: F) -> Self
+  where
+    F: Fn(&World, Entity, &ElementBox) -> bool + 'w
+  {
+    let mut q = self;
+    q.filters.push(Box::new(f));
+    q
+  }
+
+  /// Entities with exactly this Name.
+  pub fn named(self, name: &str) -> Self {
+    let name =
+      name.to_string();
+    self.filter(move |world, ent, _| {
+      world
+        .read_storage::<Name>()
+        .get(ent)
+        .map(|Name(n)| *n == name)
+        .unwrap_or(false)
+    })
+  }
+
+  /// Entities with a Name matching the glob pattern.
+  pub fn name_matches(self, pattern: &str) -> Self {
+    let pattern =
+      pattern.to_string();
+    self.filter(move |world, ent, _| {
+      world
+        .read_storage::<Name>()
+        .get(ent)
+        .map(|Name(n)| glob_match(&pattern, n))
+        .unwrap_or(false)
+    })
+  }
+
+  /// Entities matching a selector.
+  pub fn matching(self, selector: Selector) -> Self {
+    self.filter(move |world, ent, _| selector.matches(world, ent))
+  }
+
+  /// Entities that have a C component. If C was never registered nothing
+  /// has one.
+  pub fn with<C: Component>(self) -> Self {
+    self.filter(|world, ent, _| has_component::<C>(world, ent))
+  }
+
+  /// Entities that don't have a C component. If C was never registered
+  /// nothing has one.
+  pub fn without<C: Component>(self) -> Self {
+    self.filter(|world, ent, _| !has_component::<C>(world, ent))
+  }
+
+  /// Entities whose box intersects the given rectangle. Parts of a box
+  /// clipped away by ClipChildren ancestors don't count.
+  pub fn intersecting(self, x: i32, y: i32, width: u32, height: u32) -> Self {
+    self.filter(move |world, ent, el| {
+      let el =
+        visible_box(world, ent, el);
+      el.left() < x + width as i32
+        && el.right() > x
+        && el.top() < y + height as i32
+        && el.bottom() > y
+    })
+  }
+
+  /// Entities whose box contains the given point, edges included (the same
+  /// test the EventSystem uses for the mouse).
+  pub fn containing(self, x: i32, y: i32) -> Self {
+    self.filter(move |world, ent, el| {
+      el.contains(x, y)
+        && clip_of(world, ent)
+          .map(|clip| clip.contains(x, y))
+          .unwrap_or(true)
+    })
+  }
+
+  /// Entities with a z index between min and max, inclusive.
+  pub fn z_range(self, min: i32, max: i32) -> Self {
+    self.filter(move |_, _, el| {
+      el.z >= min && el.z <= max
+    })
+  }
+
+  /// Run the query.
+  pub fn iter(&self) -> std::vec::IntoIter<(Entity, ElementBox)> {
+    let entities =
+      self
+      .world
+      .entities();
+    let element_boxes =
+      self
+      .world
+      .read_storage::<ElementBox>();
+    let mut results:Vec<(Entity, ElementBox)> =
+      (&entities, &element_boxes)
+      .join()
+      .filter(|(ent, el)| {
+        self
+          .filters
+          .iter()
+          .all(|f| f(self.world, *ent, el))
+      })
+      .map(|(ent, el)| (ent, el.clone()))
+      .collect();
+    results
+      .sort_by_key(|(ent, el)| (el.z, ent.id()));
+    results
+      .into_iter()
+  }
+
+  pub fn first(&self) -> Option<(Entity, ElementBox)> {
+    self
+      .iter()
+      .next()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn globs_match_literally() {
+    assert!(glob_match("", ""));
+    assert!(glob_match("button", "button"));
+    assert!(!glob_match("button", "buttons"));
+    assert!(!glob_match("buttons", "button"));
+    assert!(!glob_match("", "a"));
+  }
+
+  #[test]
+  fn globs_match_stars() {
+    assert!(glob_match("*", ""));
+    assert!(glob_match("*", "anything"));
+    assert!(glob_match("Button*", "Button label"));
+    assert!(glob_match("*label", "Button label"));
+    assert!(glob_match("*tt*", "Button label"));
+    assert!(glob_match("B*l*l", "Button label"));
+    assert!(glob_match("a*b*c", "aXbYbZc"));
+    assert!(glob_match("**a", "a"));
+    assert!(!glob_match("*label", "Button labels"));
+    assert!(!glob_match("a*b*c", "aXbYbZ"));
+  }
+
+  #[test]
+  fn globs_match_question_marks() {
+    assert!(glob_match("row ?", "row 1"));
+    assert!(glob_match("??", "ab"));
+    assert!(glob_match("?*", "a"));
+    assert!(!glob_match("?", ""));
+    assert!(!glob_match("row ?", "row 10"));
+    assert!(glob_match("row ?*", "row 10"));
+  }
+
+  /// An entity with the given box and nothing else.
+  fn boxed(world: &mut World, x: i32, y: i32, width: u32, height: u32, z: i32) -> Entity {
+    world
+      .create_entity()
+      .with(ElementBox { x, y, z, width, height })
+      .build()
+  }
+
+  fn world() -> World {
+    let mut world =
+      World::new();
+    world.register::<ClipChildren>();
+    world.register::<ElementBox>();
+    world.register::<Name>();
+    world.register::<Parent>();
+    world
+  }
+
+  fn found(query: Query) -> Vec<Entity> {
+    query
+      .iter()
+      .map(|(ent, _)| ent)
+      .collect()
+  }
+
+  #[test]
+  fn boxes_are_found_by_intersection() {
+    let mut world =
+      world();
+    let a =
+      boxed(&mut world, 0, 0, 10, 10, 0);
+    let b =
+      boxed(&mut world, 20, 0, 10, 10, 0);
+    assert_eq!(found(Query::new(&world).intersecting(5, 5, 10, 10)), vec![a]);
+    assert_eq!(found(Query::new(&world).intersecting(5, 5, 20, 1)), vec![a, b]);
+    // Touching edges don't intersect
+    assert!(found(Query::new(&world).intersecting(10, 0, 10, 10)).is_empty());
+    assert!(found(Query::new(&world).intersecting(0, 10, 10, 10)).is_empty());
+  }
+
+  #[test]
+  fn boxes_are_found_by_the_points_they_contain() {
+    let mut world =
+      world();
+    let a =
+      boxed(&mut world, 0, 0, 10, 10, 0);
+    let b =
+      boxed(&mut world, 5, 5, 10, 10, 1);
+    assert_eq!(found(Query::new(&world).containing(2, 2)), vec![a]);
+    assert_eq!(found(Query::new(&world).containing(7, 7)), vec![a, b]);
+    // Edges are included
+    assert_eq!(found(Query::new(&world).containing(10, 10)), vec![a, b]);
+    assert!(found(Query::new(&world).containing(16, 16)).is_empty());
+  }
+
+  #[test]
+  fn clipped_away_parts_are_not_found() {
+    let mut world =
+      world();
+    let clip =
+      boxed(&mut world, 0, 0, 10, 10, 0);
+    world
+      .write_storage::<ClipChildren>()
+      .insert(clip, ClipChildren)
+      .expect("Could not clip");
+    let child =
+      boxed(&mut world, 5, 0, 10, 10, 1);
+    world
+      .write_storage::<Parent>()
+      .insert(child, Parent(clip))
+      .expect("Could not parent");
+    assert_eq!(found(Query::new(&world).containing(7, 5)), vec![clip, child]);
+    assert_eq!(found(Query::new(&world).containing(12, 5)), vec![]);
+    assert_eq!(found(Query::new(&world).intersecting(11, 0, 5, 5)), vec![]);
+  }
+
+  #[test]
+  fn results_are_in_z_order_within_the_range() {
+    let mut world =
+      world();
+    let top =
+      boxed(&mut world, 0, 0, 10, 10, 5);
+    let bottom =
+      boxed(&mut world, 0, 0, 10, 10, -1);
+    let middle =
+      boxed(&mut world, 0, 0, 10, 10, 2);
+    assert_eq!(found(Query::new(&world)), vec![bottom, middle, top]);
+    assert_eq!(found(Query::new(&world).z_range(0, 5)), vec![middle, top]);
+    assert_eq!(found(Query::new(&world).z_range(-1, 2)), vec![bottom, middle]);
+    assert!(found(Query::new(&world).z_range(3, 4)).is_empty());
+    assert_eq!(Query::new(&world).first().map(|(ent, _)| ent), Some(bottom));
+  }
+
+  #[test]
+  fn components_are_filtered_whether_registered_or_not() {
+    let mut world =
+      world();
+    let named =
+      boxed(&mut world, 0, 0, 10, 10, 0);
+    world
+      .write_storage::<Name>()
+      .insert(named, Name("named".to_string()))
+      .expect("Could not name");
+    let unnamed =
+      boxed(&mut world, 0, 0, 10, 10, 1);
+    assert_eq!(found(Query::new(&world).with::<Name>()), vec![named]);
+    assert_eq!(found(Query::new(&world).without::<Name>()), vec![unnamed]);
+    // Text was never registered
+    assert!(found(Query::new(&world).with::<Text>()).is_empty());
+    assert_eq!(found(Query::new(&world).without::<Text>()), vec![named, unnamed]);
+  }
+}
@@ -6,36 +6,82 @@ use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::{BlendMode, Texture, TextureAccess, TextureCreator, TextureQuery, WindowCanvas};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use super::components::*;
+use super::debug::DebugOverlay;
+use super::fonts::{FontRegistry, SharedFontRegistry};
+use super::measure::TextMeasurer;
 use super::picture::*;
 use super::WindowSize;
 
+mod atlas;
+mod cache;
+
+pub use atlas::{Glyph, GlyphAtlas, ELLIPSIS};
+pub use cache::{Cacheable, CacheStats, TextureCache, DEFAULT_BUDGET};
+
 
 pub type FontMap<'ctx> = HashMap<(String, u16), Font<'ctx, 'static>>;
 
-pub type TextCache<'ctx> = HashMap<Text, Texture<'ctx>>;
+pub type TextCache<'ctx> = TextureCache<Text, Texture<'ctx>>;
 
-pub type PictureCache<'ctx> = HashMap<Picture, Texture<'ctx>>;
+pub type PictureCache<'ctx> = TextureCache<Picture, Texture<'ctx>>;
 
 pub type DrawingSystemData<'a> = (
   Entities<'a>,
+  ReadStorage<'a, ClipChildren>,
   ReadStorage<'a, ElementBox>,
   ReadStorage<'a, Invisible>,
   ReadStorage<'a, Name>,
+  ReadStorage<'a, Parent>,
   ReadStorage<'a, Picture>,
   ReadStorage<'a, Text>,
   Write<'a, WindowSize>
 );
 
 
+fn to_rect(el: &ElementBox) -> Rect {
+  Rect::new(el.x, el.y, el.width, el.height)
+}
+
+
+/// The box a shrink-to-fit text was last fitted into and the font size that
+/// fit.
+struct Fit {
+  width: u32,
+  height: u32,
+  font_size: u16,
+  last_used: u64
+}
+
+
+/// Something to draw for an entity.
+enum Renderable<'a, 'ctx> {
+  Texture(&'a Texture<'ctx>),
+  Text(&'a Text)
+}
+
+
 /// Rasterizes text and 2d pictures.
 /// The Rasterizer also manages font and texture resources.
 // TODO: Abstract Rasterizer into a trait.
 pub struct Rasterizer<'ctx> {
   pub fonts: FontMap<'ctx>,
+  /// While maintaining, the UI swaps this for the registry its TextMeasurer
+  /// uses, see `share_font_registry`. Changes to the registry only affect
+  /// fonts that haven't been loaded yet.
+  pub font_registry: SharedFontRegistry,
+  /// The font files loaded for each Text font_path and size, fallbacks and
+  /// all.
+  font_chains: HashMap<(String, u16), Vec<(String, u16)>>,
+  /// How each shrink-to-fit text was last fitted. Fits that weren't used
+  /// during a frame are dropped at the end of it.
+  fitted: HashMap<Text, Fit>,
+  frame: u64,
   pub text_cache: TextCache<'ctx>,
   pub picture_cache: PictureCache<'ctx>,
+  pub atlas: GlyphAtlas<Texture<'ctx>>,
   pub canvas: Option<&'ctx mut WindowCanvas>,
   pub tex_creator: Option<&'ctx TextureCreator<WindowContext>>,
   pub ttf: Option<&'ctx Sdl2TtfContext>
@@ -50,8 +96,13 @@ impl<'ctx> Rasterizer<'ctx> {
   ) -> Rasterizer<'ctx> {
     Rasterizer {
       fonts: HashMap::new(),
-      text_cache: HashMap::new(),
-      picture_cache: HashMap::new(),
+      font_registry: FontRegistry::new().shared(),
+      font_chains: HashMap::new(),
+      fitted: HashMap::new(),
+      frame: 0,
+      text_cache: TextureCache::new(DEFAULT_BUDGET),
+      picture_cache: TextureCache::new(DEFAULT_BUDGET),
+      atlas: GlyphAtlas::new(DEFAULT_BUDGET),
       canvas: Some(canvas),
       tex_creator: Some(tex_creator),
       ttf: Some(ttf)
@@ -64,21 +115,19 @@ impl<'ctx> Rasterizer<'ctx> {
     s: &str,
     c: Color,
     font: &Font,
-  ) -> (Texture<'ctx>, u32, u32) {
+  ) -> Result<(Texture<'ctx>, u32, u32), String> {
     // Generate the texture and copy the text into it
     let surface =
       font
       .render(s)
       .blended(c)
-      .map_err(|e| e.to_string())
-      .unwrap();
+      .map_err(|e| e.to_string())?;
     let mut texture =
       self
       .tex_creator
       .expect("Rasterizer does not have a tex_creator to rasterize text with")
       .create_texture_from_surface(&surface)
-      .map_err(|e| e.to_string())
-      .unwrap();
+      .map_err(|e| e.to_string())?;
     texture
       .set_blend_mode(BlendMode::Blend);
     texture
@@ -87,46 +136,107 @@ impl<'ctx> Rasterizer<'ctx> {
     let TextureQuery{ width, height, ..} =
       texture
       .query();
-    (texture, width, height)
+    Ok((texture, width, height))
+  }
+
+
+  /// Load the given text's font and its fallbacks, if they haven't been
+  /// loaded already, returning their keys into `fonts`, primary font first.
+  /// Fonts that SDL can't open are reported and left out of the chain, which
+  /// may leave it empty.
+  fn load_fonts(&mut self, text: &Text) -> Vec<(String, u16)> {
+    let text_key =
+      (text.font_path.clone(), text.font_size);
+    if let Some(chain) = self.font_chains.get(&text_key) {
+      return chain.clone();
+    }
+
+    let mut paths:Vec<String> =
+      match self.font_registry.lock() {
+        Ok(mut registry) => {
+          registry
+            .chain(&text.font_path)
+            .into_iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect()
+        }
+        Err(e) => {
+          error!("Could not use the font registry: {}", e);
+          vec![]
+        }
+      };
+    if paths.is_empty() {
+      // Let SDL have a go at it, if only to report the error
+      paths.push(text.font_path.clone());
+    }
+
+    let mut chain:Vec<(String, u16)> =
+      vec![];
+    for font_key in paths.into_iter().map(|path| (path, text.font_size)) {
+      let has_font =
+        self
+        .fonts
+        .contains_key(&font_key);
+
+      if !has_font {
+        let may_font =
+          self
+          .ttf
+          .expect("DrawingSystem has no ttf contexnt to load fonts with.")
+          .load_font(&font_key.0, font_key.1);
+        match may_font {
+          Ok(font) => {
+            self
+              .fonts
+              .insert(font_key.clone(), font);
+          }
+          Err(e) => {
+            error!("Could not load font {:?}, leaving it out: {}", font_key, e);
+            continue;
+          }
+        }
+      }
+      chain.push(font_key);
+    }
+    debug!("fonts for {:?}: {:?}", text_key, chain);
+    self
+      .font_chains
+      .insert(text_key, chain.clone());
+    chain
   }
 
+  /// Find fonts with the given registry from now on. Fonts that have been
+  /// found for text already are looked up again.
+  pub fn share_font_registry(&mut self, registry: SharedFontRegistry) {
+    if !Arc::ptr_eq(&self.font_registry, &registry) {
+      self.font_registry = registry;
+      self.font_chains.clear();
+    }
+  }
 
   /// Get the given text as a rasterized texture and its width and height.
   /// If the given text's font has not yet been loaded it will be cached.
   /// If the given text has not yet been rasterized it will be cached.
-  pub fn get_text(&mut self, text: &Text) -> (&Texture<'ctx>, u32, u32) {
+  /// Text entities are drawn from the glyph atlas, this is for when you need
+  /// a texture of your own. Only the text's primary font is used, rendered by
+  /// SDL_ttf, so the texture may not be quite the size the TextMeasurer
+  /// measures.
+  pub fn get_text(&mut self, text: &Text) -> Result<(&Texture<'ctx>, u32, u32), String> {
     let has_texture =
         self
         .text_cache
-        .contains_key(&text);
+        .touch(&text);
 
       if !has_texture {
-        let font_key =
-          (text.font_path.clone(), text.font_size);
-        let has_font =
+        let chain =
           self
-          .fonts
-          .contains_key(&font_key);
-
-        if !has_font {
-          let font =
-            self
-            .ttf
-            .expect("DrawingSystem has no ttf contexnt to load fonts with.")
-            .load_font(&text.font_path, text.font_size)
-            .expect(
-              &format!("Could not load font: {:?}", font_key)
-            );
-          self
-            .fonts
-            .insert(font_key.clone(), font);
-        }
+          .load_fonts(text);
 
         let font =
-          self
-          .fonts
-          .get(&font_key)
-          .expect("Impossible missing font.");
+          chain
+          .first()
+          .and_then(|key| self.fonts.get(key))
+          .ok_or(format!("No fonts for {:?}", text.font_path))?;
 
         let color =
           Color::RGBA(
@@ -141,7 +251,7 @@ impl<'ctx> Rasterizer<'ctx> {
             &text.text,
             color,
             font
-          );
+          )?;
 
         self
           .text_cache
@@ -151,11 +261,112 @@ impl<'ctx> Rasterizer<'ctx> {
     let tex =
       self
       .text_cache
-      .get(&text)
+      .peek(&text)
       .expect("Could not get cached text");
     let TextureQuery{ width, height, ..} =
       tex.query();
-    (tex, width, height)
+    Ok((tex, width, height))
+  }
+
+  /// Put the text's glyphs in the atlas. Failures are reported and the
+  /// text is drawn with whichever glyphs made it in.
+  fn add_glyphs(&mut self, measurer: &mut TextMeasurer, text: &Text) {
+    let result =
+      self
+      .atlas
+      .add_glyphs(
+        self
+          .tex_creator
+          .expect("Rasterizer does not have a tex_creator to rasterize glyphs with"),
+        measurer,
+        text
+      );
+    if let Err(e) = result {
+      error!("Could not add glyphs for {:?} to the atlas: {}", text.text, e);
+    }
+  }
+
+  /// Make sure the given text's glyphs are in the atlas and return the
+  /// text's width and height, which is what the measurer measures.
+  pub fn measure_text(&mut self, measurer: &mut TextMeasurer, text: &Text) -> (u32, u32) {
+    self
+      .add_glyphs(measurer, text);
+    match text.overflow {
+      TextOverflow::Ellipsis | TextOverflow::EllipsisMiddle => {
+        let mut ellipsis = text.clone();
+        ellipsis.text = ELLIPSIS.to_string();
+        self
+          .add_glyphs(measurer, &ellipsis);
+      }
+      _ => {}
+    }
+    self
+      .atlas
+      .measure(text)
+  }
+
+  /// The text at the largest font size (no larger than its own) that fits in
+  /// the given box, with its glyphs in the atlas. Sizes are tried with the
+  /// measurer, so only the size that fits is rasterized. The result is
+  /// remembered for drawing shrink-to-fit text.
+  pub fn fit_text(
+    &mut self,
+    measurer: &mut TextMeasurer,
+    text: &Text,
+    width: u32,
+    height: u32
+  ) -> Text {
+    let frame =
+      self.frame;
+    let mut fitted = text.clone();
+    if let Some(fit) = self.fitted.get_mut(text) {
+      if (fit.width, fit.height) == (width, height) {
+        fit.last_used = frame;
+        fitted.font_size = fit.font_size;
+        return fitted;
+      }
+    }
+
+    match measurer.measure(text) {
+      Ok((tw, th)) if tw > width || th > height => {
+        // Start from a size proportional to the overflow and step down from
+        // there, since glyphs don't scale exactly with the font size
+        let ratio =
+          f64::min(width as f64 / tw as f64, height as f64 / th as f64);
+        fitted.font_size =
+          u16::max(1, (text.font_size as f64 * ratio).floor() as u16);
+        while fitted.font_size > 1 {
+          match measurer.measure(&fitted) {
+            Ok((w, h)) if w > width || h > height => {
+              fitted.font_size -= 1;
+            }
+            Ok(_) => { break; }
+            Err(e) => {
+              error!("Could not fit {:?}: {}", text.text, e);
+              break;
+            }
+          }
+        }
+      }
+      Ok(_) => {}
+      Err(e) => {
+        error!("Could not fit {:?}: {}", text.text, e);
+      }
+    }
+    self
+      .add_glyphs(measurer, &fitted);
+    self
+      .fitted
+      .insert(
+        text.clone(),
+        Fit {
+          width,
+          height,
+          font_size: fitted.font_size,
+          last_used: frame
+        }
+      );
+    fitted
   }
 
   fn rasterize_picture(picture: &Picture, canvas: &mut WindowCanvas) {
@@ -189,7 +400,7 @@ impl<'ctx> Rasterizer<'ctx> {
     let has_picture =
       self
       .picture_cache
-      .contains_key(&picture);
+      .touch(&picture);
 
     if !has_picture {
       let (w, h) =
@@ -221,7 +432,7 @@ impl<'ctx> Rasterizer<'ctx> {
     let tex =
       self
       .picture_cache
-      .get(&picture)
+      .peek(&picture)
       .expect("Could not get cached picture``");
 
     let TextureQuery{ width, height, ..} =
@@ -233,9 +444,137 @@ impl<'ctx> Rasterizer<'ctx> {
     (tex, width, height)
   }
 
+  /// Evict textures and glyph atlas pages that haven't been used this frame
+  /// from anything that is over its budget, and forget shrink-to-fit text
+  /// that wasn't fitted this frame. Call this once a frame after drawing.
+  pub fn end_frame(&mut self) {
+    let evicted =
+      self.text_cache.end_frame()
+      + self.picture_cache.end_frame();
+    if evicted > 0 {
+      debug!("rasterizer evicted {} textures", evicted);
+    }
+    let evicted_pages =
+      self.atlas.end_frame();
+    if evicted_pages > 0 {
+      debug!("rasterizer evicted {} glyph atlas pages", evicted_pages);
+    }
+    let frame =
+      self.frame;
+    self
+      .fitted
+      .retain(|_, fit| fit.last_used == frame);
+    self.frame += 1;
+  }
+
+  /// Draw a debug overlay's outlines, labels and the hovered element's
+  /// constraints on top of whatever is on the canvas.
+  fn draw_debug_overlay(
+    &mut self,
+    canvas: &mut WindowCanvas,
+    measurer: &mut TextMeasurer,
+    overlay: &DebugOverlay
+  ) {
+    let label_text = |s: &str| -> Text {
+      let mut text =
+        Text::new(s)
+        .color(255, 255, 255, 255);
+      text.font_size = 10;
+      text
+    };
+
+    if let Some(hovered) = &overlay.hovered {
+      let el =
+        &hovered.element_box;
+      canvas
+        .set_draw_color(Color::RGBA(0, 128, 255, 64));
+      canvas
+        .fill_rect(Rect::new(el.x, el.y, el.width, el.height))
+        .expect("Could not fill hovered element");
+    }
+
+    overlay
+      .boxes
+      .iter()
+      .for_each(|debug_box| {
+        let el =
+          &debug_box.element_box;
+        let is_hovered =
+          overlay.hovered.as_ref() == Some(debug_box);
+        let color =
+          if is_hovered {
+            Color::RGBA(0, 128, 255, 255)
+          } else {
+            Color::RGBA(255, 0, 0, 192)
+          };
+        canvas
+          .set_draw_color(color);
+        canvas
+          .draw_rect(Rect::new(el.x, el.y, el.width, el.height))
+          .expect("Could not outline element");
+
+        let text =
+          label_text(&debug_box.label);
+        let (w, h) =
+          self
+          .measure_text(measurer, &text);
+        canvas
+          .set_draw_color(Color::RGBA(0, 0, 0, 192));
+        canvas
+          .fill_rect(Rect::new(el.x, el.y, w, h))
+          .expect("Could not draw label background");
+        if let Err(e) = self.atlas.draw(canvas, &text, Rect::new(el.x, el.y, w, h)) {
+          error!("Could not draw element label {:?}: {}", text.text, e);
+        }
+      });
+
+    // List the hovered element's constraints in a panel in the top left
+    let lines:Vec<&String> =
+      overlay
+      .hovered
+      .iter()
+      .map(|hovered| &hovered.label)
+      .chain(overlay.constraints.iter())
+      .filter(|line| !line.is_empty())
+      .collect();
+    let mut sizes:Vec<(u32, u32)> =
+      vec![];
+    for line in lines.iter() {
+      sizes.push(self.measure_text(measurer, &label_text(line)));
+    }
+    if !sizes.is_empty() {
+      let padding = 4;
+      let width =
+        sizes
+        .iter()
+        .map(|(w, _)| *w)
+        .max()
+        .unwrap_or(0);
+      let height:u32 =
+        sizes
+        .iter()
+        .map(|(_, h)| *h)
+        .sum();
+      canvas
+        .set_draw_color(Color::RGBA(0, 0, 0, 208));
+      canvas
+        .fill_rect(Rect::new(0, 0, width + padding * 2, height + padding * 2))
+        .expect("Could not draw constraint panel");
+      let mut y = padding as i32;
+      for (line, (w, h)) in lines.into_iter().zip(sizes.into_iter()) {
+        if let Err(e) = self.atlas.draw(canvas, &label_text(line), Rect::new(padding as i32, y, w, h)) {
+          error!("Could not draw constraint {:?}: {}", line, e);
+        }
+        y += h as i32;
+      }
+    }
+  }
+
   pub fn run_sdl2_drawing<'a>(
     &mut self,
-    (entities, element_boxes, invisibles, names, pictures, texts, mut _window_size): DrawingSystemData<'a>
+    (entities, clips, element_boxes, invisibles, names, parents, pictures, texts, mut _window_size): DrawingSystemData<'a>,
+    measurer: &mut TextMeasurer,
+    may_overlay: Option<&DebugOverlay>
   ) {
     let canvas =
       self
@@ -249,7 +588,9 @@ impl<'ctx> Rasterizer<'ctx> {
       .clear();
 
     // Run through each visible entity and render it to the screen
-    let mut renderables:Vec<(&Texture, ElementBox)> =
+    let picture_cache =
+      &self.picture_cache;
+    let mut renderables:Vec<(Renderable, ElementBox, Option<ElementBox>)> =
       (&entities, !&invisibles)
       .join()
       .flat_map(|(ent, ())| {
@@ -262,38 +603,23 @@ impl<'ctx> Rasterizer<'ctx> {
           .get(ent)
           .cloned()
           .unwrap_or(ElementBox::new());
+        let may_clip =
+          clip_box(ent, &parents, &clips, &element_boxes);
+        let clipped_away =
+          may_clip
+          .as_ref()
+          .map(|clip| clip.width == 0 || clip.height == 0)
+          .unwrap_or(false);
+        if clipped_away {
+          return renders;
+        }
 
-        let mk_box = |tex: &Texture| -> ElementBox {
-          let TextureQuery{ width: tw, height: th, ..} =
-            tex
-            .query();
-
-          let mut el = el.clone();
-
-          if el.width == 0 {
-              el.width = tw;
-          }
-          assert!(el.width != 0, format!("width of {:?} = {:?}", may_name, el.width));
-          if el.height == 0 {
-            el.height = th;
-          }
-          assert!(el.height != 0, format!("height of {:?} = {:?}", may_name, el.height));
-
-          //println!("{:?} {:?}", may_name, el);
-          el
-        };
-
-        // If this thing is a piece of text, draw that
+        // If this thing is a piece of text, draw that from the atlas
         texts
           .get(ent)
           .map(|text| {
-            let tex =
-              self
-              .text_cache
-              .get(text)
-              .expect("Text was not cached! This should be impossible");
             renders
-              .push((tex, mk_box(tex)));
+              .push((Renderable::Text(text), el.clone(), may_clip.clone()));
           });
 
         // If this thing is a rasterized picture, draw that
@@ -301,36 +627,128 @@ impl<'ctx> Rasterizer<'ctx> {
           .get(ent)
           .map(|pic| {
             let tex =
-              self
-              .picture_cache
-              .get(pic)
+              picture_cache
+              .peek(pic)
               .expect("Picture was not cached! This should be impossible");
+            let TextureQuery{ width: tw, height: th, ..} =
+              tex
+              .query();
+
+            let mut el = el.clone();
+
+            if el.width == 0 {
+                el.width = tw;
+            }
+            assert!(el.width != 0, format!("width of {:?} = {:?}", may_name, el.width));
+            if el.height == 0 {
+              el.height = th;
+            }
+            assert!(el.height != 0, format!("height of {:?} = {:?}", may_name, el.height));
+
             renders
-              .push((tex, mk_box(tex)));
+              .push((Renderable::Texture(tex), el, may_clip.clone()));
           });
 
         renders
       })
       .collect::<Vec<_>>();
     renderables
-      .sort_by(|(_, el_a), (_, el_b)| {
+      .sort_by(|(_, el_a, _), (_, el_b, _)| {
         el_a.z.cmp(&el_b.z)
       });
 
+    let atlas =
+      &mut self.atlas;
+    let fitted =
+      &self.fitted;
     renderables
       .into_iter()
-      .for_each(|(tex, el)| {
+      .for_each(|(renderable, el, may_clip)| {
         canvas
-          .copy(
-            tex,
-            None,
-            Some(
-              Rect::new(el.x, el.y, el.width, el.height)
-            )
-          )
-          .unwrap();
+          .set_clip_rect(may_clip.as_ref().map(to_rect));
+        match renderable {
+          Renderable::Texture(tex) => {
+            canvas
+              .copy(
+                tex,
+                None,
+                Some(
+                  Rect::new(el.x, el.y, el.width, el.height)
+                )
+              )
+              .unwrap();
+          }
+          Renderable::Text(text) => {
+            let (tw, th) =
+              atlas
+              .measure(text);
+            // Text with no solved size is drawn at its natural size
+            let w = if el.width == 0 { tw } else { el.width };
+            let h = if el.height == 0 { th } else { el.height };
+            if w == 0 || h == 0 {
+              return;
+            }
+            let text_box =
+              ElementBox{ x: el.x, y: el.y, z: el.z, width: w, height: h };
+            let dest =
+              to_rect(&text_box);
+            let may_unscaled =
+              match text.overflow {
+                TextOverflow::Stretch => None,
+                TextOverflow::Clip => Some(text.clone()),
+                TextOverflow::Ellipsis | TextOverflow::EllipsisMiddle => {
+                  Some(atlas.ellipsize(text, w))
+                }
+                TextOverflow::ShrinkToFit => {
+                  let mut t = text.clone();
+                  fitted
+                    .get(text)
+                    .filter(|fit| (fit.width, fit.height) == (w, h))
+                    .into_iter()
+                    .for_each(|fit| t.font_size = fit.font_size);
+                  Some(t)
+                }
+              };
+            let result =
+              match may_unscaled {
+                None => {
+                  atlas
+                    .draw(canvas, text, dest)
+                }
+                Some(t) => {
+                  // Draw at the text's natural size, cut off by the box
+                  let (uw, uh) =
+                    atlas
+                    .measure(&t);
+                  let clip =
+                    may_clip
+                    .as_ref()
+                    .map(|clip| clip.intersection(&text_box))
+                    .unwrap_or(text_box);
+                  if uw > 0 && uh > 0 && clip.width > 0 && clip.height > 0 {
+                    canvas
+                      .set_clip_rect(to_rect(&clip));
+                    atlas
+                      .draw(canvas, &t, Rect::new(el.x, el.y, uw, uh))
+                  } else {
+                    Ok(())
+                  }
+                }
+              };
+            if let Err(e) = result {
+              error!("Could not draw text {:?}: {}", text.text, e);
+            }
+          }
+        }
       });
 
+    canvas
+      .set_clip_rect(None);
+
+    if let Some(overlay) = may_overlay {
+      self.draw_debug_overlay(canvas, measurer, overlay);
+    }
+
     canvas
       .present();
 
@@ -0,0 +1,601 @@
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use std::collections::HashMap;
+
+use super::super::components::{Text, TextOverflow};
+use super::super::measure::TextMeasurer;
+
+
+/// The width and height of each atlas page.
+pub const PAGE_SIZE: u32 = 512;
+
+/// What ellipsized text ends with (or has in its middle).
+pub const ELLIPSIS: &str = "...";
+
+
+/// A glyph's place in the atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glyph {
+  pub page: usize,
+  /// Where the glyph is on its page, empty for glyphs with nothing to draw.
+  pub rect: Option<Rect>,
+  /// Where the glyph is drawn, relative to the pen at the top of the line.
+  pub offset: (i32, i32),
+  /// How far to move along before drawing the next glyph.
+  pub advance: i32
+}
+
+
+pub type FontKey = (String, u16);
+
+
+struct Page<T> {
+  texture: T,
+  last_used: u64
+}
+
+
+/// Shelf packing - glyphs are placed left to right in rows as tall as the
+/// tallest glyph in them.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Shelves {
+  cursor_x: i32,
+  cursor_y: i32,
+  row_height: u32
+}
+
+
+impl Shelves {
+  /// Where a w x h glyph goes on the page, if there's still room for it.
+  fn place(&mut self, w: u32, h: u32) -> Option<Rect> {
+    if w > PAGE_SIZE || h > PAGE_SIZE {
+      return None;
+    }
+    let (mut x, mut y) =
+      (self.cursor_x, self.cursor_y);
+    let mut row_height =
+      self.row_height;
+    if x as u32 + w > PAGE_SIZE {
+      x = 0;
+      y += row_height as i32;
+      row_height = 0;
+    }
+    if y as u32 + h > PAGE_SIZE {
+      return None;
+    }
+    self.cursor_x = x + w as i32;
+    self.cursor_y = y;
+    self.row_height = u32::max(row_height, h);
+    Some(Rect::new(x, y, w, h))
+  }
+}
+
+
+/// Glyphs rendered once into shared textures, keyed by font, size and
+/// character. Text is drawn by copying each of its glyphs out of the atlas,
+/// so new strings only cost a texture upload for glyphs that haven't been
+/// seen before.
+///
+/// Glyphs are rendered by the TextMeasurer in white and tinted with the
+/// text's color when drawn. Each glyph is drawn at its advance, without
+/// kerning, the same as the TextMeasurer measures it.
+///
+/// Like a TextureCache, the atlas has a byte budget. Once its pages take up
+/// more than that the least recently used pages are dropped along with their
+/// glyphs, which are rendered again the next time they're needed. A page
+/// that has been used during the current frame is never dropped.
+///
+/// The atlas is generic over its page textures so the bookkeeping can be
+/// used without a renderer.
+pub struct GlyphAtlas<T> {
+  /// Dropped pages leave a hole that the next new page fills.
+  pages: Vec<Option<Page<T>>>,
+  glyphs: HashMap<(FontKey, char), Glyph>,
+  line_heights: HashMap<FontKey, u32>,
+  budget: usize,
+  frame: u64,
+  /// The page new glyphs go on and where they go on it.
+  current: Option<usize>,
+  shelves: Shelves
+}
+
+
+fn font_key(text: &Text) -> FontKey {
+  (text.font_path.clone(), text.font_size)
+}
+
+
+/// A blank page to render glyphs into.
+fn new_page<'ctx>(
+  tex_creator: &'ctx TextureCreator<WindowContext>
+) -> Result<Texture<'ctx>, String> {
+  let mut texture =
+    tex_creator
+    .create_texture_static(PixelFormatEnum::ARGB8888, PAGE_SIZE, PAGE_SIZE)
+    .map_err(|e| e.to_string())?;
+  texture
+    .set_blend_mode(BlendMode::Blend);
+  Ok(texture)
+}
+
+
+impl<T> GlyphAtlas<T> {
+  pub fn new(budget: usize) -> GlyphAtlas<T> {
+    GlyphAtlas {
+      pages: vec![],
+      glyphs: HashMap::new(),
+      line_heights: HashMap::new(),
+      budget,
+      frame: 0,
+      current: None,
+      shelves: Shelves::default()
+    }
+  }
+
+  pub fn budget(&self) -> usize {
+    self.budget
+  }
+
+  pub fn set_budget(&mut self, budget: usize) {
+    self.budget = budget;
+  }
+
+  pub fn page_count(&self) -> usize {
+    self
+      .pages
+      .iter()
+      .filter(|page| page.is_some())
+      .count()
+  }
+
+  /// The number of bytes of texture memory held by the pages.
+  pub fn bytes(&self) -> usize {
+    // Four bytes a pixel, same as the TextureCache
+    self.page_count() * (PAGE_SIZE * PAGE_SIZE * 4) as usize
+  }
+
+  pub fn glyph_count(&self) -> usize {
+    self.glyphs.len()
+  }
+
+  pub fn glyph(&self, font: &FontKey, c: char) -> Option<&Glyph> {
+    self
+      .glyphs
+      .get(&(font.clone(), c))
+  }
+
+  /// Mark the page as in use this frame.
+  fn touch(&mut self, page: usize) {
+    let frame =
+      self.frame;
+    if let Some(Some(page)) = self.pages.get_mut(page) {
+      page.last_used = frame;
+    }
+  }
+
+  /// Find room for a w x h glyph, starting a new page if need be.
+  fn allocate<F>(&mut self, w: u32, h: u32, new_page: F) -> Result<(usize, Rect), String>
+  where
+    F: FnOnce() -> Result<T, String>
+  {
+    if w > PAGE_SIZE || h > PAGE_SIZE {
+      return Err(format!("Glyph is too big for the atlas: {}x{}", w, h));
+    }
+    if let Some(index) = self.current {
+      if let Some(rect) = self.shelves.place(w, h) {
+        self.touch(index);
+        return Ok((index, rect));
+      }
+    }
+    let page =
+      Page {
+        texture: new_page()?,
+        last_used: self.frame
+      };
+    let index =
+      match self.pages.iter().position(|page| page.is_none()) {
+        Some(index) => {
+          self.pages[index] = Some(page);
+          index
+        }
+        None => {
+          self.pages.push(Some(page));
+          self.pages.len() - 1
+        }
+      };
+    self.current = Some(index);
+    self.shelves = Shelves::default();
+    let rect =
+      self
+      .shelves
+      .place(w, h)
+      .expect("Impossible full new atlas page");
+    Ok((index, rect))
+  }
+
+  /// The size of the text, using whichever of its glyphs are in the atlas.
+  pub fn measure(&self, text: &Text) -> (u32, u32) {
+    let key =
+      font_key(text);
+    let width:i32 =
+      text
+      .text
+      .chars()
+      .filter_map(|c| self.glyph(&key, c))
+      .map(|glyph| glyph.advance)
+      .sum();
+    let height =
+      self
+      .line_heights
+      .get(&key)
+      .cloned()
+      .unwrap_or(0);
+    (i32::max(0, width) as u32, height)
+  }
+
+  /// The text cut down to fit in the given width, with an ellipsis where
+  /// characters were dropped - at the end, or in the middle if the text's
+  /// overflow is `EllipsisMiddle`. The ellipsis glyphs must already be in the
+  /// atlas. If not even the ellipsis fits it is returned anyway.
+  pub fn ellipsize(&self, text: &Text, width: u32) -> Text {
+    let (tw, _) =
+      self.measure(text);
+    if tw <= width {
+      return text.clone();
+    }
+    let key =
+      font_key(text);
+    let advance = |c: char| -> i32 {
+      self
+        .glyph(&key, c)
+        .map(|glyph| glyph.advance)
+        .unwrap_or(0)
+    };
+    let chars:Vec<char> =
+      text
+      .text
+      .chars()
+      .collect();
+    let ellipsis:i32 =
+      ELLIPSIS
+      .chars()
+      .map(advance)
+      .sum();
+    // heads[n] is the width of the first n characters without trailing
+    // whitespace and tails[n] the width of the last n without leading
+    // whitespace, so each cut is measured without building it
+    let mut heads = vec![0; chars.len() + 1];
+    let mut tails = vec![0; chars.len() + 1];
+    let (mut head_sum, mut tail_sum) = (0, 0);
+    for n in 0 .. chars.len() {
+      let (first, last) =
+        (chars[n], chars[chars.len() - 1 - n]);
+      head_sum += advance(first);
+      tail_sum += advance(last);
+      heads[n + 1] = if first.is_whitespace() { heads[n] } else { head_sum };
+      tails[n + 1] = if last.is_whitespace() { tails[n] } else { tail_sum };
+    }
+    let split = |keep: usize| -> (usize, usize) {
+      if text.overflow == TextOverflow::EllipsisMiddle {
+        let head = (keep + 1) / 2;
+        (head, keep - head)
+      } else {
+        (keep, 0)
+      }
+    };
+    let keep =
+      (0 .. chars.len())
+      .rev()
+      .find(|keep| {
+        let (head, tail) =
+          split(*keep);
+        i32::max(0, heads[head] + ellipsis + tails[tail]) as u32 <= width
+      })
+      .unwrap_or(0);
+    let (head, tail) =
+      split(keep);
+    let start:String = chars[.. head].iter().collect();
+    let end:String = chars[chars.len() - tail ..].iter().collect();
+    let mut t = text.clone();
+    t.text = format!("{}{}{}", start.trim_end(), ELLIPSIS, end.trim_start());
+    t
+  }
+
+  /// Drop least recently used pages that weren't used this frame, and their
+  /// glyphs, until the atlas is within its budget, then start a new frame.
+  /// Returns the number of pages dropped.
+  pub fn end_frame(&mut self) -> usize {
+    let mut evicted = 0;
+    if self.bytes() > self.budget {
+      let frame =
+        self.frame;
+      let mut candidates:Vec<(u64, usize)> =
+        self
+        .pages
+        .iter()
+        .enumerate()
+        .filter_map(|(index, page)| page.as_ref().map(|page| (page.last_used, index)))
+        .filter(|(last_used, _)| *last_used < frame)
+        .collect();
+      candidates
+        .sort();
+      for (_, index) in candidates.into_iter() {
+        if self.bytes() <= self.budget {
+          break;
+        }
+        self.pages[index] = None;
+        self
+          .glyphs
+          .retain(|_, glyph| glyph.rect.is_none() || glyph.page != index);
+        if self.current == Some(index) {
+          self.current = None;
+        }
+        evicted += 1;
+      }
+    }
+    self.frame += 1;
+    evicted
+  }
+}
+
+
+impl<'ctx> GlyphAtlas<Texture<'ctx>> {
+  /// Render any of the text's glyphs that aren't in the atlas yet with the
+  /// measurer, which finds each one in the text's font or its fallbacks. A
+  /// glyph too big to fit on a page is reported and left with nothing to
+  /// draw.
+  pub fn add_glyphs(
+    &mut self,
+    tex_creator: &'ctx TextureCreator<WindowContext>,
+    measurer: &mut TextMeasurer,
+    text: &Text
+  ) -> Result<(), String> {
+    let key =
+      font_key(text);
+    if !self.line_heights.contains_key(&key) {
+      let metrics =
+        measurer.metrics(text)?;
+      self
+        .line_heights
+        .insert(key.clone(), metrics.line_height);
+    }
+    for c in text.text.chars() {
+      if let Some(glyph) = self.glyphs.get(&(key.clone(), c)).cloned() {
+        if glyph.rect.is_some() {
+          self.touch(glyph.page);
+        }
+        continue;
+      }
+      let image =
+        measurer.glyph(text, c)?;
+      let advance =
+        image.advance;
+      let glyph =
+        if image.width > 0 && image.height > 0 {
+          match self.allocate(image.width, image.height, || new_page(tex_creator)) {
+            Ok((page, rect)) => {
+              self
+                .pages[page]
+                .as_mut()
+                .expect("Impossible missing atlas page")
+                .texture
+                .update(rect, &image.pixels, image.width as usize * 4)
+                .map_err(|e| e.to_string())?;
+              Glyph {
+                page,
+                rect: Some(rect),
+                offset: (image.x, image.y),
+                advance
+              }
+            }
+            Err(e) => {
+              error!("Skipping glyph {:?} in {:?}: {}", c, key, e);
+              Glyph { page: 0, rect: None, offset: (0, 0), advance }
+            }
+          }
+        } else {
+          // Whitespace and glyphs the fonts don't have
+          Glyph { page: 0, rect: None, offset: (0, 0), advance }
+        };
+      self
+        .glyphs
+        .insert((key.clone(), c), glyph);
+    }
+    Ok(())
+  }
+
+  /// Draw the text scaled into the destination rectangle.
+  pub fn draw(&mut self, canvas: &mut WindowCanvas, text: &Text, dest: Rect) -> Result<(), String> {
+    let (tw, th) =
+      self.measure(text);
+    if tw == 0 || th == 0 {
+      return Ok(());
+    }
+    let scale_x =
+      dest.width() as f64 / tw as f64;
+    let scale_y =
+      dest.height() as f64 / th as f64;
+    let color =
+      &text.text_color;
+    self
+      .pages
+      .iter_mut()
+      .filter_map(|page| page.as_mut())
+      .for_each(|page| {
+        page.texture.set_color_mod(color.r, color.g, color.b);
+        page.texture.set_alpha_mod(color.a);
+      });
+
+    let key =
+      font_key(text);
+    let mut pen = 0;
+    for c in text.text.chars() {
+      let glyph =
+        match self.glyphs.get(&(key.clone(), c)) {
+          Some(glyph) => *glyph,
+          None => { continue; }
+        };
+      if let Some(rect) = glyph.rect {
+        let (dx, dy) =
+          glyph.offset;
+        let x =
+          dest.x() + ((pen + dx) as f64 * scale_x) as i32;
+        let y =
+          dest.y() + (dy as f64 * scale_y) as i32;
+        let w =
+          (rect.width() as f64 * scale_x).round() as u32;
+        let h =
+          (rect.height() as f64 * scale_y).round() as u32;
+        self.touch(glyph.page);
+        if let Some(page) = &self.pages[glyph.page] {
+          canvas
+            .copy(&page.texture, rect, Rect::new(x, y, w, h))?;
+        }
+      }
+      pen += glyph.advance;
+    }
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn page_bytes() -> usize {
+    (PAGE_SIZE * PAGE_SIZE * 4) as usize
+  }
+
+  /// An atlas without textures that has every glyph of `chars` at the given
+  /// advance and nothing to draw.
+  fn atlas_with(chars: &str, advance: i32) -> GlyphAtlas<()> {
+    let mut atlas =
+      GlyphAtlas::new(4 * page_bytes());
+    let key =
+      font_key(&Text::new(""));
+    for c in chars.chars() {
+      atlas
+        .glyphs
+        .insert((key.clone(), c), Glyph { page: 0, rect: None, offset: (0, 0), advance });
+    }
+    atlas
+      .line_heights
+      .insert(key, 20);
+    atlas
+  }
+
+  fn ellipsized(s: &str, overflow: TextOverflow, width: u32) -> String {
+    let atlas =
+      atlas_with("abcdefghijklmnopqrstuvwxyzH .", 10);
+    let mut text =
+      Text::new(s);
+    text.overflow = overflow;
+    let t =
+      atlas.ellipsize(&text, width);
+    assert!(atlas.measure(&t).0 <= width || t.text == ELLIPSIS);
+    t.text
+  }
+
+  #[test]
+  fn shelves_fill_rows_then_pages() {
+    let mut shelves =
+      Shelves::default();
+    assert_eq!(shelves.place(100, 10), Some(Rect::new(0, 0, 100, 10)));
+    assert_eq!(shelves.place(200, 20), Some(Rect::new(100, 0, 200, 20)));
+    // Too wide for the rest of the row, so it starts the next one below the
+    // row's tallest glyph
+    assert_eq!(shelves.place(300, 5), Some(Rect::new(0, 20, 300, 5)));
+    assert_eq!(shelves.place(212, 5), Some(Rect::new(300, 20, 212, 5)));
+    assert_eq!(shelves.place(10, 487), Some(Rect::new(0, 25, 10, 487)));
+    // The page is full
+    assert_eq!(shelves.place(10, 488), None);
+    assert_eq!(shelves.place(PAGE_SIZE + 1, 1), None);
+  }
+
+  #[test]
+  fn glyphs_are_allocated_across_pages() {
+    let mut atlas:GlyphAtlas<()> =
+      GlyphAtlas::new(4 * page_bytes());
+    assert_eq!(atlas.allocate(PAGE_SIZE, 300, || Ok(())), Ok((0, Rect::new(0, 0, PAGE_SIZE, 300))));
+    assert_eq!(atlas.allocate(10, 200, || Ok(())), Ok((0, Rect::new(0, 300, 10, 200))));
+    assert_eq!(atlas.allocate(10, 300, || Ok(())), Ok((1, Rect::new(0, 0, 10, 300))));
+    assert_eq!(atlas.page_count(), 2);
+    assert_eq!(atlas.bytes(), 2 * page_bytes());
+
+    assert!(atlas.allocate(PAGE_SIZE + 1, 10, || Ok(())).is_err());
+    assert!(atlas.allocate(PAGE_SIZE, PAGE_SIZE, || Err("No textures".to_string())).is_err());
+    assert_eq!(atlas.page_count(), 2);
+  }
+
+  #[test]
+  fn pages_are_evicted_past_the_budget_once_unused() {
+    let mut atlas:GlyphAtlas<()> =
+      GlyphAtlas::new(page_bytes());
+    let key =
+      font_key(&Text::new(""));
+    // A page each
+    for c in "ab".chars() {
+      let (page, rect) =
+        atlas
+        .allocate(PAGE_SIZE, PAGE_SIZE, || Ok(()))
+        .expect("Could not allocate");
+      atlas
+        .glyphs
+        .insert((key.clone(), c), Glyph { page, rect: Some(rect), offset: (0, 0), advance: 1 });
+    }
+    atlas
+      .glyphs
+      .insert((key.clone(), ' '), Glyph { page: 0, rect: None, offset: (0, 0), advance: 1 });
+
+    // Both pages were used this frame
+    assert_eq!(atlas.end_frame(), 0);
+    assert_eq!(atlas.page_count(), 2);
+
+    // Only the second page is used in the next frame, so the first goes
+    // along with its glyphs
+    atlas.touch(1);
+    assert_eq!(atlas.end_frame(), 1);
+    assert_eq!(atlas.page_count(), 1);
+    assert_eq!(atlas.glyph(&key, 'a'), None);
+    assert!(atlas.glyph(&key, 'b').is_some());
+    assert!(atlas.glyph(&key, ' ').is_some());
+
+    // Within budget nothing goes
+    assert_eq!(atlas.end_frame(), 0);
+    assert_eq!(atlas.page_count(), 1);
+
+    // The next page fills the hole
+    assert_eq!(atlas.allocate(10, 10, || Ok(())), Ok((0, Rect::new(0, 0, 10, 10))));
+  }
+
+  #[test]
+  fn text_is_measured_by_its_glyphs() {
+    let atlas =
+      atlas_with("Helo", 10);
+    assert_eq!(atlas.measure(&Text::new("Hello")), (50, 20));
+    // Glyphs that aren't in the atlas take up no room
+    assert_eq!(atlas.measure(&Text::new("Hello!")), (50, 20));
+    let mut other =
+      Text::new("Hello");
+    other.font_size += 1;
+    assert_eq!(atlas.measure(&other), (0, 0));
+  }
+
+  #[test]
+  fn text_is_ellipsized_to_fit() {
+    assert_eq!(ellipsized("hello world", TextOverflow::Ellipsis, 200), "hello world");
+    assert_eq!(ellipsized("hello world", TextOverflow::Ellipsis, 60), "hel...");
+    // Whitespace before the ellipsis doesn't count
+    assert_eq!(ellipsized("hello world", TextOverflow::Ellipsis, 90), "hello...");
+    assert_eq!(ellipsized("hello world", TextOverflow::EllipsisMiddle, 90), "hel...rld");
+    assert_eq!(ellipsized("hello world", TextOverflow::EllipsisMiddle, 100), "hell...rld");
+    assert_eq!(ellipsized("hello world", TextOverflow::EllipsisMiddle, 80), "hel...ld");
+    assert_eq!(ellipsized("ab cd ef", TextOverflow::EllipsisMiddle, 70), "ab...ef");
+    // If not even the ellipsis fits it's all that's left
+    assert_eq!(ellipsized("hello world", TextOverflow::Ellipsis, 10), ELLIPSIS);
+  }
+}
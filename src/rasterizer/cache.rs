@@ -0,0 +1,312 @@
+use sdl2::render::{Texture, TextureQuery};
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+
+/// 64MB, about 16 fullscreen 1080p textures.
+pub const DEFAULT_BUDGET: usize = 64 * 1024 * 1024;
+
+
+/// Counts of how a TextureCache is doing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  pub evictions: u64,
+  pub entries: usize,
+  pub bytes: usize,
+  pub budget: usize
+}
+
+
+impl CacheStats {
+  /// The fraction of lookups that found a texture, 0.0 to 1.0.
+  pub fn hit_rate(&self) -> f64 {
+    let lookups =
+      self.hits + self.misses;
+    if lookups == 0 {
+      0.0
+    } else {
+      self.hits as f64 / lookups as f64
+    }
+  }
+}
+
+
+/// Something a TextureCache can hold.
+pub trait Cacheable {
+  /// The estimated number of bytes of memory it holds.
+  fn bytes(&self) -> usize;
+}
+
+
+impl<'ctx> Cacheable for Texture<'ctx> {
+  fn bytes(&self) -> usize {
+    let TextureQuery{ width, height, ..} =
+      self.query();
+    // Assume four bytes a pixel, which is what we ask SDL for
+    width as usize * height as usize * 4
+  }
+}
+
+
+struct CacheEntry<V> {
+  texture: V,
+  bytes: usize,
+  last_used: u64
+}
+
+
+/// A texture cache that evicts the least recently used textures once it
+/// grows past its byte budget. A texture that has been used during the
+/// current frame is in use and is never evicted, even if that leaves the
+/// cache over budget.
+///
+/// The cache is generic over what it holds so the bookkeeping can be used
+/// without a renderer.
+pub struct TextureCache<K, V> {
+  entries: HashMap<K, CacheEntry<V>>,
+  budget: usize,
+  bytes: usize,
+  frame: u64,
+  hits: u64,
+  misses: u64,
+  evictions: u64
+}
+
+
+impl<K: Hash + Eq + Clone, V: Cacheable> TextureCache<K, V> {
+  pub fn new(budget: usize) -> TextureCache<K, V> {
+    TextureCache {
+      entries: HashMap::new(),
+      budget,
+      bytes: 0,
+      frame: 0,
+      hits: 0,
+      misses: 0,
+      evictions: 0
+    }
+  }
+
+  pub fn budget(&self) -> usize {
+    self.budget
+  }
+
+  pub fn set_budget(&mut self, budget: usize) {
+    self.budget = budget;
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// The estimated number of bytes of texture memory held.
+  pub fn bytes(&self) -> usize {
+    self.bytes
+  }
+
+  pub fn contains_key(&self, key: &K) -> bool {
+    self
+      .entries
+      .contains_key(key)
+  }
+
+  /// Mark a texture as in use this frame, counting the hit or miss.
+  /// Returns whether or not the cache has it.
+  pub fn touch(&mut self, key: &K) -> bool {
+    let frame =
+      self.frame;
+    match self.entries.get_mut(key) {
+      Some(entry) => {
+        self.hits += 1;
+        entry.last_used = frame;
+        true
+      }
+      None => {
+        self.misses += 1;
+        false
+      }
+    }
+  }
+
+  /// Look up a texture without touching it or the stats.
+  pub fn peek(&self, key: &K) -> Option<&V> {
+    self
+      .entries
+      .get(key)
+      .map(|entry| &entry.texture)
+  }
+
+  pub fn insert(&mut self, key: K, texture: V) {
+    let bytes =
+      texture.bytes();
+    let entry =
+      CacheEntry {
+        texture,
+        bytes,
+        last_used: self.frame
+      };
+    if let Some(old) = self.entries.insert(key, entry) {
+      self.bytes -= old.bytes;
+    }
+    self.bytes += bytes;
+  }
+
+  /// Evict least recently used textures that weren't used this frame until
+  /// the cache is within its budget, then start a new frame. Returns the
+  /// number of textures evicted.
+  pub fn end_frame(&mut self) -> usize {
+    let mut evicted = 0;
+    if self.bytes > self.budget {
+      let frame =
+        self.frame;
+      let mut candidates:Vec<(u64, K)> =
+        self
+        .entries
+        .iter()
+        .filter(|(_, entry)| entry.last_used < frame)
+        .map(|(key, entry)| (entry.last_used, key.clone()))
+        .collect();
+      candidates
+        .sort_by_key(|(last_used, _)| *last_used);
+      for (_, key) in candidates.into_iter() {
+        if self.bytes <= self.budget {
+          break;
+        }
+        if let Some(entry) = self.entries.remove(&key) {
+          self.bytes -= entry.bytes;
+          evicted += 1;
+        }
+      }
+    }
+    self.evictions += evicted as u64;
+    self.frame += 1;
+    evicted
+  }
+
+  /// Drop every texture.
+  pub fn clear(&mut self) {
+    self.entries.clear();
+    self.bytes = 0;
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits,
+      misses: self.misses,
+      evictions: self.evictions,
+      entries: self.entries.len(),
+      bytes: self.bytes,
+      budget: self.budget
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Stands in for a texture of the given number of bytes.
+  struct Fake(usize);
+
+  impl Cacheable for Fake {
+    fn bytes(&self) -> usize {
+      self.0
+    }
+  }
+
+  fn keys(cache: &TextureCache<&'static str, Fake>) -> Vec<&'static str> {
+    let mut keys:Vec<&'static str> =
+      cache
+      .entries
+      .keys()
+      .cloned()
+      .collect();
+    keys.sort();
+    keys
+  }
+
+  #[test]
+  fn hits_and_misses_are_counted() {
+    let mut cache =
+      TextureCache::new(100);
+    assert_eq!(cache.stats().hit_rate(), 0.0);
+    assert!(!cache.touch(&"a"));
+    cache.insert("a", Fake(10));
+    assert!(cache.touch(&"a"));
+    assert!(cache.touch(&"a"));
+    assert!(!cache.touch(&"b"));
+    // Peeking doesn't count
+    assert!(cache.peek(&"a").is_some());
+    assert!(cache.peek(&"b").is_none());
+
+    let stats =
+      cache.stats();
+    assert_eq!((stats.hits, stats.misses), (2, 2));
+    assert_eq!(stats.hit_rate(), 0.5);
+    assert_eq!((stats.entries, stats.bytes, stats.budget), (1, 10, 100));
+  }
+
+  #[test]
+  fn replacing_an_entry_replaces_its_bytes() {
+    let mut cache =
+      TextureCache::new(100);
+    cache.insert("a", Fake(10));
+    cache.insert("b", Fake(20));
+    cache.insert("a", Fake(40));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.bytes(), 60);
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(cache.bytes(), 0);
+  }
+
+  #[test]
+  fn least_recently_used_go_first() {
+    let mut cache =
+      TextureCache::new(30);
+    cache.insert("a", Fake(10));
+    cache.insert("b", Fake(10));
+    assert_eq!(cache.end_frame(), 0);
+
+    cache.insert("c", Fake(10));
+    assert_eq!(cache.end_frame(), 0);
+
+    // "a" is used more recently than "b", and "d" puts the cache over
+    cache.touch(&"a");
+    cache.insert("d", Fake(10));
+    assert_eq!(cache.end_frame(), 1);
+    assert_eq!(keys(&cache), vec!["a", "c", "d"]);
+
+    // Everything over budget that wasn't used this frame goes, oldest first
+    cache.set_budget(15);
+    cache.touch(&"d");
+    assert_eq!(cache.end_frame(), 2);
+    assert_eq!(keys(&cache), vec!["d"]);
+    assert_eq!(cache.stats().evictions, 3);
+  }
+
+  #[test]
+  fn textures_in_use_are_kept_over_budget() {
+    let mut cache =
+      TextureCache::new(10);
+    cache.insert("a", Fake(10));
+    cache.insert("b", Fake(10));
+    cache.insert("c", Fake(10));
+    // All of them were used this frame
+    assert_eq!(cache.end_frame(), 0);
+    assert_eq!(cache.bytes(), 30);
+
+    cache.touch(&"b");
+    cache.touch(&"c");
+    assert_eq!(cache.end_frame(), 1);
+    assert_eq!(keys(&cache), vec!["b", "c"]);
+    assert_eq!(cache.bytes(), 20);
+  }
+}
@@ -0,0 +1,345 @@
+//! Screens described in RON files.
+//!
+//! A screen is a list of named elements, each with optional text, an optional
+//! picture and constraints that refer to elements by name (or to the stage):
+//!
+//!```ron
+//! #![enable(implicit_some)]
+//! (
+//!   elements: [
+//!     (
+//!       name: "pic",
+//!       picture: [SetColor(255, 255, 0, 255), FillRect(0, 0, 100, 100)],
+//!       constraints: ["pic.left == 100", "pic.top == 100"],
+//!     ),
+//!     (
+//!       name: "label",
+//!       text: (text: "<- Look at this thing to the left!", color: (0, 0, 0, 255)),
+//!       constraints: [
+//!         "label.left == pic.right + 4",
+//!         "label.top == pic.bottom - 10 @ strong",
+//!       ],
+//!     ),
+//!   ],
+//! )
+//!```
+use serde::Deserialize;
+use specs::prelude::*;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::components::*;
+use super::picture::{Picture, PictureCmd};
+use super::UI;
+
+mod parse;
+mod watch;
+
+pub use parse::{parse_constraint, AnyConstraint};
+pub use watch::ScreenWatcher;
+
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TextDef {
+  pub text: String,
+  #[serde(default)]
+  pub font_path: Option<String>,
+  #[serde(default)]
+  pub font_size: Option<u16>,
+  #[serde(default)]
+  pub color: Option<(u8, u8, u8, u8)>,
+  #[serde(default)]
+  pub overflow: Option<TextOverflow>
+}
+
+
+impl TextDef {
+  pub fn to_text(&self) -> Text {
+    let mut text =
+      Text::new(&self.text);
+    self
+      .font_path
+      .iter()
+      .for_each(|path| text.font_path = path.clone());
+    self
+      .font_size
+      .iter()
+      .for_each(|size| text.font_size = *size);
+    self
+      .overflow
+      .iter()
+      .for_each(|overflow| text.overflow = *overflow);
+    match self.color {
+      Some((r, g, b, a)) => text.color(r, g, b, a),
+      None => text
+    }
+  }
+}
+
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ElementDef {
+  pub name: String,
+  #[serde(default)]
+  pub text: Option<TextDef>,
+  #[serde(default)]
+  pub picture: Option<Vec<PictureCmd>>,
+  #[serde(default)]
+  pub constraints: Vec<String>
+}
+
+
+/// Scratch solvers that a screen's constraints are tried in before they go
+/// anywhere near the live ones, so a file that contradicts itself can't take
+/// the layout down with it.
+struct ConstraintCheck {
+  xs: Solver<VariableX>,
+  ys: Solver<VariableY>,
+  zs: Solver<VariableZ>
+}
+
+
+impl ConstraintCheck {
+  fn new() -> ConstraintCheck {
+    ConstraintCheck {
+      xs: Solver::new(),
+      ys: Solver::new(),
+      zs: Solver::new()
+    }
+  }
+
+  /// Whether the constraint can be satisfied along with the ones admitted
+  /// before it.
+  fn admit(&mut self, c: &AnyConstraint) -> bool {
+    match c {
+      AnyConstraint::X(c) => self.xs.add_constraint(c.clone()).is_ok(),
+      AnyConstraint::Y(c) => self.ys.add_constraint(c.clone()).is_ok(),
+      AnyConstraint::Z(c) => self.zs.add_constraint(c.clone()).is_ok()
+    }
+  }
+}
+
+
+impl ElementDef {
+  /// Parse this element's constraints, sorted by axis. Constraints that
+  /// contradict the element's others are reported and left out.
+  pub fn constraints(&self, names: &HashMap<String, Entity>) -> Result<AxisConstraints, String> {
+    self.checked_constraints(names, &mut ConstraintCheck::new(), "screen")
+  }
+
+  fn checked_constraints(
+    &self,
+    names: &HashMap<String, Entity>,
+    check: &mut ConstraintCheck,
+    source: &str
+  ) -> Result<AxisConstraints, String> {
+    let mut cs =
+      AxisConstraints::new();
+    for s in self.constraints.iter() {
+      let c =
+        parse_constraint(s, names)
+        .map_err(|e| format!("{}: {:?}: {}", self.name, s, e))?;
+      if check.admit(&c) {
+        cs.push(c);
+      } else {
+        error!(
+          "{}: {}: skipping {:?}, it can't be satisfied along with the rest of the screen",
+          source,
+          self.name,
+          s
+        );
+      }
+    }
+    Ok(cs)
+  }
+
+  /// An ElementBuilder for this element, with its constraints resolved
+  /// against `names`.
+  pub fn builder(&self, names: &HashMap<String, Entity>) -> Result<ElementBuilder, String> {
+    Ok(self.builder_with(self.constraints(names)?))
+  }
+
+  fn builder_with(&self, cs: AxisConstraints) -> ElementBuilder {
+    let mut eb =
+      ElementBuilder::new()
+      .name(&self.name)
+      .constraints(cs);
+    if let Some(text) = &self.text {
+      eb = eb.text(&text.to_text());
+    }
+    if let Some(cmds) = &self.picture {
+      eb = eb.picture(&Picture(cmds.clone()));
+    }
+    eb
+  }
+}
+
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ScreenDef {
+  pub elements: Vec<ElementDef>,
+  /// The file the screen was loaded from, if any.
+  #[serde(skip)]
+  pub path: Option<PathBuf>
+}
+
+
+impl ScreenDef {
+  pub fn from_ron(s: &str) -> Result<ScreenDef, String> {
+    ron::de::from_str(s)
+      .map_err(|e| format!("Could not parse screen: {}", e))
+  }
+
+  pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ScreenDef, String> {
+    let s =
+      std::fs::read_to_string(path.as_ref())
+      .map_err(|e| format!("Could not read screen {:?}: {}", path.as_ref(), e))?;
+    let mut def =
+      ScreenDef::from_ron(&s)?;
+    def.path = Some(path.as_ref().to_path_buf());
+    Ok(def)
+  }
+
+  /// Parse the constraints of every element, in order. Constraints that
+  /// can't be satisfied along with the ones before them are reported against
+  /// the screen and element and left out, rather than being handed to the
+  /// layout solvers.
+  pub fn constraints(&self, names: &HashMap<String, Entity>) -> Result<Vec<AxisConstraints>, String> {
+    let source =
+      self
+      .path
+      .as_ref()
+      .map(|path| path.display().to_string())
+      .unwrap_or("screen".to_string());
+    let mut check =
+      ConstraintCheck::new();
+    self
+      .elements
+      .iter()
+      .map(|el| el.checked_constraints(names, &mut check, &source))
+      .collect()
+  }
+
+  /// Build the screen's elements, returning a map of element names to
+  /// entities. Nothing is left in the world if any constraint fails to parse.
+  pub fn build(&self, ui: &mut UI) -> Result<HashMap<String, Entity>, String> {
+    // Create every entity first so constraints can refer to elements that
+    // are defined further down the file.
+    let mut names:HashMap<String, Entity> =
+      HashMap::new();
+    for el in self.elements.iter() {
+      if el.name == "stage" || names.contains_key(&el.name) {
+        let _ =
+          ui.world
+          .delete_entities(&names.values().cloned().collect::<Vec<_>>());
+        return Err(format!("Element name {:?} is reserved or already taken", el.name));
+      }
+      let ent =
+        ElementBuilder::new()
+        .name(&el.name)
+        .build(ui);
+      names.insert(el.name.clone(), ent);
+    }
+
+    match self.constraints(&names) {
+      Ok(constraints) => {
+        self
+          .elements
+          .iter()
+          .zip(constraints.into_iter())
+          .for_each(|(el, cs)| el.builder_with(cs).update(ui, names[&el.name]));
+        Ok(names)
+      }
+      Err(e) => {
+        let _ =
+          ui.world
+          .delete_entities(&names.values().cloned().collect::<Vec<_>>());
+        Err(e)
+      }
+    }
+  }
+}
+
+
+/// Load a screen file and build its elements, returning a map of element
+/// names to entities.
+pub fn load_screen<P: AsRef<Path>>(ui: &mut UI, path: P) -> Result<HashMap<String, Entity>, String> {
+  ScreenDef::from_file(path)?
+    .build(ui)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn screens_parse_from_ron() {
+    let def =
+      ScreenDef::from_ron(r#"
+        #![enable(implicit_some)]
+        (
+          elements: [
+            (
+              name: "label",
+              text: (text: "hello", font_size: 24, overflow: Ellipsis),
+              constraints: ["label.left == 4"],
+            ),
+            (name: "spacer"),
+          ],
+        )
+      "#)
+      .expect("Could not parse screen");
+    assert_eq!(def.path, None);
+    assert_eq!(def.elements.len(), 2);
+    let label = &def.elements[0];
+    assert_eq!(label.name, "label");
+    assert_eq!(label.constraints, vec!["label.left == 4".to_string()]);
+    let text =
+      label
+      .text
+      .as_ref()
+      .expect("Label has no text")
+      .to_text();
+    assert_eq!(text.text, "hello");
+    assert_eq!(text.font_size, 24);
+    assert_eq!(text.overflow, TextOverflow::Ellipsis);
+    let spacer = &def.elements[1];
+    assert!(spacer.text.is_none() && spacer.picture.is_none() && spacer.constraints.is_empty());
+
+    assert!(ScreenDef::from_ron("(elements: [(text: \"no name\")])").is_err());
+  }
+
+  #[test]
+  fn element_constraints_resolve_names() {
+    let mut world = World::new();
+    let pic = world.create_entity().build();
+    let names:HashMap<String, Entity> =
+      vec![("pic".to_string(), pic)]
+      .into_iter()
+      .collect();
+    let el =
+      ElementDef {
+        name: "pic".to_string(),
+        text: None,
+        picture: None,
+        constraints: vec![
+          "pic.left == 0".to_string(),
+          "pic.top == stage.top + 10 @ weak".to_string(),
+          "pic.z == 1".to_string()
+        ]
+      };
+    let cs =
+      el.constraints(&names)
+      .expect("Could not resolve constraints");
+    assert_eq!((cs.x.len(), cs.y.len(), cs.z.len()), (1, 1, 1));
+
+    let unknown =
+      ElementDef {
+        constraints: vec!["pic.left == nope.right".to_string()],
+        ..el
+      };
+    assert!(unknown.constraints(&names).is_err());
+  }
+}
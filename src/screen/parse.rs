@@ -0,0 +1,648 @@
+use specs::prelude::Entity;
+use std::collections::HashMap;
+
+use super::super::components::*;
+
+
+/// A constraint parsed from a string, sorted into its axis.
+#[derive(Clone, Debug)]
+pub enum AnyConstraint {
+  X(Constraint<VariableX>),
+  Y(Constraint<VariableY>),
+  Z(Constraint<VariableZ>)
+}
+
+
+impl IntoAxisConstraints for AnyConstraint {
+  fn push_into(self, axes: &mut AxisConstraints) {
+    match self {
+      AnyConstraint::X(c) => axes.push(c),
+      AnyConstraint::Y(c) => axes.push(c),
+      AnyConstraint::Z(c) => axes.push(c)
+    }
+  }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Relation {
+  Equal,
+  GreaterOrEqual,
+  LessOrEqual
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Name(String),
+  Number(f64),
+  Dot,
+  Plus,
+  Minus,
+  Star,
+  Slash,
+  Open,
+  Close,
+  At,
+  Relation(Relation)
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Attribute {
+  Left,
+  Width,
+  Right,
+  CenterX,
+  Top,
+  Height,
+  Bottom,
+  CenterY,
+  Baseline,
+  ScrollX,
+  ScrollY,
+  Z
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Axis {
+  X,
+  Y,
+  Z
+}
+
+
+impl Attribute {
+  fn named(s: &str) -> Option<Attribute> {
+    match s {
+      "left" => Some(Attribute::Left),
+      "width" => Some(Attribute::Width),
+      "right" => Some(Attribute::Right),
+      "center_x" => Some(Attribute::CenterX),
+      "top" => Some(Attribute::Top),
+      "height" => Some(Attribute::Height),
+      "bottom" => Some(Attribute::Bottom),
+      "center_y" => Some(Attribute::CenterY),
+      "baseline" => Some(Attribute::Baseline),
+      "scroll_x" => Some(Attribute::ScrollX),
+      "scroll_y" => Some(Attribute::ScrollY),
+      "z" | "z_index" => Some(Attribute::Z),
+      _ => None
+    }
+  }
+
+  fn axis(&self) -> Axis {
+    match self {
+      Attribute::Left
+        | Attribute::Width
+        | Attribute::Right
+        | Attribute::CenterX
+        | Attribute::ScrollX => Axis::X,
+      Attribute::Top
+        | Attribute::Height
+        | Attribute::Bottom
+        | Attribute::CenterY
+        | Attribute::Baseline
+        | Attribute::ScrollY => Axis::Y,
+      Attribute::Z => Axis::Z
+    }
+  }
+}
+
+
+/// A sum of element attributes (an entity, or the stage when `None`) times
+/// their coefficients, plus a constant.
+#[derive(Clone, Debug)]
+struct Linear {
+  terms: Vec<(Option<Entity>, Attribute, f64)>,
+  constant: f64
+}
+
+
+impl Linear {
+  fn constant(constant: f64) -> Linear {
+    Linear {
+      terms: vec![],
+      constant
+    }
+  }
+
+  fn scale(self, k: f64) -> Linear {
+    Linear {
+      terms:
+        self
+        .terms
+        .into_iter()
+        .map(|(target, attribute, coefficient)| (target, attribute, coefficient * k))
+        .collect(),
+      constant: self.constant * k
+    }
+  }
+
+  fn add(self, other: Linear) -> Linear {
+    let mut lin = self;
+    lin.terms.extend(other.terms);
+    lin.constant += other.constant;
+    lin
+  }
+
+  fn multiply(self, other: Linear) -> Result<Linear, String> {
+    if self.terms.is_empty() {
+      Ok(other.scale(self.constant))
+    } else if other.terms.is_empty() {
+      Ok(self.scale(other.constant))
+    } else {
+      Err("Constraints must be linear, two attributes cannot be multiplied".to_string())
+    }
+  }
+
+  fn axis(&self) -> Result<Option<Axis>, String> {
+    self
+      .terms
+      .iter()
+      .try_fold(None, |may_axis, (_, attribute, _)| {
+        match may_axis {
+          Some(axis) if axis != attribute.axis() => {
+            Err("Constraint mixes attributes from different axes".to_string())
+          }
+          _ => Ok(Some(attribute.axis()))
+        }
+      })
+  }
+
+  fn x_expression(&self) -> Expression<VariableX> {
+    fn attribute_of<T: HasXConstraints>(t: &T, attribute: Attribute) -> Expression<VariableX> {
+      match attribute {
+        Attribute::Left => t.left(),
+        Attribute::Width => t.width(),
+        Attribute::Right => t.right(),
+        Attribute::CenterX => t.center_x(),
+        Attribute::ScrollX => t.scroll_x(),
+        _ => unreachable!("Attribute {:?} is not on the x axis", attribute)
+      }
+    }
+    self
+      .terms
+      .iter()
+      .fold(
+        self.constant.into(),
+        |expr, (target, attribute, coefficient)| {
+          let term =
+            match target {
+              Some(ent) => attribute_of(ent, *attribute),
+              None => attribute_of(&Stage, *attribute)
+            };
+          expr + term * *coefficient
+        }
+      )
+  }
+
+  fn y_expression(&self) -> Expression<VariableY> {
+    fn attribute_of<T: HasYConstraints>(t: &T, attribute: Attribute) -> Expression<VariableY> {
+      match attribute {
+        Attribute::Top => t.top(),
+        Attribute::Height => t.height(),
+        Attribute::Bottom => t.bottom(),
+        Attribute::CenterY => t.center_y(),
+        Attribute::Baseline => t.baseline(),
+        Attribute::ScrollY => t.scroll_y(),
+        _ => unreachable!("Attribute {:?} is not on the y axis", attribute)
+      }
+    }
+    self
+      .terms
+      .iter()
+      .fold(
+        self.constant.into(),
+        |expr, (target, attribute, coefficient)| {
+          let term =
+            match target {
+              Some(ent) => attribute_of(ent, *attribute),
+              None => attribute_of(&Stage, *attribute)
+            };
+          expr + term * *coefficient
+        }
+      )
+  }
+
+  fn z_expression(&self) -> Expression<VariableZ> {
+    self
+      .terms
+      .iter()
+      .fold(
+        self.constant.into(),
+        |expr, (target, _, coefficient)| {
+          let ent =
+            target
+            .expect("The stage has no z index");
+          expr + ent.z_index() * *coefficient
+        }
+      )
+  }
+}
+
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+  let mut tokens = vec![];
+  let mut chars =
+    s
+    .chars()
+    .peekable();
+  while let Some(c) = chars.next() {
+    let token =
+      match c {
+        ' ' | '\t' | '\n' | '\r' => { continue; }
+        '.' => Token::Dot,
+        '+' => Token::Plus,
+        '-' => Token::Minus,
+        '*' => Token::Star,
+        '/' => Token::Slash,
+        '(' => Token::Open,
+        ')' => Token::Close,
+        '@' => Token::At,
+        '=' | '>' | '<' => {
+          if chars.next() != Some('=') {
+            return Err(format!("Expected '=' after '{}'", c));
+          }
+          Token::Relation(
+            match c {
+              '=' => Relation::Equal,
+              '>' => Relation::GreaterOrEqual,
+              _ => Relation::LessOrEqual
+            }
+          )
+        }
+        '"' => {
+          let mut name = String::new();
+          loop {
+            match chars.next() {
+              Some('"') => { break; }
+              Some(c) => { name.push(c); }
+              None => { return Err("Unterminated quoted name".to_string()); }
+            }
+          }
+          Token::Name(name)
+        }
+        c if c.is_ascii_digit() => {
+          let mut number = c.to_string();
+          while let Some(c) = chars.peek().cloned() {
+            if c.is_ascii_digit() || c == '.' {
+              number.push(c);
+              chars.next();
+            } else {
+              break;
+            }
+          }
+          Token::Number(
+            number
+              .parse()
+              .map_err(|_| format!("Could not parse number {:?}", number))?
+          )
+        }
+        c if c.is_alphabetic() || c == '_' => {
+          let mut name = c.to_string();
+          while let Some(c) = chars.peek().cloned() {
+            if c.is_alphanumeric() || c == '_' {
+              name.push(c);
+              chars.next();
+            } else {
+              break;
+            }
+          }
+          Token::Name(name)
+        }
+        c => { return Err(format!("Unexpected character {:?}", c)); }
+      };
+    tokens.push(token);
+  }
+  Ok(tokens)
+}
+
+
+struct Parser<'a> {
+  tokens: Vec<Token>,
+  pos: usize,
+  names: &'a HashMap<String, Entity>
+}
+
+
+impl<'a> Parser<'a> {
+  fn peek(&self) -> Option<&Token> {
+    self
+      .tokens
+      .get(self.pos)
+  }
+
+  fn advance(&mut self) -> Option<Token> {
+    let may_token =
+      self
+      .tokens
+      .get(self.pos)
+      .cloned();
+    self.pos += 1;
+    may_token
+  }
+
+  fn expression(&mut self) -> Result<Linear, String> {
+    let mut lin =
+      self.product()?;
+    loop {
+      match self.peek() {
+        Some(Token::Plus) => {
+          self.pos += 1;
+          lin = lin.add(self.product()?);
+        }
+        Some(Token::Minus) => {
+          self.pos += 1;
+          lin = lin.add(self.product()?.scale(-1.0));
+        }
+        _ => { return Ok(lin); }
+      }
+    }
+  }
+
+  fn product(&mut self) -> Result<Linear, String> {
+    let mut lin =
+      self.factor()?;
+    loop {
+      match self.peek() {
+        Some(Token::Star) => {
+          self.pos += 1;
+          lin = lin.multiply(self.factor()?)?;
+        }
+        Some(Token::Slash) => {
+          self.pos += 1;
+          let divisor =
+            self.factor()?;
+          if !divisor.terms.is_empty() {
+            return Err("Constraints must be linear, cannot divide by an attribute".to_string());
+          }
+          if divisor.constant == 0.0 {
+            return Err("Division by zero".to_string());
+          }
+          lin = lin.scale(1.0 / divisor.constant);
+        }
+        _ => { return Ok(lin); }
+      }
+    }
+  }
+
+  fn factor(&mut self) -> Result<Linear, String> {
+    match self.advance() {
+      Some(Token::Number(n)) => Ok(Linear::constant(n)),
+      Some(Token::Minus) => Ok(self.factor()?.scale(-1.0)),
+      Some(Token::Open) => {
+        let lin =
+          self.expression()?;
+        match self.advance() {
+          Some(Token::Close) => Ok(lin),
+          _ => Err("Expected ')'".to_string())
+        }
+      }
+      Some(Token::Name(name)) => {
+        if self.advance() != Some(Token::Dot) {
+          return Err(format!("Expected '.' and an attribute after {:?}", name));
+        }
+        let attribute =
+          match self.advance() {
+            Some(Token::Name(attr)) => {
+              Attribute::named(&attr)
+                .ok_or(format!("Unknown attribute {:?}", attr))?
+            }
+            _ => { return Err(format!("Expected an attribute after {:?}", name)); }
+          };
+        let target =
+          if name == "stage" {
+            if attribute == Attribute::Z {
+              return Err("The stage has no z index".to_string());
+            }
+            None
+          } else {
+            Some(
+              *self
+                .names
+                .get(&name)
+                .ok_or(format!("Unknown element {:?}", name))?
+            )
+          };
+        Ok(
+          Linear {
+            terms: vec![(target, attribute, 1.0)],
+            constant: 0.0
+          }
+        )
+      }
+      Some(token) => Err(format!("Unexpected {:?}", token)),
+      None => Err("Unexpected end of constraint".to_string())
+    }
+  }
+}
+
+
+fn strength_from_str(s: &str) -> Result<f64, String> {
+  match s {
+    "weak" => Ok(strength::WEAK),
+    "medium" => Ok(strength::MEDIUM),
+    "strong" => Ok(strength::STRONG),
+    "required" => Ok(strength::REQUIRED),
+    _ => Err(format!("Unknown strength {:?}", s))
+  }
+}
+
+
+macro_rules! relate {
+  ($lhs:expr, $relation:expr, $rhs:expr) => {
+    match $relation {
+      Relation::Equal => $lhs.is($rhs),
+      Relation::GreaterOrEqual => $lhs.is_ge($rhs),
+      Relation::LessOrEqual => $lhs.is_le($rhs)
+    }
+  }
+}
+
+
+/// Parse a constraint like `label.left == pic.right + 4 @ weak`, where names
+/// are looked up in `names` and `stage` refers to the stage. Attributes are
+/// left, width, right, center_x, top, height, bottom, center_y, baseline
+/// and z.
+pub fn parse_constraint(s: &str, names: &HashMap<String, Entity>) -> Result<AnyConstraint, String> {
+  let mut parser =
+    Parser {
+      tokens: tokenize(s)?,
+      pos: 0,
+      names
+    };
+  let lhs =
+    parser.expression()?;
+  let relation =
+    match parser.advance() {
+      Some(Token::Relation(relation)) => relation,
+      _ => { return Err("Expected '==', '>=' or '<='".to_string()); }
+    };
+  let rhs =
+    parser.expression()?;
+  let strength =
+    if parser.peek() == Some(&Token::At) {
+      parser.pos += 1;
+      match parser.advance() {
+        Some(Token::Name(name)) => strength_from_str(&name)?,
+        Some(Token::Number(n)) => n,
+        _ => { return Err("Expected a strength after '@'".to_string()); }
+      }
+    } else {
+      strength::REQUIRED
+    };
+  if let Some(token) = parser.peek() {
+    return Err(format!("Unexpected {:?} at the end of the constraint", token));
+  }
+
+  let axis =
+    match (lhs.axis()?, rhs.axis()?) {
+      (Some(a), Some(b)) if a != b => {
+        return Err("Constraint mixes attributes from different axes".to_string());
+      }
+      (Some(axis), _) | (None, Some(axis)) => axis,
+      (None, None) => {
+        return Err("Constraint does not mention any attributes".to_string());
+      }
+    };
+
+  Ok(
+    match axis {
+      Axis::X => {
+        AnyConstraint::X(
+          relate!(lhs.x_expression(), relation, rhs.x_expression())
+            .with_strength(strength)
+        )
+      }
+      Axis::Y => {
+        AnyConstraint::Y(
+          relate!(lhs.y_expression(), relation, rhs.y_expression())
+            .with_strength(strength)
+        )
+      }
+      Axis::Z => {
+        AnyConstraint::Z(
+          relate!(lhs.z_expression(), relation, rhs.z_expression())
+            .with_strength(strength)
+        )
+      }
+    }
+  )
+}
+
+
+#[cfg(test)]
+mod tests {
+  use specs::prelude::*;
+
+  use super::*;
+
+  fn names_of(world: &mut World, names: &[&str]) -> HashMap<String, Entity> {
+    names
+      .iter()
+      .map(|name| (name.to_string(), world.create_entity().build()))
+      .collect()
+  }
+
+  /// The coefficient of a variable in an expression, summing repeats.
+  fn coefficient<T: PartialEq>(expr: &Expression<T>, variable: &T) -> f64 {
+    expr
+      .terms
+      .iter()
+      .filter(|term| term.variable == *variable)
+      .map(|term| term.coefficient)
+      .sum()
+  }
+
+  #[test]
+  fn tokenizes_names_numbers_and_relations() {
+    assert_eq!(
+      tokenize("a.left >= \"my pic\".right - 4.5 @ weak"),
+      Ok(vec![
+        Token::Name("a".to_string()),
+        Token::Dot,
+        Token::Name("left".to_string()),
+        Token::Relation(Relation::GreaterOrEqual),
+        Token::Name("my pic".to_string()),
+        Token::Dot,
+        Token::Name("right".to_string()),
+        Token::Minus,
+        Token::Number(4.5),
+        Token::At,
+        Token::Name("weak".to_string())
+      ])
+    );
+    assert!(tokenize("a.left = 4").is_err());
+    assert!(tokenize("a.left == \"oops").is_err());
+    assert!(tokenize("a.left == 4 $").is_err());
+  }
+
+  #[test]
+  fn parses_x_constraints() {
+    let mut world = World::new();
+    let names = names_of(&mut world, &["a", "b"]);
+    let (a, b) = (names["a"], names["b"]);
+    match parse_constraint("a.left == b.right + 4", &names) {
+      Ok(AnyConstraint::X(c)) => {
+        assert_eq!(c.op(), RelationalOperator::Equal);
+        assert_eq!(c.strength(), strength::REQUIRED);
+        let expr = c.expr();
+        assert_eq!(coefficient(expr, &VariableX::Left(Some(a))), 1.0);
+        assert_eq!(coefficient(expr, &VariableX::Left(Some(b))), -1.0);
+        assert_eq!(coefficient(expr, &VariableX::Width(Some(b))), -1.0);
+        assert_eq!(expr.constant, -4.0);
+      }
+      other => panic!("Expected an x constraint, got {:?}", other)
+    }
+  }
+
+  #[test]
+  fn parses_strengths_stage_and_arithmetic() {
+    let mut world = World::new();
+    let names = names_of(&mut world, &["a"]);
+    let a = names["a"];
+    match parse_constraint("a.height * 2 <= (stage.height - 10) / 2 @ strong", &names) {
+      Ok(AnyConstraint::Y(c)) => {
+        assert_eq!(c.op(), RelationalOperator::LessOrEqual);
+        assert_eq!(c.strength(), strength::STRONG);
+        let expr = c.expr();
+        assert_eq!(coefficient(expr, &VariableY::Height(Some(a))), 2.0);
+        assert_eq!(coefficient(expr, &VariableY::Height(None)), -0.5);
+        assert_eq!(expr.constant, 5.0);
+      }
+      other => panic!("Expected a y constraint, got {:?}", other)
+    }
+    match parse_constraint("a.z == 3 @ 12", &names) {
+      Ok(AnyConstraint::Z(c)) => {
+        assert_eq!(c.strength(), 12.0);
+        assert_eq!(coefficient(c.expr(), &VariableZ(a)), 1.0);
+      }
+      other => panic!("Expected a z constraint, got {:?}", other)
+    }
+  }
+
+  #[test]
+  fn rejects_bad_constraints() {
+    let mut world = World::new();
+    let names = names_of(&mut world, &["a", "b"]);
+    let bad = [
+      "a.left == b.top",
+      "a.width * b.width == 4",
+      "a.width / b.width == 4",
+      "a.width / 0 == 4",
+      "a.left == c.left",
+      "a.middle == 4",
+      "a.left 4",
+      "a.left == 4 @ mighty",
+      "a.left == 4 4",
+      "4 == 5",
+      "stage.z == a.z",
+      "a.left == (b.left"
+    ];
+    for s in bad.iter() {
+      assert!(parse_constraint(s, &names).is_err(), "{:?} should not parse", s);
+    }
+  }
+}
@@ -0,0 +1,309 @@
+use specs::prelude::*;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::super::components::*;
+use super::super::picture::Picture;
+use super::super::UI;
+use super::{ElementDef, ScreenDef};
+
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+  std::fs::metadata(path)
+    .and_then(|meta| meta.modified())
+    .ok()
+}
+
+
+fn rollback(ui: &mut UI, created: &[Entity]) {
+  let _ =
+    ui.world
+    .delete_entities(created);
+}
+
+
+/// A screen loaded from a file that is reloaded when the file changes.
+/// Reloading updates the live world in place - elements keep their entities
+/// for as long as their names stay in the file, so game code holding on to
+/// those entities keeps working.
+pub struct ScreenWatcher {
+  path: PathBuf,
+  modified: Option<SystemTime>,
+  /// What the file held the last time it failed to load, while it stays
+  /// broken.
+  broken: Option<String>,
+  def: ScreenDef,
+  names: HashMap<String, Entity>
+}
+
+
+impl ScreenWatcher {
+  /// Load and build the screen at the given path and start watching it.
+  pub fn new<P: AsRef<Path>>(ui: &mut UI, path: P) -> Result<ScreenWatcher, String> {
+    let path =
+      path
+      .as_ref()
+      .to_path_buf();
+    let modified =
+      modified_time(&path);
+    let def =
+      ScreenDef::from_file(&path)?;
+    let names =
+      def.build(ui)?;
+    Ok(
+      ScreenWatcher {
+        path,
+        modified,
+        broken: None,
+        def,
+        names
+      }
+    )
+  }
+
+  /// The map of element names to entities.
+  pub fn names(&self) -> &HashMap<String, Entity> {
+    &self.names
+  }
+
+  pub fn get(&self, name: &str) -> Option<Entity> {
+    self
+      .names
+      .get(name)
+      .cloned()
+  }
+
+  /// Reload the screen if its file has changed since the last look.
+  /// Call this once a frame. Returns whether or not the screen was reloaded.
+  /// If the new definition has errors the live screen is left as it was, and
+  /// the file is tried again as soon as it changes - editors often save in
+  /// more than one write, so a half written file may be fine a moment later.
+  /// A broken file is read again on every poll, since a second write can
+  /// land within the same modified time, but each broken version is only
+  /// reported once.
+  pub fn poll(&mut self, ui: &mut UI) -> Result<bool, String> {
+    let modified =
+      modified_time(&self.path);
+    if modified == self.modified && self.broken.is_none() {
+      return Ok(false);
+    }
+    self.modified = modified;
+    let s =
+      std::fs::read_to_string(&self.path)
+      .map_err(|e| format!("Could not read screen {:?}: {}", self.path, e))?;
+    if self.broken.as_ref() == Some(&s) {
+      return Ok(false);
+    }
+    let result =
+      ScreenDef::from_ron(&s)
+      .and_then(|mut def| {
+        def.path = Some(self.path.clone());
+        self.apply(ui, def)
+      });
+    match result {
+      Ok(()) => {
+        self.broken = None;
+        Ok(true)
+      }
+      Err(e) => {
+        self.broken = Some(s);
+        Err(e)
+      }
+    }
+  }
+
+  /// Diff the given definition against the live screen and update the world
+  /// to match it.
+  pub fn apply(&mut self, ui: &mut UI, def: ScreenDef) -> Result<(), String> {
+    let mut names:HashMap<String, Entity> =
+      HashMap::new();
+    let mut created:Vec<Entity> =
+      vec![];
+
+    for el in def.elements.iter() {
+      if el.name == "stage" || names.contains_key(&el.name) {
+        rollback(ui, &created);
+        return Err(format!("Element name {:?} is reserved or already taken", el.name));
+      }
+      let ent =
+        match self.names.get(&el.name) {
+          Some(ent) => *ent,
+          None => {
+            let ent =
+              ElementBuilder::new()
+              .name(&el.name)
+              .build(ui);
+            created.push(ent);
+            ent
+          }
+        };
+      names.insert(el.name.clone(), ent);
+    }
+
+    // Resolve everything before touching the world
+    let resolved =
+      match def.constraints(&names) {
+        Ok(resolved) => resolved,
+        Err(e) => {
+          rollback(ui, &created);
+          return Err(e);
+        }
+      };
+
+    for (el, AxisConstraints{ x, y, z }) in def.elements.iter().zip(resolved.into_iter()) {
+      let ent =
+        names[&el.name];
+      let may_old:Option<&ElementDef> =
+        self
+        .def
+        .elements
+        .iter()
+        .find(|old| old.name == el.name);
+      let is_new =
+        may_old.is_none();
+
+      if is_new || may_old.map(|old| &old.text) != Some(&el.text) {
+        ui.update(ent, el.text.as_ref().map(|text| text.to_text()));
+        ui.update::<ContentSize>(ent, None);
+      }
+
+      if is_new || may_old.map(|old| &old.picture) != Some(&el.picture) {
+        ui.update(ent, el.picture.clone().map(Picture));
+        ui.update::<ContentSize>(ent, None);
+      }
+
+      if is_new || may_old.map(|old| &old.constraints) != Some(&el.constraints) {
+        ui.update(ent, Some(x).filter(|xs| !xs.is_empty()).map(Constraints));
+        ui.update(ent, Some(y).filter(|ys| !ys.is_empty()).map(Constraints));
+        ui.update(ent, Some(z).filter(|zs| !zs.is_empty()).map(Constraints));
+      }
+    }
+
+    let removed:Vec<Entity> =
+      self
+      .names
+      .iter()
+      .filter(|(name, _)| !names.contains_key(*name))
+      .map(|(_, ent)| *ent)
+      .collect();
+    let _ =
+      ui.world
+      .delete_entities(&removed);
+
+    self.def = def;
+    self.names = names;
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::parse_constraint;
+  use super::super::AnyConstraint;
+
+  fn def(s: &str) -> ScreenDef {
+    ScreenDef::from_ron(s)
+      .expect("Could not parse screen")
+  }
+
+  fn watch(ui: &mut UI, def: ScreenDef) -> ScreenWatcher {
+    let names =
+      def.build(ui)
+      .expect("Could not build screen");
+    ScreenWatcher {
+      path: PathBuf::new(),
+      modified: None,
+      broken: None,
+      def,
+      names
+    }
+  }
+
+  fn first() -> ScreenDef {
+    def(r#"
+      #![enable(implicit_some)]
+      (
+        elements: [
+          (name: "a", text: (text: "hi"), constraints: ["a.left == 0"]),
+          (name: "b", picture: [FillRect(0, 0, 10, 10)], constraints: ["b.left == 0"]),
+          (name: "c", constraints: ["c.left == 0"]),
+        ],
+      )
+    "#)
+  }
+
+  #[test]
+  fn applying_a_screen_updates_it_in_place() {
+    let mut ui = UI::new();
+    let mut watcher = watch(&mut ui, first());
+    let a = watcher.get("a").unwrap();
+    let b = watcher.get("b").unwrap();
+    let c = watcher.get("c").unwrap();
+
+    watcher
+      .apply(&mut ui, def(r#"
+        #![enable(implicit_some)]
+        (
+          elements: [
+            (name: "a", text: (text: "bye"), constraints: ["a.left == 0"]),
+            (name: "b", picture: [FillRect(0, 0, 10, 10)], constraints: ["b.left == a.right"]),
+            (name: "d", constraints: ["d.top == b.bottom"]),
+          ],
+        )
+      "#))
+      .expect("Could not apply screen");
+
+    // Elements that stay keep their entities
+    assert_eq!(watcher.get("a"), Some(a));
+    assert_eq!(watcher.get("b"), Some(b));
+    assert_eq!(ui.get::<Text>(a).map(|text| text.text), Some("bye".to_string()));
+    assert!(ui.get::<Picture>(b).is_some());
+    let b_left =
+      match parse_constraint("b.left == a.right", watcher.names()) {
+        Ok(AnyConstraint::X(c)) => c,
+        _ => panic!("Could not parse b's constraint")
+      };
+    assert_eq!(ui.get::<Constraints<VariableX>>(b).map(|xs| xs.0), Some(vec![b_left]));
+
+    // Elements that are gone are deleted and new ones are built
+    assert!(!ui.world.is_alive(c));
+    assert_eq!(watcher.get("c"), None);
+    let d = watcher.get("d").expect("New element was not built");
+    assert!(ui.world.is_alive(d));
+    assert_eq!(ui.get::<Name>(d).map(|Name(name)| name), Some("d".to_string()));
+    assert!(ui.get::<Constraints<VariableY>>(d).is_some());
+    assert_eq!(watcher.names().len(), 3);
+  }
+
+  #[test]
+  fn broken_screens_leave_the_live_one_alone() {
+    let mut ui = UI::new();
+    let mut watcher = watch(&mut ui, first());
+    let names = watcher.names().clone();
+
+    let result =
+      watcher
+      .apply(&mut ui, def(r#"
+        (
+          elements: [
+            (name: "a", constraints: ["a.left == 0"]),
+            (name: "e", constraints: ["e.left == nope.right"]),
+          ],
+        )
+      "#));
+    assert!(result.is_err());
+    assert_eq!(watcher.names(), &names);
+    assert!(names.values().all(|ent| ui.world.is_alive(*ent)));
+    assert_eq!(watcher.def, first());
+    // The entity built for the new element is rolled back
+    let rolled_back =
+      (&ui.world.entities(), &ui.world.read_storage::<Name>())
+      .join()
+      .any(|(_, Name(name))| name == "e");
+    assert!(!rolled_back);
+  }
+}
@@ -0,0 +1,544 @@
+//! CSS-like selectors for finding entities.
+//!
+//! * `Button`, `Text`, `Picture`, `Container` - entities with that component,
+//!   `*` matches anything. Any other bare word matches an entity by name, so
+//!   `menu` is the same as `#menu`.
+//! * `#label`, `#'Button label'` - entities with that Name.
+//! * `[text='Start']` - attribute tests. Attributes are `name`, `text` (an
+//!   entity's Text, or a button's label), `x`, `y`, `z`, `width` and
+//!   `height`. The operators are `=`, `*=` (contains), `^=` (starts with)
+//!   and `$=` (ends with); `[text]` tests for presence.
+//! * `:hover`, `:pressed`, `:visible`, `:invisible` - state.
+//! * `a > b` matches a `b` whose Parent matches `a`, `a b` matches a `b` with
+//!   any ancestor matching `a`, and `a, b` matches either.
+use specs::prelude::*;
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::components::*;
+use super::picture::Picture;
+use super::stack::Container;
+use super::systems::button::Button;
+use super::systems::event::Mouse;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Combinator {
+  Child,
+  Descendant
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AttributeOp {
+  Equals,
+  Contains,
+  StartsWith,
+  EndsWith
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+struct AttributeTest {
+  attribute: String,
+  may_op: Option<(AttributeOp, String)>
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pseudo {
+  Hover,
+  Pressed,
+  Visible,
+  Invisible
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+struct Compound {
+  kind: Option<String>,
+  name: Option<String>,
+  attributes: Vec<AttributeTest>,
+  pseudos: Vec<Pseudo>
+}
+
+
+/// A parsed selector, see the module docs for the syntax.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selector {
+  // Each alternative is a chain of compounds, each paired with how it
+  // relates to the compound before it.
+  alternatives: Vec<Vec<(Combinator, Compound)>>
+}
+
+
+fn parent_of(world: &World, ent: Entity) -> Option<Entity> {
+  world
+    .read_storage::<Parent>()
+    .get(ent)
+    .map(|Parent(parent)| *parent)
+}
+
+
+fn attribute_value(world: &World, ent: Entity, attribute: &str) -> Option<String> {
+  let element_box = || {
+    world
+      .read_storage::<ElementBox>()
+      .get(ent)
+      .cloned()
+  };
+  match attribute {
+    "name" => {
+      world
+        .read_storage::<Name>()
+        .get(ent)
+        .map(|Name(n)| n.clone())
+    }
+    "text" => {
+      world
+        .read_storage::<Text>()
+        .get(ent)
+        .map(|t| t.text.clone())
+        .or(
+          world
+            .read_storage::<Button>()
+            .get(ent)
+            .map(|b| b.text().to_string())
+        )
+    }
+    "x" => element_box().map(|el| el.x.to_string()),
+    "y" => element_box().map(|el| el.y.to_string()),
+    "z" => element_box().map(|el| el.z.to_string()),
+    "width" => element_box().map(|el| el.width.to_string()),
+    "height" => element_box().map(|el| el.height.to_string()),
+    _ => None
+  }
+}
+
+
+impl Compound {
+  fn new() -> Compound {
+    Compound {
+      kind: None,
+      name: None,
+      attributes: vec![],
+      pseudos: vec![]
+    }
+  }
+
+  fn is_empty(&self) -> bool {
+    *self == Compound::new()
+  }
+
+  fn matches(&self, world: &World, ent: Entity) -> bool {
+    let kind_matches =
+      match self.kind.as_deref() {
+        None | Some("*") => true,
+        Some("Button") => world.read_storage::<Button>().contains(ent),
+        Some("Text") => world.read_storage::<Text>().contains(ent),
+        Some("Picture") => world.read_storage::<Picture>().contains(ent),
+        Some("Container") => world.read_storage::<Container>().contains(ent),
+        Some(name) => attribute_value(world, ent, "name").as_deref() == Some(name)
+      };
+    let name_matches =
+      self
+      .name
+      .as_ref()
+      .map(|name| attribute_value(world, ent, "name").as_ref() == Some(name))
+      .unwrap_or(true);
+    let attributes_match =
+      self
+      .attributes
+      .iter()
+      .all(|test| {
+        let may_value =
+          attribute_value(world, ent, &test.attribute);
+        match (&test.may_op, may_value) {
+          (None, may_value) => may_value.is_some(),
+          (Some(_), None) => false,
+          (Some((op, expected)), Some(value)) => {
+            match op {
+              AttributeOp::Equals => value == *expected,
+              AttributeOp::Contains => value.contains(expected.as_str()),
+              AttributeOp::StartsWith => value.starts_with(expected.as_str()),
+              AttributeOp::EndsWith => value.ends_with(expected.as_str())
+            }
+          }
+        }
+      });
+    let pseudos_match =
+      self
+      .pseudos
+      .iter()
+      .all(|pseudo| {
+        // Like the EventSystem, only the part of the box that isn't clipped
+        // away counts
+        let is_over = || {
+          let mouse =
+            world
+            .read_resource::<Mouse>();
+          let element_boxes =
+            world
+            .read_storage::<ElementBox>();
+          let is_in_clip =
+            clip_box(
+              ent,
+              &world.read_storage::<Parent>(),
+              &world.read_storage::<ClipChildren>(),
+              &element_boxes
+            )
+            .map(|clip| clip.contains(mouse.x, mouse.y))
+            .unwrap_or(true);
+          element_boxes
+            .get(ent)
+            .map(|el| el.contains(mouse.x, mouse.y) && is_in_clip)
+            .unwrap_or(false)
+        };
+        match pseudo {
+          Pseudo::Hover => is_over(),
+          Pseudo::Pressed => is_over() && world.read_resource::<Mouse>().left_btn_down,
+          Pseudo::Visible => !world.read_storage::<Invisible>().contains(ent),
+          Pseudo::Invisible => world.read_storage::<Invisible>().contains(ent)
+        }
+      });
+
+    kind_matches && name_matches && attributes_match && pseudos_match
+  }
+}
+
+
+fn matches_chain(chain: &[(Combinator, Compound)], world: &World, ent: Entity) -> bool {
+  let ((combinator, compound), rest) =
+    match chain.split_last() {
+      Some(split) => split,
+      None => { return true; }
+    };
+  if !compound.matches(world, ent) {
+    return false;
+  }
+  if rest.is_empty() {
+    return true;
+  }
+  match combinator {
+    Combinator::Child => {
+      parent_of(world, ent)
+        .map(|parent| matches_chain(rest, world, parent))
+        .unwrap_or(false)
+    }
+    Combinator::Descendant => {
+      let mut may_ancestor =
+        parent_of(world, ent);
+      while let Some(ancestor) = may_ancestor {
+        if matches_chain(rest, world, ancestor) {
+          return true;
+        }
+        may_ancestor = parent_of(world, ancestor);
+      }
+      false
+    }
+  }
+}
+
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) -> bool {
+  let mut skipped = false;
+  while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+    chars.next();
+    skipped = true;
+  }
+  skipped
+}
+
+
+fn parse_word(chars: &mut Peekable<Chars>) -> Result<String, String> {
+  match chars.peek().cloned() {
+    Some(quote) if quote == '\'' || quote == '"' => {
+      chars.next();
+      let mut word = String::new();
+      loop {
+        match chars.next() {
+          Some(c) if c == quote => { return Ok(word); }
+          Some(c) => { word.push(c); }
+          None => { return Err("Unterminated quoted string".to_string()); }
+        }
+      }
+    }
+    _ => {
+      let mut word = String::new();
+      while let Some(c) = chars.peek().cloned() {
+        if c.is_alphanumeric() || c == '_' || c == '-' {
+          word.push(c);
+          chars.next();
+        } else {
+          break;
+        }
+      }
+      if word.is_empty() {
+        Err(
+          chars
+            .peek()
+            .map(|c| format!("Unexpected {:?}", c))
+            .unwrap_or("Unexpected end of selector".to_string())
+        )
+      } else {
+        Ok(word)
+      }
+    }
+  }
+}
+
+
+fn parse_compound(chars: &mut Peekable<Chars>) -> Result<Compound, String> {
+  let mut compound =
+    Compound::new();
+  while let Some(c) = chars.peek().cloned() {
+    match c {
+      '*' if compound.is_empty() => {
+        chars.next();
+        compound.kind = Some("*".to_string());
+      }
+      '#' => {
+        chars.next();
+        compound.name = Some(parse_word(chars)?);
+      }
+      '[' => {
+        chars.next();
+        skip_whitespace(chars);
+        let attribute =
+          parse_word(chars)?;
+        skip_whitespace(chars);
+        let may_op =
+          match chars.next() {
+            Some(']') => None,
+            Some(c) => {
+              let op =
+                match c {
+                  '=' => AttributeOp::Equals,
+                  '*' => AttributeOp::Contains,
+                  '^' => AttributeOp::StartsWith,
+                  '$' => AttributeOp::EndsWith,
+                  c => { return Err(format!("Unknown attribute operator {:?}", c)); }
+                };
+              if op != AttributeOp::Equals && chars.next() != Some('=') {
+                return Err(format!("Expected '=' after {:?}", c));
+              }
+              skip_whitespace(chars);
+              let value =
+                parse_word(chars)?;
+              skip_whitespace(chars);
+              if chars.next() != Some(']') {
+                return Err("Expected ']'".to_string());
+              }
+              Some((op, value))
+            }
+            None => { return Err("Unterminated attribute test".to_string()); }
+          };
+        compound.attributes.push(AttributeTest{ attribute, may_op });
+      }
+      ':' => {
+        chars.next();
+        let pseudo =
+          match parse_word(chars)?.as_str() {
+            "hover" => Pseudo::Hover,
+            "pressed" => Pseudo::Pressed,
+            "visible" => Pseudo::Visible,
+            "invisible" => Pseudo::Invisible,
+            other => { return Err(format!("Unknown pseudo class {:?}", other)); }
+          };
+        compound.pseudos.push(pseudo);
+      }
+      c if compound.is_empty() && (c.is_alphanumeric() || c == '_') => {
+        compound.kind = Some(parse_word(chars)?);
+      }
+      _ => { break; }
+    }
+  }
+  if compound.is_empty() {
+    Err(
+      chars
+        .peek()
+        .map(|c| format!("Expected a selector at {:?}", c))
+        .unwrap_or("Expected a selector".to_string())
+    )
+  } else {
+    Ok(compound)
+  }
+}
+
+
+impl Selector {
+  pub fn parse(s: &str) -> Result<Selector, String> {
+    let mut chars =
+      s
+      .chars()
+      .peekable();
+    let mut alternatives = vec![];
+    let mut chain = vec![];
+    let mut combinator = Combinator::Descendant;
+    skip_whitespace(&mut chars);
+    loop {
+      chain.push((combinator, parse_compound(&mut chars)?));
+      let had_whitespace =
+        skip_whitespace(&mut chars);
+      match chars.peek().cloned() {
+        None => {
+          alternatives.push(chain);
+          break;
+        }
+        Some(',') => {
+          chars.next();
+          skip_whitespace(&mut chars);
+          alternatives.push(chain);
+          chain = vec![];
+          combinator = Combinator::Descendant;
+        }
+        Some('>') => {
+          chars.next();
+          skip_whitespace(&mut chars);
+          combinator = Combinator::Child;
+        }
+        Some(_) if had_whitespace => {
+          combinator = Combinator::Descendant;
+        }
+        Some(c) => {
+          return Err(format!("Unexpected {:?}", c));
+        }
+      }
+    }
+    Ok(Selector{ alternatives })
+  }
+
+  /// Whether or not the given entity matches this selector.
+  pub fn matches(&self, world: &World, ent: Entity) -> bool {
+    self
+      .alternatives
+      .iter()
+      .any(|chain| matches_chain(chain, world, ent))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn world() -> World {
+    let mut world =
+      World::new();
+    world.register::<Name>();
+    world.register::<Parent>();
+    world.register::<ElementBox>();
+    world.register::<Text>();
+    world.register::<Button>();
+    world.register::<Picture>();
+    world.register::<Container>();
+    world.register::<Invisible>();
+    world.register::<ClipChildren>();
+    world.insert(Mouse::default());
+    world
+  }
+
+  fn element(world: &mut World, name: &str, x: i32, y: i32, width: u32, height: u32) -> Entity {
+    world
+      .create_entity()
+      .with(Name(name.to_string()))
+      .with(ElementBox{ x, y, z: 0, width, height })
+      .build()
+  }
+
+  fn matches(world: &World, selector: &str, ent: Entity) -> bool {
+    Selector::parse(selector)
+      .expect("Could not parse selector")
+      .matches(world, ent)
+  }
+
+  #[test]
+  fn selectors_parse() {
+    assert!(Selector::parse("Button").is_ok());
+    assert!(Selector::parse("menu > Button[text='Start']:hover").is_ok());
+    assert!(Selector::parse("#'Button label', Text[width]").is_ok());
+    assert!(Selector::parse("a b > c").is_ok());
+    assert!(Selector::parse("").is_err());
+    assert!(Selector::parse("Button[text='Start'").is_err());
+    assert!(Selector::parse("a >").is_err());
+    assert!(Selector::parse(":wiggling").is_err());
+  }
+
+  #[test]
+  fn selectors_match_kinds_names_and_attributes() {
+    let mut world =
+      world();
+    let label =
+      element(&mut world, "label", 10, 20, 30, 40);
+    world
+      .write_storage::<Text>()
+      .insert(label, Text::new("Start game"))
+      .expect("Could not insert text");
+
+    assert!(matches(&world, "*", label));
+    assert!(matches(&world, "Text", label));
+    assert!(!matches(&world, "Picture", label));
+    assert!(matches(&world, "label", label));
+    assert!(matches(&world, "#label", label));
+    assert!(!matches(&world, "#other", label));
+    assert!(matches(&world, "[text]", label));
+    assert!(matches(&world, "[text='Start game']", label));
+    assert!(matches(&world, "[text^='Start']", label));
+    assert!(matches(&world, "[text$='game']", label));
+    assert!(matches(&world, "[text*='t g']", label));
+    assert!(!matches(&world, "[text='Start']", label));
+    assert!(matches(&world, "[x='10'][height='40']", label));
+    assert!(matches(&world, "Picture, Text", label));
+  }
+
+  #[test]
+  fn selectors_match_combinators() {
+    let mut world =
+      world();
+    let menu =
+      element(&mut world, "menu", 0, 0, 100, 100);
+    let row =
+      element(&mut world, "row", 0, 0, 100, 20);
+    let item =
+      element(&mut world, "item", 0, 0, 50, 20);
+    world.write_storage::<Parent>().insert(row, Parent(menu)).unwrap();
+    world.write_storage::<Parent>().insert(item, Parent(row)).unwrap();
+
+    assert!(matches(&world, "menu item", item));
+    assert!(matches(&world, "row > item", item));
+    assert!(matches(&world, "menu > row > item", item));
+    assert!(!matches(&world, "menu > item", item));
+    assert!(!matches(&world, "item menu", menu));
+  }
+
+  #[test]
+  fn hover_respects_clipping() {
+    let mut world =
+      world();
+    let view =
+      element(&mut world, "view", 0, 0, 100, 100);
+    let inside =
+      element(&mut world, "inside", 0, 0, 50, 50);
+    let clipped =
+      element(&mut world, "clipped", 0, 150, 50, 50);
+    world.write_storage::<Parent>().insert(inside, Parent(view)).unwrap();
+    world.write_storage::<Parent>().insert(clipped, Parent(view)).unwrap();
+    world.write_storage::<ClipChildren>().insert(view, ClipChildren).unwrap();
+
+    *world.write_resource::<Mouse>() =
+      Mouse{ x: 25, y: 175, ..Mouse::default() };
+    assert!(!matches(&world, ":hover", clipped));
+    assert!(!matches(&world, ":hover", inside));
+
+    *world.write_resource::<Mouse>() =
+      Mouse{ x: 25, y: 25, left_btn_down: true, ..Mouse::default() };
+    assert!(matches(&world, ":hover", inside));
+    assert!(matches(&world, ":pressed", inside));
+    assert!(!matches(&world, ":hover", clipped));
+
+    assert!(matches(&world, ":visible", inside));
+    world.write_storage::<Invisible>().insert(inside, Invisible).unwrap();
+    assert!(matches(&world, ":invisible", inside));
+  }
+}
@@ -0,0 +1,791 @@
+use specs::prelude::*;
+use cassowary::strength::*;
+
+use super::components::*;
+use super::UI;
+
+
+/// How children are placed along a stack's main axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+  Start,
+  Center,
+  End,
+  SpaceBetween,
+  Fill
+}
+
+
+/// How children are placed along a stack's cross axis, or within a grid cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+  Start,
+  Center,
+  End,
+  Fill
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+  Horizontal,
+  Vertical
+}
+
+
+/// A container entity whose children are positioned by the constraints held
+/// in the `constraints` entity.
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct Container {
+  pub children: Vec<Entity>,
+  pub constraints: Entity
+}
+
+
+/// Lays its children out in a row, left to right.
+pub struct HStack;
+
+
+impl HStack {
+  pub fn new(children: Vec<Entity>) -> StackBuilder {
+    StackBuilder::new(Axis::Horizontal, children)
+  }
+}
+
+
+/// Lays its children out in a column, top to bottom.
+pub struct VStack;
+
+
+impl VStack {
+  pub fn new(children: Vec<Entity>) -> StackBuilder {
+    StackBuilder::new(Axis::Vertical, children)
+  }
+}
+
+
+/// A builder for a stack container.
+/// The container wraps its children unless it is given a size of its own,
+/// which `Distribution::SpaceBetween` and `Distribution::Fill` need in order
+/// to do anything interesting.
+///
+/// To split the stage into three columns that share its width 1:2:1:
+///
+///```ignore
+/// let columns =
+///   HStack::new(vec![left, middle, right])
+///   .distribution(Distribution::Fill)
+///   .weights(vec![1.0, 2.0, 1.0])
+///   .build(ui);
+/// ElementBuilder::new()
+///   .left(ui.stage().left())
+///   .width(ui.stage().width())
+///   .update(ui, columns);
+///```
+pub struct StackBuilder {
+  axis: Axis,
+  children: Vec<Entity>,
+  weights: Vec<f64>,
+  spacing: f64,
+  padding: f64,
+  alignment: Alignment,
+  distribution: Distribution
+}
+
+
+impl StackBuilder {
+  pub fn new(axis: Axis, children: Vec<Entity>) -> StackBuilder {
+    StackBuilder {
+      axis,
+      children,
+      weights: vec![],
+      spacing: 0.0,
+      padding: 0.0,
+      alignment: Alignment::Start,
+      distribution: Distribution::Start
+    }
+  }
+
+  pub fn spacing(self, spacing: f64) -> Self {
+    let mut sb = self;
+    sb.spacing = spacing;
+    sb
+  }
+
+  pub fn padding(self, padding: f64) -> Self {
+    let mut sb = self;
+    sb.padding = padding;
+    sb
+  }
+
+  /// How main axis space is split when using `Distribution::Fill`. The
+  /// children with a weight split the space that the unweighted children
+  /// and the spacing don't take, each getting a size in proportion to its
+  /// weight whatever its own size. Children without a weight get 1.0,
+  /// children with a weight of 0.0 keep their own size.
+  pub fn weights(self, weights: Vec<f64>) -> Self {
+    let mut sb = self;
+    sb.weights = weights;
+    sb
+  }
+
+  pub fn alignment(self, alignment: Alignment) -> Self {
+    let mut sb = self;
+    sb.alignment = alignment;
+    sb
+  }
+
+  pub fn distribution(self, distribution: Distribution) -> Self {
+    let mut sb = self;
+    sb.distribution = distribution;
+    sb
+  }
+
+  pub fn build(self, ui: &mut UI) -> Entity {
+    let container =
+      ElementBuilder::new()
+      .build(ui);
+    let (xs, ys, name) =
+      self.constraints(container);
+    build_container(ui, container, self.children, name, xs, ys)
+  }
+
+  /// The constraints that lay this stack's children out within the given
+  /// container.
+  fn constraints(
+    &self,
+    container: Entity
+  ) -> (Vec<Constraint<VariableX>>, Vec<Constraint<VariableY>>, &'static str) {
+    let padding =
+      self.padding;
+    let weights =
+      (0..self.children.len())
+      .map(|i| self.weights.get(i).cloned().unwrap_or(1.0))
+      .collect::<Vec<_>>();
+    match self.axis {
+      Axis::Horizontal => {
+        let xs =
+          distribute_x(container, &self.children, &weights, padding, self.spacing, self.distribution);
+        let mut ys =
+          vec![container.height().is(0).with_strength(WEAK)];
+        ys.extend(
+          self
+          .children
+          .iter()
+          .flat_map(|child| {
+            align_y(
+              *child,
+              container.top() + padding,
+              container.height() - padding * 2.0,
+              self.alignment
+            )
+          })
+        );
+        (xs, ys, "HStack constraints")
+      }
+      Axis::Vertical => {
+        let ys =
+          distribute_y(container, &self.children, &weights, padding, self.spacing, self.distribution);
+        let mut xs =
+          vec![container.width().is(0).with_strength(WEAK)];
+        xs.extend(
+          self
+          .children
+          .iter()
+          .flat_map(|child| {
+            align_x(
+              *child,
+              container.left() + padding,
+              container.width() - padding * 2.0,
+              self.alignment
+            )
+          })
+        );
+        (xs, ys, "VStack constraints")
+      }
+    }
+  }
+}
+
+
+/// Lays its children out in a grid of equally sized cells, filling each row
+/// from left to right before moving on to the next. A grid always has at
+/// least one column.
+pub struct Grid {
+  columns: usize,
+  children: Vec<Entity>,
+  spacing: f64,
+  padding: f64,
+  alignment: Alignment
+}
+
+
+impl Grid {
+  pub fn new(columns: usize, children: Vec<Entity>) -> Grid {
+    Grid {
+      columns: columns.max(1),
+      children,
+      spacing: 0.0,
+      padding: 0.0,
+      alignment: Alignment::Start
+    }
+  }
+
+  pub fn spacing(self, spacing: f64) -> Self {
+    let mut g = self;
+    g.spacing = spacing;
+    g
+  }
+
+  pub fn padding(self, padding: f64) -> Self {
+    let mut g = self;
+    g.padding = padding;
+    g
+  }
+
+  /// How each child is placed within its cell.
+  pub fn alignment(self, alignment: Alignment) -> Self {
+    let mut g = self;
+    g.alignment = alignment;
+    g
+  }
+
+  pub fn build(self, ui: &mut UI) -> Entity {
+    let container =
+      ElementBuilder::new()
+      .build(ui);
+    let (xs, ys) =
+      self.constraints(container);
+    build_container(ui, container, self.children, "Grid constraints", xs, ys)
+  }
+
+  /// The constraints that lay this grid's children out within the given
+  /// container.
+  fn constraints(
+    &self,
+    container: Entity
+  ) -> (Vec<Constraint<VariableX>>, Vec<Constraint<VariableY>>) {
+    let columns =
+      self.columns;
+    let rows =
+      (self.children.len() + columns - 1) / columns;
+    let (padding, spacing) =
+      (self.padding, self.spacing);
+    let cell_width =
+      (container.width() - (padding * 2.0 + spacing * (columns as f64 - 1.0)))
+      * (1.0 / columns as f64);
+    let cell_height =
+      (container.height() - (padding * 2.0 + spacing * (rows as f64 - 1.0)))
+      * (1.0 / rows.max(1) as f64);
+
+    let mut xs =
+      vec![container.width().is(0).with_strength(WEAK)];
+    let mut ys =
+      vec![container.height().is(0).with_strength(WEAK)];
+    self
+      .children
+      .iter()
+      .enumerate()
+      .for_each(|(i, child)| {
+        let (col, row) =
+          ((i % columns) as f64, (i / columns) as f64);
+        xs.extend(
+          align_x(
+            *child,
+            container.left() + (padding + spacing * col) + cell_width.clone() * col,
+            cell_width.clone(),
+            self.alignment
+          )
+        );
+        ys.extend(
+          align_y(
+            *child,
+            container.top() + (padding + spacing * row) + cell_height.clone() * row,
+            cell_height.clone(),
+            self.alignment
+          )
+        );
+      });
+
+    (xs, ys)
+  }
+}
+
+
+/// Create the constraints entity for a container, mark the container and
+/// parent its children.
+fn build_container(
+  ui: &mut UI,
+  container: Entity,
+  children: Vec<Entity>,
+  name: &str,
+  xs: Vec<Constraint<VariableX>>,
+  ys: Vec<Constraint<VariableY>>
+) -> Entity {
+  let zs =
+    children
+    .iter()
+    .map(|child| {
+      child.z_index().is(container.z_index() + 1).with_strength(STRONG)
+    })
+    .collect::<Vec<_>>();
+  let constraints =
+    ElementBuilder::new()
+    .name(name)
+    .x_constraints(xs)
+    .y_constraints(ys)
+    .z_constraints(zs)
+    .build(ui);
+
+  children
+    .iter()
+    .for_each(|child| ui.update(*child, Some(Parent(container))));
+
+  ui.update(
+    container,
+    Some(
+      Container {
+        children,
+        constraints
+      }
+    )
+  );
+
+  container
+}
+
+
+/// Place children one after another along x.
+fn distribute_x(
+  container: Entity,
+  children: &[Entity],
+  weights: &[f64],
+  padding: f64,
+  spacing: f64,
+  distribution: Distribution
+) -> Vec<Constraint<VariableX>> {
+  let (first, last) =
+    match (children.first(), children.last()) {
+      (Some(first), Some(last)) => (*first, *last),
+      _ => { return vec![]; }
+    };
+  // The container wraps its children unless told otherwise
+  let mut xs =
+    vec![
+      container.width().is(0).with_strength(WEAK),
+      first.left().is_ge(container.left() + padding).with_strength(MEDIUM),
+      container.right().is_ge(last.right() + padding).with_strength(MEDIUM),
+    ];
+  children
+    .windows(2)
+    .for_each(|pair| {
+      let (a, b) = (pair[0], pair[1]);
+      if distribution == Distribution::SpaceBetween {
+        xs.push(b.left().is_ge(a.right() + spacing).with_strength(STRONG));
+      } else {
+        xs.push(b.left().is(a.right() + spacing).with_strength(STRONG));
+      }
+    });
+
+  match distribution {
+    Distribution::Start => {
+      xs.push(first.left().is(container.left() + padding).with_strength(STRONG));
+    }
+    Distribution::Center => {
+      xs.push(
+        (first.left() + last.right())
+          .is(container.left() + container.right())
+          .with_strength(STRONG)
+      );
+    }
+    Distribution::End => {
+      xs.push(last.right().is(container.right() - padding).with_strength(STRONG));
+    }
+    Distribution::SpaceBetween => {
+      xs.push(first.left().is(container.left() + padding).with_strength(STRONG));
+      xs.push(last.right().is(container.right() - padding).with_strength(STRONG));
+      if children.len() > 2 {
+        let gap =
+          children[1].left() - children[0].right();
+        children
+          .windows(2)
+          .skip(1)
+          .for_each(|pair| {
+            xs.push(
+              (pair[1].left() - pair[0].right())
+                .is(gap.clone())
+                .with_strength(STRONG)
+            );
+          });
+      }
+    }
+    Distribution::Fill => {
+      xs.push(first.left().is(container.left() + padding).with_strength(STRONG));
+      xs.push(last.right().is(container.right() - padding).with_strength(STRONG));
+      // Weighted children are sized in proportion to each other, so between
+      // them they take whatever width the rest of the children don't
+      let weighted =
+        children
+        .iter()
+        .zip(weights.iter())
+        .filter(|(_, weight)| **weight > 0.0)
+        .collect::<Vec<_>>();
+      if let Some((anchor, anchor_weight)) = weighted.first() {
+        weighted
+          .iter()
+          .skip(1)
+          .for_each(|(child, weight)| {
+            xs.push(
+              (child.width() * **anchor_weight)
+                .is(anchor.width() * **weight)
+                .with_strength(STRONG)
+            );
+          });
+      }
+    }
+  }
+
+  xs
+}
+
+
+/// Place children one after another along y.
+fn distribute_y(
+  container: Entity,
+  children: &[Entity],
+  weights: &[f64],
+  padding: f64,
+  spacing: f64,
+  distribution: Distribution
+) -> Vec<Constraint<VariableY>> {
+  let (first, last) =
+    match (children.first(), children.last()) {
+      (Some(first), Some(last)) => (*first, *last),
+      _ => { return vec![]; }
+    };
+  // The container wraps its children unless told otherwise
+  let mut ys =
+    vec![
+      container.height().is(0).with_strength(WEAK),
+      first.top().is_ge(container.top() + padding).with_strength(MEDIUM),
+      container.bottom().is_ge(last.bottom() + padding).with_strength(MEDIUM),
+    ];
+  children
+    .windows(2)
+    .for_each(|pair| {
+      let (a, b) = (pair[0], pair[1]);
+      if distribution == Distribution::SpaceBetween {
+        ys.push(b.top().is_ge(a.bottom() + spacing).with_strength(STRONG));
+      } else {
+        ys.push(b.top().is(a.bottom() + spacing).with_strength(STRONG));
+      }
+    });
+
+  match distribution {
+    Distribution::Start => {
+      ys.push(first.top().is(container.top() + padding).with_strength(STRONG));
+    }
+    Distribution::Center => {
+      ys.push(
+        (first.top() + last.bottom())
+          .is(container.top() + container.bottom())
+          .with_strength(STRONG)
+      );
+    }
+    Distribution::End => {
+      ys.push(last.bottom().is(container.bottom() - padding).with_strength(STRONG));
+    }
+    Distribution::SpaceBetween => {
+      ys.push(first.top().is(container.top() + padding).with_strength(STRONG));
+      ys.push(last.bottom().is(container.bottom() - padding).with_strength(STRONG));
+      if children.len() > 2 {
+        let gap =
+          children[1].top() - children[0].bottom();
+        children
+          .windows(2)
+          .skip(1)
+          .for_each(|pair| {
+            ys.push(
+              (pair[1].top() - pair[0].bottom())
+                .is(gap.clone())
+                .with_strength(STRONG)
+            );
+          });
+      }
+    }
+    Distribution::Fill => {
+      ys.push(first.top().is(container.top() + padding).with_strength(STRONG));
+      ys.push(last.bottom().is(container.bottom() - padding).with_strength(STRONG));
+      // Weighted children are sized in proportion to each other, so between
+      // them they take whatever height the rest of the children don't
+      let weighted =
+        children
+        .iter()
+        .zip(weights.iter())
+        .filter(|(_, weight)| **weight > 0.0)
+        .collect::<Vec<_>>();
+      if let Some((anchor, anchor_weight)) = weighted.first() {
+        weighted
+          .iter()
+          .skip(1)
+          .for_each(|(child, weight)| {
+            ys.push(
+              (child.height() * **anchor_weight)
+                .is(anchor.height() * **weight)
+                .with_strength(STRONG)
+            );
+          });
+      }
+    }
+  }
+
+  ys
+}
+
+
+/// Align a child along x within the span starting at `start` that is `size`
+/// wide. The span grows to fit the child unless told otherwise.
+fn align_x(
+  child: Entity,
+  start: Expression<VariableX>,
+  size: Expression<VariableX>,
+  alignment: Alignment
+) -> Vec<Constraint<VariableX>> {
+  let mut xs =
+    vec![size.clone().is_ge(child.width()).with_strength(MEDIUM)];
+  match alignment {
+    Alignment::Start => {
+      xs.push(child.left().is(start));
+    }
+    Alignment::Center => {
+      xs.push(child.center_x().is(start + size * 0.5));
+    }
+    Alignment::End => {
+      xs.push(child.right().is(start + size));
+    }
+    Alignment::Fill => {
+      xs.push(child.left().is(start));
+      xs.push(child.width().is(size));
+    }
+  }
+  xs
+}
+
+
+/// Align a child along y within the span starting at `start` that is `size`
+/// tall. The span grows to fit the child unless told otherwise.
+fn align_y(
+  child: Entity,
+  start: Expression<VariableY>,
+  size: Expression<VariableY>,
+  alignment: Alignment
+) -> Vec<Constraint<VariableY>> {
+  let mut ys =
+    vec![size.clone().is_ge(child.height()).with_strength(MEDIUM)];
+  match alignment {
+    Alignment::Start => {
+      ys.push(child.top().is(start));
+    }
+    Alignment::Center => {
+      ys.push(child.center_y().is(start + size * 0.5));
+    }
+    Alignment::End => {
+      ys.push(child.bottom().is(start + size));
+    }
+    Alignment::Fill => {
+      ys.push(child.top().is(start));
+      ys.push(child.height().is(size));
+    }
+  }
+  ys
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  fn entities(n: usize) -> Vec<Entity> {
+    let mut world =
+      World::new();
+    (0..n)
+      .map(|_| world.create_entity().build())
+      .collect()
+  }
+
+  fn solve<T>(cs: Vec<Constraint<T>>) -> HashMap<T, f64>
+  where
+    T: Clone + std::fmt::Debug + Eq + std::hash::Hash
+  {
+    let mut solver =
+      Solver::new();
+    cs
+      .into_iter()
+      .for_each(|c| {
+        solver
+          .add_constraint(c)
+          .expect("Could not add constraint");
+      });
+    solver
+      .fetch_changes()
+      .iter()
+      .cloned()
+      .collect()
+  }
+
+  fn value<T: Eq + std::hash::Hash>(values: &HashMap<T, f64>, variable: T) -> f64 {
+    values
+      .get(&variable)
+      .cloned()
+      .unwrap_or(0.0)
+  }
+
+  fn assert_near(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 0.001, "expected {} but got {}", expected, actual);
+  }
+
+  #[test]
+  fn hstack_places_children_in_a_row_and_wraps_them() {
+    let ents =
+      entities(3);
+    let (container, a, b) =
+      (ents[0], ents[1], ents[2]);
+    let (mut xs, mut ys, _) =
+      HStack::new(vec![a, b])
+      .spacing(5.0)
+      .padding(2.0)
+      .constraints(container);
+    xs.extend(vec![
+      container.left().is(0),
+      a.width().is(10),
+      b.width().is(20)
+    ]);
+    ys.extend(vec![
+      container.top().is(0),
+      a.height().is(10),
+      b.height().is(30)
+    ]);
+    let xs = solve(xs);
+    let ys = solve(ys);
+    assert_near(value(&xs, VariableX::Left(Some(a))), 2.0);
+    assert_near(value(&xs, VariableX::Left(Some(b))), 17.0);
+    assert_near(value(&xs, VariableX::Width(Some(container))), 39.0);
+    assert_near(value(&ys, VariableY::Top(Some(a))), 2.0);
+    assert_near(value(&ys, VariableY::Top(Some(b))), 2.0);
+    assert_near(value(&ys, VariableY::Height(Some(container))), 34.0);
+  }
+
+  #[test]
+  fn fill_splits_space_by_weight() {
+    let ents =
+      entities(4);
+    let (container, left, middle, right) =
+      (ents[0], ents[1], ents[2], ents[3]);
+    let (mut xs, _, _) =
+      HStack::new(vec![left, middle, right])
+      .distribution(Distribution::Fill)
+      .weights(vec![1.0, 2.0, 1.0])
+      .constraints(container);
+    xs.extend(vec![
+      container.left().is(0),
+      container.width().is(100)
+    ]);
+    let xs = solve(xs);
+    assert_near(value(&xs, VariableX::Width(Some(left))), 25.0);
+    assert_near(value(&xs, VariableX::Width(Some(middle))), 50.0);
+    assert_near(value(&xs, VariableX::Width(Some(right))), 25.0);
+    assert_near(value(&xs, VariableX::Left(Some(right))), 75.0);
+  }
+
+  #[test]
+  fn fill_keeps_unweighted_children_at_their_own_size() {
+    let ents =
+      entities(4);
+    let (container, header, body, footer) =
+      (ents[0], ents[1], ents[2], ents[3]);
+    let (_, mut ys, _) =
+      VStack::new(vec![header, body, footer])
+      .distribution(Distribution::Fill)
+      .spacing(10.0)
+      .weights(vec![0.0, 1.0, 0.0])
+      .constraints(container);
+    ys.extend(vec![
+      container.top().is(0),
+      container.height().is(200),
+      header.height().is(20),
+      footer.height().is(30)
+    ]);
+    let ys = solve(ys);
+    assert_near(value(&ys, VariableY::Top(Some(body))), 30.0);
+    assert_near(value(&ys, VariableY::Height(Some(body))), 130.0);
+    assert_near(value(&ys, VariableY::Top(Some(footer))), 170.0);
+  }
+
+  #[test]
+  fn grid_places_children_in_cells() {
+    let ents =
+      entities(4);
+    let (container, a, b, c) =
+      (ents[0], ents[1], ents[2], ents[3]);
+    let (mut xs, mut ys) =
+      Grid::new(2, vec![a, b, c])
+      .spacing(10.0)
+      .alignment(Alignment::Fill)
+      .constraints(container);
+    xs.extend(vec![
+      container.left().is(0),
+      container.width().is(110)
+    ]);
+    ys.extend(vec![
+      container.top().is(0),
+      container.height().is(50)
+    ]);
+    let xs = solve(xs);
+    let ys = solve(ys);
+    assert_near(value(&xs, VariableX::Left(Some(b))), 60.0);
+    assert_near(value(&xs, VariableX::Width(Some(b))), 50.0);
+    assert_near(value(&xs, VariableX::Left(Some(c))), 0.0);
+    assert_near(value(&ys, VariableY::Top(Some(c))), 30.0);
+    assert_near(value(&ys, VariableY::Height(Some(c))), 20.0);
+  }
+
+  #[test]
+  fn grid_has_at_least_one_column() {
+    let ents =
+      entities(3);
+    let (container, a, b) =
+      (ents[0], ents[1], ents[2]);
+    let (_, mut ys) =
+      Grid::new(0, vec![a, b])
+      .constraints(container);
+    ys.extend(vec![
+      container.top().is(0),
+      a.height().is(10),
+      b.height().is(10)
+    ]);
+    let ys = solve(ys);
+    assert_near(value(&ys, VariableY::Top(Some(b))), 10.0);
+  }
+
+  #[test]
+  fn percentages_are_relative_to_the_parent() {
+    let ents =
+      entities(2);
+    let (parent, child) =
+      (ents[0], ents[1]);
+    let xs =
+      solve(vec![
+        parent.width().is(200),
+        child.width().is(parent.percent_width(30.0))
+      ]);
+    let ys =
+      solve(vec![
+        parent.height().is(80),
+        child.height().is(parent.percent_height(50.0))
+      ]);
+    assert_near(value(&xs, VariableX::Width(Some(child))), 60.0);
+    assert_near(value(&ys, VariableY::Height(Some(child))), 40.0);
+  }
+}
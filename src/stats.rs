@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use super::rasterizer::CacheStats;
+
+
+/// Where the UI spent its last `UI::maintain`, and how much stuff it is
+/// holding on to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UiStats {
+  /// Time spent in each system, by name, in the order they first ran.
+  pub systems: Vec<(String, Duration)>,
+  /// Time spent rasterizing pictures and text into textures.
+  pub rasterization: Duration,
+  /// Time spent drawing the frame.
+  pub drawing: Duration,
+  /// Time spent in the whole of `UI::maintain`.
+  pub total: Duration,
+  pub cached_text_textures: usize,
+  pub cached_picture_textures: usize,
+  pub text_cache: CacheStats,
+  pub picture_cache: CacheStats,
+  pub glyphs: usize,
+  pub glyph_pages: usize,
+  /// Bytes of texture memory held by the glyph atlas' pages.
+  pub glyph_bytes: usize,
+  pub entities: usize,
+  /// Constraints held by each axis' solver.
+  pub x_constraints: usize,
+  pub y_constraints: usize,
+  pub z_constraints: usize
+}
+
+
+impl UiStats {
+  pub fn new() -> UiStats {
+    UiStats::default()
+  }
+
+  /// The time the named system took, eg `stats.system_time("layout_x")`.
+  pub fn system_time(&self, name: &str) -> Option<Duration> {
+    self
+      .systems
+      .iter()
+      .find(|(n, _)| n == name)
+      .map(|(_, d)| *d)
+  }
+
+  pub fn set_system_time(&mut self, name: &str, duration: Duration) {
+    match self.systems.iter_mut().find(|(n, _)| n == name) {
+      Some((_, d)) => { *d = duration; }
+      None => { self.systems.push((name.to_string(), duration)); }
+    }
+  }
+}
@@ -1,4 +1,14 @@
+pub mod aspect_ratio;
+pub mod baseline;
 pub mod button;
+pub mod checkbox;
 pub mod event;
+pub mod focus;
 pub mod layout;
+pub mod owned;
+pub mod progress;
+pub mod radio;
+pub mod scroll;
 pub mod shrinkwrap;
+pub mod slider;
+pub mod timed;
@@ -0,0 +1,120 @@
+use specs::prelude::*;
+use cassowary::strength::*;
+
+use std::collections::HashMap;
+
+use super::super::components::{
+  Constraints,
+  ElementBox,
+  HasXConstraints,
+  Invisible,
+  VariableX,
+};
+use super::layout::EditVariables;
+use super::owned::OwnedConstraints;
+
+
+/// Keeps an entity's width at a fixed ratio of its height (width / height).
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct AspectRatio(pub f64);
+
+
+/// The aspect ratio system constrains the width of an entity to its height
+/// times its AspectRatio. Width and height live in separate solvers, so the
+/// height is mirrored into the x solver as the width of a hidden stand-in
+/// entity, which is an edit variable. This runs after the y layout and
+/// before the x layout, so the width follows the height solved in the same
+/// maintain.
+#[derive(Default)]
+pub struct AspectRatioSystem {
+  /// The stand-ins whose widths mirror the heights, by entity.
+  mirrors: HashMap<Entity, Entity>,
+  applied: OwnedConstraints<VariableX, f64>
+}
+
+
+impl AspectRatioSystem {
+  pub fn new() -> AspectRatioSystem {
+    AspectRatioSystem {
+      mirrors: HashMap::new(),
+      applied: OwnedConstraints::new()
+    }
+  }
+}
+
+
+impl<'a> System<'a> for AspectRatioSystem {
+  type SystemData = (
+    Entities<'a>,
+    ReadStorage<'a, AspectRatio>,
+    ReadStorage<'a, ElementBox>,
+    Write<'a, EditVariables<VariableX>>,
+    WriteStorage<'a, Invisible>,
+    WriteStorage<'a, Constraints<VariableX>>,
+  );
+
+  fn run(
+    &mut self,
+    (entities,
+     ratios,
+     element_boxes,
+     mut edits,
+     mut invisibles,
+     mut x_cs,
+    ): Self::SystemData
+  ) {
+    for (ent, AspectRatio(ratio)) in (&entities, &ratios).join() {
+      let mirror =
+        *self
+        .mirrors
+        .entry(ent)
+        .or_insert_with(|| {
+          let mirror =
+            entities.create();
+          invisibles
+            .insert(mirror, Invisible)
+            .expect("Could not hide an aspect ratio mirror");
+          mirror
+        });
+      let height =
+        element_boxes
+        .get(ent)
+        .map(|el| el.height)
+        .unwrap_or(0);
+      edits
+        .0
+        .insert(VariableX::Width(Some(mirror)), height as f64);
+      self
+        .applied
+        .keep(ent, *ratio, &mut x_cs, || {
+          ent.width().is(mirror.width() * *ratio).with_strength(STRONG)
+        });
+    }
+
+    // Forget entities that are gone or no longer keep a ratio
+    self
+      .applied
+      .release_unless(&entities, &mut x_cs, |ent| ratios.contains(ent));
+    let gone:Vec<Entity> =
+      self
+      .mirrors
+      .keys()
+      .filter(|ent| !entities.is_alive(**ent) || !ratios.contains(**ent))
+      .cloned()
+      .collect();
+    for ent in gone {
+      let mirror =
+        self
+        .mirrors
+        .remove(&ent)
+        .expect("Could not find an aspect ratio mirror");
+      edits
+        .0
+        .remove(&VariableX::Width(Some(mirror)));
+      let _ =
+        entities
+        .delete(mirror);
+    }
+  }
+}
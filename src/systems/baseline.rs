@@ -0,0 +1,84 @@
+use specs::prelude::*;
+
+use super::super::components::{
+  Constraints,
+  HasYConstraints,
+  VariableY,
+};
+use super::owned::OwnedConstraints;
+
+
+/// The metrics of an entity's Text's font, put there by the rasterizer.
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(VecStorage)]
+pub struct FontMetrics {
+  /// Distance from the top of the line to the baseline.
+  pub ascent: i32,
+  /// Distance from the baseline to the bottom of the line, usually negative.
+  pub descent: i32,
+  /// The height of a line of text.
+  pub line_height: u32,
+  /// The recommended distance from one baseline to the next.
+  pub line_skip: u32
+}
+
+
+impl FontMetrics {
+  /// Where the baseline sits as a fraction of the line height.
+  pub fn baseline_ratio(&self) -> f64 {
+    if self.line_height == 0 {
+      0.0
+    } else {
+      self.ascent as f64 / self.line_height as f64
+    }
+  }
+}
+
+
+/// The baseline system keeps the baseline of each entity with FontMetrics at
+/// its font's ascent below its top, scaled along with the entity's height.
+#[derive(Default)]
+pub struct BaselineSystem {
+  applied: OwnedConstraints<VariableY, f64>
+}
+
+
+impl BaselineSystem {
+  pub fn new() -> BaselineSystem {
+    BaselineSystem {
+      applied: OwnedConstraints::new()
+    }
+  }
+}
+
+
+impl<'a> System<'a> for BaselineSystem {
+  type SystemData = (
+    Entities<'a>,
+    ReadStorage<'a, FontMetrics>,
+    WriteStorage<'a, Constraints<VariableY>>,
+  );
+
+  fn run(
+    &mut self,
+    (entities,
+     metrics,
+     mut y_cs,
+    ): Self::SystemData
+  ) {
+    for (ent, metrics) in (&entities, &metrics).join() {
+      let ratio =
+        metrics.baseline_ratio();
+      self
+        .applied
+        .keep(ent, ratio, &mut y_cs, || {
+          ent.baseline().is(ent.top() + ent.height() * ratio)
+        });
+    }
+
+    // Forget entities that are gone or no longer have metrics
+    self
+      .applied
+      .release_unless(&entities, &mut y_cs, |ent| metrics.contains(ent));
+  }
+}
@@ -5,7 +5,6 @@ use cassowary::strength::*;
 use super::super::picture::Picture;
 use super::super::components::*;
 use super::super::UI;
-use super::super::rasterizer::Rasterizer;
 
 
 const PADDING_W: u32 = 4;
@@ -25,6 +24,11 @@ pub struct Button {
 
 
 impl Button {
+  /// The button's label text.
+  pub fn text(&self) -> &str {
+    &self.text_string
+  }
+
   fn background() -> Picture {
     Picture::new()
       .set_color(0, 0, 0, 128)
@@ -88,7 +92,7 @@ impl ButtonBuilder {
     builder
   }
 
-  pub fn build(self, ui: &mut UI, rasterizer: &mut Rasterizer) -> Entity {
+  pub fn build(self, ui: &mut UI) -> Entity {
     let mut bb =
       self;
     let text =
@@ -96,9 +100,12 @@ impl ButtonBuilder {
       .text
       .take()
       .unwrap();
-    let (_, tw, th) =
-      rasterizer
-      .get_text(&text);
+    let (tw, th) =
+      ui.measure_text(&text)
+      .unwrap_or_else(|e| {
+        error!("Could not measure button text {:?}: {}", text.text, e);
+        (0, 0)
+      });
     let button =
       ElementBuilder::new()
       .build(ui);
@@ -106,16 +113,19 @@ impl ButtonBuilder {
       ElementBuilder::new()
       .picture(&Button::foreground())
       .name("Button foreground")
+      .parent(button)
       .build(ui);
     let background =
       ElementBuilder::new()
       .picture(&Button::background())
       .name("Button background")
+      .parent(button)
       .build(ui);
     let label =
       ElementBuilder::new()
       .text(&text)
       .name("Button label")
+      .parent(button)
       .build(ui);
     let total_width =
       tw + PADDING_W * 3;
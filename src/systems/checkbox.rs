@@ -0,0 +1,295 @@
+use specs::prelude::*;
+
+use cassowary::strength::*;
+
+use super::super::picture::Picture;
+use super::super::components::*;
+use super::super::UI;
+use super::event::{push_event, Key, Keys, Mouse};
+use super::focus::{Focus, Focusable};
+
+
+/// The width and height of a checkbox's box or a radio button's circle.
+pub(super) const INDICATOR_SIZE: u32 = 14;
+/// The space between an indicator and its label.
+pub(super) const LABEL_GAP: u32 = 4;
+
+
+/// Marks a checked checkbox, or the selected option of a radio group.
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct Checked;
+
+
+/// The text for a checkbox or radio button label.
+pub(super) fn label_text(s: &str) -> Text {
+  Text::new(s)
+    .color(0x33, 0x33, 0x33, 255)
+}
+
+
+/// The color of an indicator's outline.
+pub(super) fn outline_color(focused: bool) -> (u8, u8, u8) {
+  if focused {
+    (0x33, 0x66, 0xff)
+  } else {
+    (0x33, 0x33, 0x33)
+  }
+}
+
+
+/// Constraints that put an indicator (and the mark inside it) on the left of
+/// the widget with the label after it, the widget wrapping them both. Since
+/// the widget's box covers its label, clicking the label clicks the widget.
+pub(super) fn indicator_constraints(
+  widget: Entity,
+  indicator: Entity,
+  mark: Entity,
+  mark_inset: u32,
+  label: Entity,
+  (label_width, label_height): (u32, u32)
+) -> AxisConstraints {
+  AxisConstraints {
+    x: vec![
+      indicator.left().is(widget.left()),
+      indicator.width().is(INDICATOR_SIZE),
+      mark.left().is(indicator.left() + mark_inset),
+      mark.width().is(indicator.width() - 2 * mark_inset),
+      label.left().is(indicator.right() + LABEL_GAP),
+      label.width().is(label_width),
+      widget.right().is(label.right())
+    ],
+    y: vec![
+      indicator.center_y().is(widget.center_y()),
+      indicator.height().is(INDICATOR_SIZE),
+      mark.top().is(indicator.top() + mark_inset),
+      mark.height().is(indicator.height() - 2 * mark_inset),
+      label.center_y().is(widget.center_y()),
+      label.height().is(label_height),
+      widget.height().is_ge(indicator.height()),
+      widget.height().is_ge(label.height()),
+      widget.height().is(u32::max(INDICATOR_SIZE, label_height)).with_strength(WEAK)
+    ],
+    z: vec![
+      indicator.z_index().is(widget.z_index()),
+      mark.z_index().is(widget.z_index() + 1),
+      label.z_index().is(widget.z_index())
+    ]
+  }
+}
+
+
+/// Whether any of the keys activate a focused checkbox or radio button.
+pub(super) fn has_activation_key(keys: &Keys) -> bool {
+  keys
+    .0
+    .iter()
+    .any(|key| *key == Key::Space || *key == Key::Enter)
+}
+
+
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct Checkbox {
+  text_string: String,
+  check_box: Entity,
+  mark: Entity,
+  label: Entity,
+  static_constraints: Entity,
+  /// Whether the mouse went down on the checkbox and hasn't come up yet.
+  pressed: bool,
+  focused: bool
+}
+
+
+impl Checkbox {
+  /// The checkbox's label text.
+  pub fn text(&self) -> &str {
+    &self.text_string
+  }
+
+  fn check_box(focused: bool) -> Picture {
+    let (r, g, b) =
+      outline_color(focused);
+    Picture::new()
+      .set_color(r, g, b, 255)
+      .fill_rect(0, 0, INDICATOR_SIZE, INDICATOR_SIZE)
+      .set_color(255, 255, 255, 255)
+      .fill_rect(2, 2, INDICATOR_SIZE - 4, INDICATOR_SIZE - 4)
+  }
+
+  fn mark() -> Picture {
+    Picture::new()
+      .set_color(0x33, 0x33, 0x33, 255)
+      .fill_rect(0, 0, 1, 1)
+  }
+}
+
+
+pub struct CheckboxBuilder {
+  text: Text,
+  checked: bool
+}
+
+
+impl CheckboxBuilder {
+  pub fn new(s: &str) -> CheckboxBuilder {
+    CheckboxBuilder {
+      text: label_text(s),
+      checked: false
+    }
+  }
+
+  /// Whether the checkbox starts out checked. Defaults to false.
+  pub fn checked(self, checked: bool) -> Self {
+    let mut cb = self;
+    cb.checked = checked;
+    cb
+  }
+
+  pub fn build(self, ui: &mut UI) -> Entity {
+    let label_size =
+      ui.measure_text(&self.text)
+      .unwrap_or_else(|e| {
+        error!("Could not measure checkbox text {:?}: {}", self.text.text, e);
+        (0, 0)
+      });
+    let checkbox =
+      ElementBuilder::new()
+      .name("Checkbox")
+      .build(ui);
+    let check_box =
+      ElementBuilder::new()
+      .picture(&Checkbox::check_box(false))
+      .name("Checkbox box")
+      .parent(checkbox)
+      .build(ui);
+    let mark =
+      ElementBuilder::new()
+      .picture(&Checkbox::mark())
+      .name("Checkbox mark")
+      .parent(checkbox)
+      .build(ui);
+    let label =
+      ElementBuilder::new()
+      .text(&self.text)
+      .name("Checkbox label")
+      .parent(checkbox)
+      .build(ui);
+    let cs =
+      indicator_constraints(checkbox, check_box, mark, 4, label, label_size);
+    let static_constraints =
+      ElementBuilder::new()
+      .name("Checkbox static constraints")
+      .x_constraints(cs.x)
+      .y_constraints(cs.y)
+      .z_constraints(cs.z)
+      .build(ui);
+
+    ui.update(checkbox, Some(Focusable));
+    if self.checked {
+      ui.update(checkbox, Some(Checked));
+    } else {
+      ui.update(mark, Some(Invisible));
+    }
+    ui.update(
+      checkbox,
+      Some(
+        Checkbox {
+          text_string: self.text.text.clone(),
+          check_box,
+          mark,
+          label,
+          static_constraints,
+          pressed: false,
+          focused: false
+        }
+      )
+    );
+
+    checkbox
+  }
+}
+
+
+/// Toggles checkboxes when they are clicked (anywhere, label included) or
+/// when they have the focus and Space or Enter is pressed. A click is a press
+/// and a release both on the same checkbox.
+pub struct CheckboxSystem;
+
+
+impl CheckboxSystem {
+  pub fn new() -> CheckboxSystem {
+    CheckboxSystem
+  }
+}
+
+
+impl<'a> System<'a> for CheckboxSystem {
+  type SystemData = (
+    Entities<'a>,
+    Read<'a, Focus>,
+    Read<'a, Keys>,
+    Read<'a, Mouse>,
+    WriteStorage<'a, Checkbox>,
+    WriteStorage<'a, Checked>,
+    WriteStorage<'a, Events>,
+    WriteStorage<'a, Invisible>,
+    WriteStorage<'a, Picture>
+  );
+
+  fn run(
+    &mut self,
+    (entities, focus, keys, mouse, mut checkboxes, mut checked, mut events, mut invisibles, mut pictures): Self::SystemData
+  ) {
+    for (ent, checkbox) in (&entities, &mut checkboxes).join() {
+      let has_event = |event: Event| {
+        events
+          .get(ent)
+          .map(|evs| evs.0.contains(&event))
+          .unwrap_or(false)
+      };
+      if has_event(Event::MouseDown) {
+        checkbox.pressed = true;
+      }
+      let was_clicked =
+        checkbox.pressed && has_event(Event::MouseUp);
+      if !mouse.left_btn_down {
+        checkbox.pressed = false;
+      }
+      let is_focused =
+        focus.0 == Some(ent);
+      let was_activated =
+        is_focused && has_activation_key(&keys);
+
+      if was_clicked || was_activated {
+        if checked.contains(ent) {
+          checked.remove(ent);
+        } else {
+          checked
+            .insert(ent, Checked)
+            .expect("Could not check checkbox");
+        }
+        push_event(&mut events, ent, Event::Toggled);
+      }
+
+      // Show the mark when checked, however it was checked
+      let is_checked =
+        checked.contains(ent);
+      if is_checked && invisibles.contains(checkbox.mark) {
+        invisibles.remove(checkbox.mark);
+      } else if !is_checked && !invisibles.contains(checkbox.mark) {
+        invisibles
+          .insert(checkbox.mark, Invisible)
+          .expect("Could not hide checkbox mark");
+      }
+
+      if checkbox.focused != is_focused {
+        pictures
+          .insert(checkbox.check_box, Checkbox::check_box(is_focused))
+          .expect("Could not update checkbox box");
+        checkbox.focused = is_focused;
+      }
+    }
+  }
+}
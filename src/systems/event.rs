@@ -28,13 +28,46 @@ impl Default for Mouse {
 }
 
 
+/// Mouse wheel clicks since the last maintain, right and away from the user
+/// being positive.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MouseWheel {
+  pub x: i32,
+  pub y: i32
+}
+
+
+/// Keys that widgets respond to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+  Tab,
+  Space,
+  Enter,
+  Left,
+  Right,
+  Up,
+  Down
+}
+
+
+/// Keys pressed since the last maintain.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Keys(pub Vec<Key>);
+
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Event {
   MouseOver,
   MouseMove,
   MouseOut,
   MouseDown,
-  MouseUp
+  MouseUp,
+  /// A checkbox was checked or unchecked.
+  Toggled,
+  /// A radio group's selected option changed.
+  Selected,
+  /// A slider's value changed.
+  ValueChanged
 }
 
 
@@ -43,6 +76,21 @@ pub enum Event {
 pub struct Events(pub Vec<Event>);
 
 
+/// Add an event to the entity's events for this maintain. Widget systems use
+/// this to report their own events after the EventSystem has run.
+pub fn push_event(events: &mut WriteStorage<Events>, ent: Entity, event: Event) {
+  let mut evs =
+    events
+    .get(ent)
+    .cloned()
+    .unwrap_or(Events(vec![]));
+  evs.0.push(event);
+  events
+    .insert(ent, evs)
+    .expect("Could not push event");
+}
+
+
 
 pub struct EventSystem {
   mouse: Mouse,
@@ -58,20 +106,25 @@ impl EventSystem {
     }
   }
 
-  /// Determine the current events for the given entity.
+  /// Determine the current events for the given entity. The mouse is only
+  /// over the part of the entity inside its clip box, if it has one, and is
+  /// never over hidden entities.
   // TODO: Use an r*tree for storing entity AABBs
   fn determine_current_events(
     &mut self,
     ent: Entity,
     element_box: &ElementBox,
+    may_clip: Option<ElementBox>,
+    hidden: bool,
     mouse: &Mouse,
   ) -> Option<Events> {
     let mut events:Vec<Event> = vec![];
     let mouse_is_over =
-      element_box.left() <= mouse.x
-      && element_box.right() >= mouse.x
-      && element_box.top() <= mouse.y
-      && element_box.bottom() >= mouse.y;
+      !hidden
+      && element_box.contains(mouse.x, mouse.y)
+      && may_clip
+        .map(|clip| clip.contains(mouse.x, mouse.y))
+        .unwrap_or(true);
     let was_previously_over =
       self
       .entities_mouse_is_over
@@ -149,11 +202,17 @@ impl<'a> System<'a> for EventSystem {
   type SystemData = (
     Entities<'a>,
     Read<'a, Mouse>,
+    ReadStorage<'a, ClipChildren>,
     ReadStorage<'a, ElementBox>,
+    ReadStorage<'a, Invisible>,
+    ReadStorage<'a, Parent>,
     WriteStorage<'a, Events>
   );
 
-  fn run(&mut self, (entities, mouse, element_boxes, mut events): Self::SystemData) {
+  fn run(
+    &mut self,
+    (entities, mouse, clips, element_boxes, invisibles, parents, mut events): Self::SystemData
+  ) {
     // Remove any events that were in the system before
     let past_events:Vec<Entity> =
       (&entities, &events)
@@ -170,8 +229,12 @@ impl<'a> System<'a> for EventSystem {
 
     // Figure out the new events
     for (ent, element_box) in (&entities, &element_boxes).join() {
+      let may_clip =
+        clip_box(ent, &parents, &clips, &element_boxes);
+      let hidden =
+        is_hidden(ent, &parents, &invisibles);
       self
-        .determine_current_events(ent, element_box, &mouse)
+        .determine_current_events(ent, element_box, may_clip, hidden, &mouse)
         .into_iter()
         .for_each(|evs| {
           events
@@ -0,0 +1,97 @@
+use specs::prelude::*;
+
+use super::super::components::*;
+use super::event::{Key, Keys, Mouse};
+
+
+/// Marks an entity that can take the keyboard focus.
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct Focusable;
+
+
+/// The entity that gets keyboard input, if any.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Focus(pub Option<Entity>);
+
+
+/// Focuses Focusable entities when they are clicked, unfocuses when anything
+/// else is clicked and moves the focus through the visible Focusable entities
+/// (top to bottom, left to right) with Tab.
+pub struct FocusSystem {
+  left_was_down: bool
+}
+
+
+impl FocusSystem {
+  pub fn new() -> FocusSystem {
+    FocusSystem {
+      left_was_down: false
+    }
+  }
+}
+
+
+impl<'a> System<'a> for FocusSystem {
+  type SystemData = (
+    Entities<'a>,
+    Write<'a, Focus>,
+    Read<'a, Keys>,
+    Read<'a, Mouse>,
+    ReadStorage<'a, ElementBox>,
+    ReadStorage<'a, Events>,
+    ReadStorage<'a, Focusable>,
+    ReadStorage<'a, Invisible>
+  );
+
+  fn run(
+    &mut self,
+    (entities, mut focus, keys, mouse, element_boxes, events, focusables, invisibles): Self::SystemData
+  ) {
+    if focus.0.map(|ent| !entities.is_alive(ent)).unwrap_or(false) {
+      focus.0 = None;
+    }
+
+    let left_went_down =
+      mouse.left_btn_down && !self.left_was_down;
+    self.left_was_down =
+      mouse.left_btn_down;
+    if left_went_down {
+      focus.0 =
+        (&entities, &focusables, &element_boxes, &events)
+        .join()
+        .filter(|(_, _, _, evs)| evs.0.contains(&Event::MouseDown))
+        .max_by_key(|(_, _, el, _)| el.z)
+        .map(|(ent, _, _, _)| ent);
+    }
+
+    let tabs =
+      keys
+      .0
+      .iter()
+      .filter(|key| **key == Key::Tab)
+      .count();
+    if tabs > 0 {
+      let mut order:Vec<(Entity, ElementBox)> =
+        (&entities, &focusables, &element_boxes, !&invisibles)
+        .join()
+        .map(|(ent, _, el, ())| (ent, el.clone()))
+        .collect();
+      order
+        .sort_by_key(|(ent, el)| (el.y, el.x, ent.id()));
+      if !order.is_empty() {
+        let may_current =
+          focus
+          .0
+          .and_then(|focused| order.iter().position(|(ent, _)| *ent == focused));
+        let next =
+          match may_current {
+            Some(current) => (current + tabs) % order.len(),
+            None => (tabs - 1) % order.len()
+          };
+        focus.0 =
+          Some(order[next].0);
+      }
+    }
+  }
+}
@@ -6,6 +6,7 @@ use std::collections::HashMap;
 use std::any::Any;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::marker::PhantomData;
 
 use super::super::WindowSize;
 use super::super::components::{
@@ -18,11 +19,86 @@ use super::super::components::{
 };
 
 
+fn entity_name(names: &ReadStorage<Name>, ent: Entity) -> String {
+  names
+    .get(ent)
+    .map(|Name(s)| s.clone())
+    .unwrap_or(format!("entity({:?})", ent.id()))
+}
+
+
+/// Values for layout variables that systems drive from outside the solver,
+/// eg the height an aspect ratio's width follows or a scroll view's scroll
+/// offset. Each one is added to the solver as an edit variable while it is
+/// in here, and its value is suggested whenever it changes.
+pub struct EditVariables<T>(pub HashMap<T, f64>);
+
+
+impl<T> Default for EditVariables<T> {
+  fn default() -> Self {
+    EditVariables(HashMap::new())
+  }
+}
+
+
+/// How many constraints a layout system's solver holds, as of its last run.
+/// Only the constraints the solver accepted are counted, the ones it
+/// rejected are kept aside.
+pub struct LayoutStats<T> {
+  /// The layout system's own constraints, eg those pinning the stage.
+  pub initial_constraints: usize,
+  /// Constraints from entities' Constraints components.
+  pub entity_constraints: usize,
+  /// Constraints from entities' Constraints components that the solver
+  /// rejected, by the id of the entity holding them. These have no effect
+  /// on the layout.
+  pub rejected: HashMap<u32, Vec<Constraint<T>>>,
+  phantom: PhantomData<T>
+}
+
+
+impl<T> Default for LayoutStats<T> {
+  fn default() -> Self {
+    LayoutStats {
+      initial_constraints: 0,
+      entity_constraints: 0,
+      rejected: HashMap::new(),
+      phantom: PhantomData
+    }
+  }
+}
+
+
+impl<T> LayoutStats<T> {
+  pub fn constraints(&self) -> usize {
+    self.initial_constraints + self.entity_constraints
+  }
+}
+
+
+impl<T> LayoutStats<T>
+where
+  T: Clone + Debug + Eq + Hash
+{
+  /// Whether or not the solver rejected the given constraint held by the
+  /// given entity.
+  pub fn is_rejected(&self, ent: Entity, constraint: &Constraint<T>) -> bool {
+    self
+      .rejected
+      .get(&ent.id())
+      .map(|cs| cs.contains(constraint))
+      .unwrap_or(false)
+  }
+}
+
+
 /// The SystemData for an IsLayoutSystem implementation.
 type LayoutSystemData<'a, T, R> = (
   Entities<'a>,
 
   Read<'a, R>,
+  Read<'a, EditVariables<T>>,
+  Write<'a, LayoutStats<T>>,
 
   ReadStorage<'a, Constraints<T>>,
   WriteStorage<'a, ElementBox>,
@@ -40,6 +116,8 @@ where
   fn solver_mut(&mut self) -> &mut Option<Solver<T>>;
   fn reader_mut(&mut self) -> &mut Option<ReaderId<ComponentEvent>>;
   fn cache_mut(&mut self) -> &mut HashMap<u32, Constraints<T>>;
+  /// The values last suggested for the EditVariables resource's variables.
+  fn edits_mut(&mut self) -> &mut HashMap<T, f64>;
 
   fn initial_constraints(&self) -> Constraints<T>;
   fn edit_variables(&self) -> Vec<T>;
@@ -65,6 +143,8 @@ where
     &mut self,
     (entities,
      edit_variable_values,
+     system_edit_variables,
+     mut stats,
      constraints,
      mut element_boxes,
      names,
@@ -77,18 +157,30 @@ where
       .unwrap_or({
         let mut solver =
           Solver::new();
-        solver
-          .add_constraints(self.initial_constraints().0)
-          .expect("Could not add initial constraints");
+        stats.initial_constraints =
+          self
+          .initial_constraints()
+          .0
+          .into_iter()
+          .filter(|c| {
+            match solver.add_constraint(c.clone()) {
+              Ok(()) => true,
+              Err(e) => {
+                error!("{}: could not add initial constraint {:?}: {:?}", self.name(), c, e);
+                false
+              }
+            }
+          })
+          .count();
 
         // Add the edit variables
         self
           .edit_variables()
           .iter()
           .for_each(|v| {
-            solver
-              .add_edit_variable(v.clone(), strength::STRONG)
-              .expect("Could not add edit variable");
+            if let Err(e) = solver.add_edit_variable(v.clone(), strength::STRONG) {
+              error!("{}: could not add edit variable {:?}: {:?}", self.name(), v, e);
+            }
           });
 
         solver
@@ -101,9 +193,9 @@ where
         let value =
           self
           .get_edit_variable_value(e, &edit_variable_values);
-        solver
-          .suggest_value(e.clone(), value)
-          .expect(&format!("Could not suggest value for edit variable {:?}", e));
+        if let Err(err) = solver.suggest_value(e.clone(), value) {
+          error!("{}: could not suggest value for edit variable {:?}: {:?}", self.name(), e, err);
+        }
       });
 
     let name:String =
@@ -111,37 +203,98 @@ where
         .name()
         .to_string();
 
+    // Sync the edit variables that systems drive
+    let edits =
+      self.edits_mut();
+    edits
+      .retain(|variable, _| {
+        let is_live =
+          system_edit_variables.0.contains_key(variable);
+        if !is_live {
+          if let Err(e) = solver.remove_edit_variable(variable.clone()) {
+            error!("{}: could not remove edit variable {:?}: {:?}", name, variable, e);
+          }
+        }
+        is_live
+      });
+    system_edit_variables
+      .0
+      .iter()
+      .for_each(|(variable, value)| {
+        if !edits.contains_key(variable) {
+          if let Err(e) = solver.add_edit_variable(variable.clone(), strength::STRONG) {
+            error!("{}: could not add edit variable {:?}: {:?}", name, variable, e);
+            return;
+          }
+        }
+        if edits.get(variable) != Some(value) {
+          if let Err(e) = solver.suggest_value(variable.clone(), *value) {
+            error!("{}: could not suggest value for edit variable {:?}: {:?}", name, variable, e);
+          }
+          edits
+            .insert(variable.clone(), *value);
+        }
+      });
+
     let reader =
       self
       .reader_mut()
       .as_mut()
       .expect("LayoutSystem has no constraint update reader");
 
-    let insert = |id: u32, the_solver: &mut Solver<T>, cache: &mut HashMap<u32, Constraints<T>>| {
+    // Constraints that the solver rejects (usually because they can't be
+    // satisfied along with the others) are reported and skipped, so only the
+    // ones that made it into the solver are cached
+    let insert = |
+      id: u32,
+      the_solver: &mut Solver<T>,
+      cache: &mut HashMap<u32, Constraints<T>>,
+      rejected: &mut HashMap<u32, Vec<Constraint<T>>>
+    | {
       let ent =
         entities
         .entity(id);
       let new_constraints =
-        constraints
-        .get(ent)
-        .expect("Could not find inserted constraint");
-      the_solver
-        .add_constraints(new_constraints.0.clone())
-        .expect(
-          &format!(
-            "{:?} could not add new constraints for: {:?}",
-            name,
-            names
-              .get(ent)
-              .map(|Name(s)| s.clone())
-              .unwrap_or("unnamed entity".to_string()),
-          )
-        );
+        match constraints.get(ent) {
+          Some(cs) => cs,
+          None => { return; }
+        };
+      let (added, skipped):(Vec<Constraint<T>>, Vec<Constraint<T>>) =
+        new_constraints
+        .0
+        .iter()
+        .cloned()
+        .partition(|c| {
+          match the_solver.add_constraint(c.clone()) {
+            Ok(()) => true,
+            Err(e) => {
+              error!(
+                "{}: skipping constraint {:?} on {}: {:?}",
+                name,
+                c,
+                entity_name(&names, ent),
+                e
+              );
+              false
+            }
+          }
+        });
       cache
-        .insert(id, new_constraints.clone());
+        .insert(id, Constraints(added));
+      if !skipped.is_empty() {
+        rejected
+          .insert(id, skipped);
+      }
     };
 
-    let remove = |id: u32, the_solver: &mut Solver<T>, cache: &mut HashMap<u32, Constraints<T>>| {
+    let remove = |
+      id: u32,
+      the_solver: &mut Solver<T>,
+      cache: &mut HashMap<u32, Constraints<T>>,
+      rejected: &mut HashMap<u32, Vec<Constraint<T>>>
+    | {
+      rejected
+        .remove(&id);
       cache
         .remove(&id)
         .into_iter()
@@ -149,9 +302,9 @@ where
           cs.0
             .into_iter()
             .for_each(|c| {
-              the_solver
-                .remove_constraint(&c)
-                .expect("Could not remove constraint");
+              if let Err(e) = the_solver.remove_constraint(&c) {
+                error!("{}: could not remove constraint {:?}: {:?}", name, c, e);
+              }
             });
         });
     };
@@ -162,27 +315,42 @@ where
       .for_each(|event| {
         match event {
           ComponentEvent::Inserted(id) => {
-            remove(*id, &mut solver, self.cache_mut());
-            insert(*id, &mut solver, self.cache_mut());
+            debug!("{}: inserted constraints on {}", name, entity_name(&names, entities.entity(*id)));
+            remove(*id, &mut solver, self.cache_mut(), &mut stats.rejected);
+            insert(*id, &mut solver, self.cache_mut(), &mut stats.rejected);
           }
           ComponentEvent::Modified(id) => {
-            remove(*id, &mut solver, self.cache_mut());
-            insert(*id, &mut solver, self.cache_mut());
+            debug!("{}: modified constraints on {}", name, entity_name(&names, entities.entity(*id)));
+            remove(*id, &mut solver, self.cache_mut(), &mut stats.rejected);
+            insert(*id, &mut solver, self.cache_mut(), &mut stats.rejected);
           }
           ComponentEvent::Removed(id) => {
-            remove(*id, &mut solver, self.cache_mut());
+            debug!("{}: removed constraints on {}", name, entity_name(&names, entities.entity(*id)));
+            remove(*id, &mut solver, self.cache_mut(), &mut stats.rejected);
           }
         }
       });
 
     // Fetch changes from the solver and input them into the ECS
-    solver
-      .fetch_changes()
-      .into_iter()
+    let changes =
+      solver
+      .fetch_changes();
+    if !changes.is_empty() {
+      debug!("{}: solver changed {} variables", name, changes.len());
+    }
+    changes
+      .iter()
       .for_each(|(variable, value)| {
         self.update_variable_value(&mut element_boxes, &names, variable.clone(), *value)
       });
 
+    stats.entity_constraints =
+      self
+      .cache_mut()
+      .values()
+      .map(|cs| cs.0.len())
+      .sum();
+
     *self.solver_mut() =
       Some(solver);
   }
@@ -195,7 +363,8 @@ where
 {
   pub solver: Option<Solver<T>>,
   pub reader: Option<ReaderId<ComponentEvent>>,
-  pub cache: HashMap<u32, Constraints<T>>
+  pub cache: HashMap<u32, Constraints<T>>,
+  pub edits: HashMap<T, f64>
 }
 
 
@@ -207,7 +376,8 @@ where
     LayoutSystem {
       solver: None,
       reader: None,
-      cache: HashMap::new()
+      cache: HashMap::new(),
+      edits: HashMap::new()
     }
   }
 }
@@ -246,6 +416,10 @@ impl IsLayoutSystem<VariableX, WindowSize> for LayoutSystem<VariableX> {
     &mut self.cache
   }
 
+  fn edits_mut(&mut self) -> &mut HashMap<VariableX, f64> {
+    &mut self.edits
+  }
+
   fn initial_constraints(&self) -> Constraints<VariableX> {
     Constraints(vec![VariableX::Left(None).is(0)])
   }
@@ -265,7 +439,7 @@ impl IsLayoutSystem<VariableX, WindowSize> for LayoutSystem<VariableX> {
   fn update_variable_value(
     &self,
     element_boxes: &mut WriteStorage<ElementBox>,
-    _names: &ReadStorage<Name>,
+    names: &ReadStorage<Name>,
     var: VariableX,
     val: f64
   ) {
@@ -297,7 +471,7 @@ impl IsLayoutSystem<VariableX, WindowSize> for LayoutSystem<VariableX> {
       }
       _ => {}
     };
-    //println!("layout: {} = {:?}", var.to_pathy_string(&_names), val);
+    trace!("layout: {} = {:?}", var.to_pathy_string(names), val);
   }
 }
 
@@ -319,6 +493,10 @@ impl IsLayoutSystem<VariableY, WindowSize> for LayoutSystem<VariableY> {
     &mut self.cache
   }
 
+  fn edits_mut(&mut self) -> &mut HashMap<VariableY, f64> {
+    &mut self.edits
+  }
+
   fn initial_constraints(&self) -> Constraints<VariableY> {
     Constraints(vec![VariableY::Top(None).is(0)])
   }
@@ -338,7 +516,7 @@ impl IsLayoutSystem<VariableY, WindowSize> for LayoutSystem<VariableY> {
   fn update_variable_value(
     &self,
     element_boyes: &mut WriteStorage<ElementBox>,
-    _names: &ReadStorage<Name>,
+    names: &ReadStorage<Name>,
     var: VariableY,
     val: f64
   ) {
@@ -367,7 +545,7 @@ impl IsLayoutSystem<VariableY, WindowSize> for LayoutSystem<VariableY> {
       }
       _ => {}
     };
-    //println!("layout: {} = {:?}", var.to_pathy_string(&_names), val);
+    trace!("layout: {} = {:?}", var.to_pathy_string(names), val);
   }
 }
 
@@ -388,6 +566,10 @@ impl IsLayoutSystem<VariableZ, WindowSize> for LayoutSystem<VariableZ> {
     &mut self.cache
   }
 
+  fn edits_mut(&mut self) -> &mut HashMap<VariableZ, f64> {
+    &mut self.edits
+  }
+
   fn initial_constraints(&self) -> Constraints<VariableZ> {
     Constraints(vec![])
   }
@@ -405,7 +587,7 @@ impl IsLayoutSystem<VariableZ, WindowSize> for LayoutSystem<VariableZ> {
   fn update_variable_value(
     &self,
     element_boxes: &mut WriteStorage<ElementBox>,
-    _names: &ReadStorage<Name>,
+    names: &ReadStorage<Name>,
     VariableZ(ent): VariableZ,
     val: f64
   ) {
@@ -418,11 +600,6 @@ impl IsLayoutSystem<VariableZ, WindowSize> for LayoutSystem<VariableZ> {
     element_boxes
       .insert(ent, el)
       .expect("Could not update element box x");
-    let _name =
-      _names
-      .get(ent)
-      .map(|Name(s)| s.clone())
-      .unwrap_or("unknown".to_string());
-    //println!("layout: {}.z = {:?}", _name, val);
+    trace!("layout: {}.z = {:?}", entity_name(names, ent), val);
   }
 }
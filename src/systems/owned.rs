@@ -0,0 +1,199 @@
+use specs::prelude::*;
+use cassowary::*;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use super::super::components::Constraints;
+
+
+/// One constraint per entity that a system keeps in the entity's own
+/// Constraints, next to whatever else is in there. Anything can replace an
+/// entity's Constraints, so the constraint is put back whenever it goes
+/// missing, and it is taken back out once the system is done with the
+/// entity. Each constraint is made from a key, eg an aspect ratio, and is
+/// only made again when the key changes.
+pub struct OwnedConstraints<T, K> {
+  applied: HashMap<Entity, (K, Constraint<T>)>
+}
+
+
+impl<T, K> Default for OwnedConstraints<T, K> {
+  fn default() -> Self {
+    OwnedConstraints {
+      applied: HashMap::new()
+    }
+  }
+}
+
+
+impl<T, K> OwnedConstraints<T, K>
+where
+  T: Any + Clone + Debug + Eq + Hash + Send + Sync,
+  K: PartialEq
+{
+  pub fn new() -> OwnedConstraints<T, K> {
+    OwnedConstraints::default()
+  }
+
+  /// Make sure the entity's Constraints hold the constraint for `key`,
+  /// making it with `make` if they don't.
+  pub fn keep<F>(
+    &mut self,
+    ent: Entity,
+    key: K,
+    constraints: &mut WriteStorage<Constraints<T>>,
+    make: F
+  )
+  where
+    F: FnOnce() -> Constraint<T>
+  {
+    let mut cs =
+      constraints
+      .get(ent)
+      .cloned()
+      .unwrap_or(Constraints(vec![]));
+    let is_applied =
+      self
+      .applied
+      .get(&ent)
+      .map(|(applied_key, c)| *applied_key == key && cs.0.contains(c))
+      .unwrap_or(false);
+    if is_applied {
+      return;
+    }
+    self
+      .applied
+      .remove(&ent)
+      .into_iter()
+      .for_each(|(_, old)| {
+        cs.0.retain(|c| *c != old);
+      });
+    let constraint =
+      make();
+    cs.0.push(constraint.clone());
+    constraints
+      .insert(ent, cs)
+      .expect("Could not insert owned constraints");
+    self
+      .applied
+      .insert(ent, (key, constraint));
+  }
+
+  /// Take the constraint back out of the entities that `wants` no longer
+  /// holds for, and forget the entities that are gone.
+  pub fn release_unless<F>(
+    &mut self,
+    entities: &Entities,
+    constraints: &mut WriteStorage<Constraints<T>>,
+    wants: F
+  )
+  where
+    F: Fn(Entity) -> bool
+  {
+    let gone:Vec<Entity> =
+      self
+      .applied
+      .keys()
+      .filter(|ent| !entities.is_alive(**ent) || !wants(**ent))
+      .cloned()
+      .collect();
+    for ent in gone {
+      let (_, old) =
+        self
+        .applied
+        .remove(&ent)
+        .expect("Could not find an owned constraint");
+      if entities.is_alive(ent) {
+        let may_cs =
+          constraints
+          .get(ent)
+          .cloned();
+        if let Some(mut cs) = may_cs {
+          cs.0.retain(|c| *c != old);
+          constraints
+            .insert(ent, cs)
+            .expect("Could not remove owned constraints");
+        }
+      }
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::super::components::*;
+
+  fn setup() -> (World, Entity) {
+    let mut world = World::new();
+    world.register::<Constraints<VariableY>>();
+    let ent = world.create_entity().build();
+    (world, ent)
+  }
+
+  fn constraints_of(world: &World, ent: Entity) -> Vec<Constraint<VariableY>> {
+    world
+      .read_storage::<Constraints<VariableY>>()
+      .get(ent)
+      .map(|cs| cs.0.clone())
+      .unwrap_or(vec![])
+  }
+
+  #[test]
+  fn owned_constraints_sit_next_to_others_and_follow_their_key() {
+    let (world, ent) = setup();
+    let other = ent.top().is(10);
+    world
+      .write_storage::<Constraints<VariableY>>()
+      .insert(ent, Constraints(vec![other.clone()]))
+      .unwrap();
+    let mut owned:OwnedConstraints<VariableY, f64> = OwnedConstraints::new();
+
+    owned.keep(ent, 1.0, &mut world.write_storage(), || ent.height().is(10));
+    assert_eq!(constraints_of(&world, ent), vec![other.clone(), ent.height().is(10)]);
+
+    // The same key leaves things be
+    owned.keep(ent, 1.0, &mut world.write_storage(), || panic!("Made a kept constraint again"));
+
+    owned.keep(ent, 2.0, &mut world.write_storage(), || ent.height().is(20));
+    assert_eq!(constraints_of(&world, ent), vec![other, ent.height().is(20)]);
+  }
+
+  #[test]
+  fn owned_constraints_are_put_back_when_replaced() {
+    let (world, ent) = setup();
+    let mut owned:OwnedConstraints<VariableY, f64> = OwnedConstraints::new();
+    owned.keep(ent, 1.0, &mut world.write_storage(), || ent.height().is(10));
+
+    let replacement = ent.top().is(0);
+    world
+      .write_storage::<Constraints<VariableY>>()
+      .insert(ent, Constraints(vec![replacement.clone()]))
+      .unwrap();
+    owned.keep(ent, 1.0, &mut world.write_storage(), || ent.height().is(10));
+    assert_eq!(constraints_of(&world, ent), vec![replacement, ent.height().is(10)]);
+  }
+
+  #[test]
+  fn owned_constraints_are_released() {
+    let (mut world, ent) = setup();
+    let dead = world.create_entity().build();
+    let mut owned:OwnedConstraints<VariableY, f64> = OwnedConstraints::new();
+    owned.keep(ent, 1.0, &mut world.write_storage(), || ent.height().is(10));
+    owned.keep(dead, 1.0, &mut world.write_storage(), || dead.height().is(10));
+    world.delete_entity(dead).unwrap();
+    world.maintain();
+
+    owned.release_unless(&world.entities(), &mut world.write_storage(), |_| true);
+    assert_eq!(owned.applied.keys().collect::<Vec<_>>(), vec![&ent]);
+    assert_eq!(constraints_of(&world, ent), vec![ent.height().is(10)]);
+
+    owned.release_unless(&world.entities(), &mut world.write_storage(), |_| false);
+    assert!(owned.applied.is_empty());
+    assert!(constraints_of(&world, ent).is_empty());
+  }
+}
@@ -0,0 +1,425 @@
+use specs::prelude::*;
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+use cassowary::strength::*;
+
+use super::super::picture::Picture;
+use super::super::components::*;
+use super::super::UI;
+use super::layout::EditVariables;
+
+
+/// How long a progress bar is unless it is given a size.
+const DEFAULT_WIDTH: u32 = 200;
+/// How tall a progress bar is unless it is given a size (or its text is
+/// taller).
+const DEFAULT_HEIGHT: u32 = 12;
+/// The fraction of the track covered by an indeterminate bar's moving fill.
+const SEGMENT: f64 = 0.3;
+/// Seconds it takes an indeterminate bar's fill to cross the track one way.
+const PERIOD: f64 = 1.5;
+
+
+/// The progress kept between 0 and 1. Values that aren't numbers are at 0.
+fn progress(value: f64) -> f64 {
+  // f64::max ignores NaN
+  f64::min(1.0, f64::max(0.0, value))
+}
+
+
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct ProgressBar {
+  /// From 0 to 1, or None when indeterminate.
+  value: Option<f64>,
+  track: Entity,
+  fill: Entity,
+  /// A hidden spacer between the left of the track and the fill, as wide as
+  /// an indeterminate bar's segment is across.
+  offset: Entity,
+  label: Entity,
+  static_constraints: Entity,
+  dynamic_constraints: Entity,
+  label_constraints: Entity,
+  /// How far an indeterminate bar's fill is through a trip across the track
+  /// and back, from 0 to 1.
+  phase: f64,
+  /// The value that the fill's width is currently constrained at, or None
+  /// when it is the width of the segment.
+  applied: Option<f64>
+}
+
+
+impl ProgressBar {
+  /// The progress from 0 to 1, or None when indeterminate.
+  pub fn value(&self) -> Option<f64> {
+    self.value
+  }
+
+  /// Set the progress, kept between 0 and 1.
+  pub fn set_value(&mut self, value: f64) {
+    self.value =
+      Some(progress(value));
+  }
+
+  /// Show activity without a known amount of progress.
+  pub fn set_indeterminate(&mut self) {
+    self.value = None;
+  }
+
+  /// The entity of the text drawn over the bar.
+  pub fn label(&self) -> Entity {
+    self.label
+  }
+
+  /// Change the text drawn over the bar of the given progress bar entity.
+  pub fn set_text(ui: &mut UI, bar: Entity, text: &Text) {
+    let progress =
+      ui.get::<ProgressBar>(bar)
+      .expect("Could not find progress bar");
+    let size =
+      ProgressBar::label_size(ui, text);
+    ElementBuilder::new()
+      .text(text)
+      .update(ui, progress.label);
+    ProgressBar::label_constraints(bar, progress.label, size)
+      .update(ui, progress.label_constraints);
+  }
+
+  fn track() -> Picture {
+    Picture::new()
+      .set_color(0, 0, 0, 64)
+      .fill_rect(0, 0, 1, 1)
+  }
+
+  fn fill((r, g, b, a): (u8, u8, u8, u8)) -> Picture {
+    Picture::new()
+      .set_color(r, g, b, a)
+      .fill_rect(0, 0, 1, 1)
+  }
+
+  /// Without text the label takes up no room, so it doesn't hold the bar
+  /// open.
+  fn label_size(ui: &UI, text: &Text) -> (u32, u32) {
+    if text.text.is_empty() {
+      (0, 0)
+    } else {
+      ui.measure_text(text)
+        .unwrap_or_else(|e| {
+          error!("Could not measure progress bar text {:?}: {}", text.text, e);
+          (0, 0)
+        })
+    }
+  }
+
+  /// Center the text over the bar.
+  fn label_constraints(
+    bar: Entity,
+    label: Entity,
+    (width, height): (u32, u32)
+  ) -> ElementBuilder {
+    ElementBuilder::new()
+      .name("ProgressBar label constraints")
+      .x_constraints(vec![
+        label.center_x().is(bar.center_x()),
+        label.width().is(width)
+      ])
+      .y_constraints(vec![
+        label.center_y().is(bar.center_y()),
+        label.height().is(height),
+        bar.height().is_ge(label.height())
+      ])
+  }
+
+  /// Fill `value` of the track, or when indeterminate a segment of it. The
+  /// fill starts where the offset spacer ends.
+  fn new_dynamic_constraints(
+    track: Entity,
+    fill: Entity,
+    value: Option<f64>
+  ) -> Constraints<VariableX> {
+    Constraints(vec![
+      fill.width().is(track.width() * value.unwrap_or(SEGMENT))
+    ])
+  }
+
+  /// How far across the track an indeterminate bar's segment starts, as a
+  /// fraction of the track's width. The segment goes from the left edge to
+  /// the right edge in the first half of the phase and comes back in the
+  /// second.
+  fn segment_start(phase: f64) -> f64 {
+    let there_and_back =
+      if phase < 0.5 {
+        phase * 2.0
+      } else {
+        2.0 - phase * 2.0
+      };
+    there_and_back * (1.0 - SEGMENT)
+  }
+}
+
+
+pub struct ProgressBarBuilder {
+  value: Option<f64>,
+  text: Text,
+  color: (u8, u8, u8, u8)
+}
+
+
+impl ProgressBarBuilder {
+  /// An empty, determinate progress bar.
+  pub fn new() -> ProgressBarBuilder {
+    ProgressBarBuilder {
+      value: Some(0.0),
+      text: Text::new("").color(0x33, 0x33, 0x33, 255),
+      color: (0x33, 0x66, 0xff, 255)
+    }
+  }
+
+  /// The progress to begin with, from 0 to 1.
+  pub fn value(self, value: f64) -> Self {
+    let mut pb = self;
+    pb.value = Some(progress(value));
+    pb
+  }
+
+  /// Animate a segment back and forth instead of showing a value.
+  pub fn indeterminate(self) -> Self {
+    let mut pb = self;
+    pb.value = None;
+    pb
+  }
+
+  /// Text to draw over the bar, eg "Loading..." or "75/100".
+  pub fn text(self, text: &Text) -> Self {
+    let mut pb = self;
+    pb.text = text.clone();
+    pb
+  }
+
+  /// The color of the fill.
+  pub fn color(self, r: u8, g: u8, b: u8, a: u8) -> Self {
+    let mut pb = self;
+    pb.color = (r, g, b, a);
+    pb
+  }
+
+  pub fn build(self, ui: &mut UI) -> Entity {
+    let label_size =
+      ProgressBar::label_size(ui, &self.text);
+    let bar =
+      ElementBuilder::new()
+      .name("ProgressBar")
+      .build(ui);
+    let track =
+      ElementBuilder::new()
+      .picture(&ProgressBar::track())
+      .name("ProgressBar track")
+      .parent(bar)
+      .build(ui);
+    let fill =
+      ElementBuilder::new()
+      .picture(&ProgressBar::fill(self.color))
+      .name("ProgressBar fill")
+      .parent(bar)
+      .build(ui);
+    let offset =
+      ElementBuilder::new()
+      .name("ProgressBar offset")
+      .parent(bar)
+      .build(ui);
+    ui.update(offset, Some(Invisible));
+    let label =
+      ElementBuilder::new()
+      .text(&self.text)
+      .name("ProgressBar label")
+      .parent(bar)
+      .build(ui);
+
+    let static_constraints =
+      ElementBuilder::new()
+      .name("ProgressBar static constraints")
+      .x_constraints(vec![
+        track.left().is(bar.left()),
+        track.width().is(bar.width()),
+        offset.left().is(track.left()),
+        fill.left().is(offset.right()),
+        bar.width().is(DEFAULT_WIDTH).with_strength(WEAK)
+      ])
+      .y_constraints(vec![
+        track.top().is(bar.top()),
+        track.height().is(bar.height()),
+        fill.top().is(track.top()),
+        fill.height().is(track.height()),
+        bar.height().is(DEFAULT_HEIGHT).with_strength(WEAK)
+      ])
+      .z_constraints(vec![
+        track.z_index().is(bar.z_index()),
+        fill.z_index().is(bar.z_index() + 1),
+        label.z_index().is(bar.z_index() + 2)
+      ])
+      .build(ui);
+    let label_constraints =
+      ProgressBar::label_constraints(bar, label, label_size)
+      .build(ui);
+    let dynamic_constraints =
+      ElementBuilder::new()
+      .name("ProgressBar dynamic constraints")
+      .x_constraints(ProgressBar::new_dynamic_constraints(track, fill, self.value).0)
+      .build(ui);
+    // Give the offset to the solver straight away, rather than leaving it
+    // free until the ProgressSystem first runs
+    ui.world
+      .write_resource::<EditVariables<VariableX>>()
+      .0
+      .insert(VariableX::Width(Some(offset)), 0.0);
+
+    ui.update(bar, Some(ClipChildren));
+    ui.update(
+      bar,
+      Some(
+        ProgressBar {
+          value: self.value,
+          track,
+          fill,
+          offset,
+          label,
+          static_constraints,
+          dynamic_constraints,
+          label_constraints,
+          phase: 0.0,
+          applied: self.value
+        }
+      )
+    );
+
+    bar
+  }
+}
+
+
+/// Keeps progress bars' fills in step with their values and animates
+/// indeterminate bars. The segment is moved by suggesting the width of the
+/// bar's offset spacer, which is an edit variable, so the solver's
+/// constraints only change along with the value.
+pub struct ProgressSystem {
+  last_run: Option<Instant>,
+  /// The offset spacers whose widths are being driven.
+  offsets: HashSet<Entity>
+}
+
+
+impl ProgressSystem {
+  pub fn new() -> ProgressSystem {
+    ProgressSystem {
+      last_run: None,
+      offsets: HashSet::new()
+    }
+  }
+}
+
+
+impl<'a> System<'a> for ProgressSystem {
+  type SystemData = (
+    ReadStorage<'a, ElementBox>,
+    Write<'a, EditVariables<VariableX>>,
+    WriteStorage<'a, Constraints<VariableX>>,
+    WriteStorage<'a, ProgressBar>
+  );
+
+  fn run(
+    &mut self,
+    (element_boxes,
+     mut edits,
+     mut x_constraints,
+     mut bars
+    ): Self::SystemData
+  ) {
+    let now =
+      Instant::now();
+    let dt =
+      self
+      .last_run
+      .map(|last| now.duration_since(last).as_secs_f64())
+      .unwrap_or(0.0);
+    self.last_run =
+      Some(now);
+
+    let mut offsets =
+      HashSet::new();
+    for bar in (&mut bars).join() {
+      let start =
+        if bar.value.is_none() {
+          bar.phase =
+            (bar.phase + dt / (PERIOD * 2.0)) % 1.0;
+          ProgressBar::segment_start(bar.phase)
+        } else {
+          0.0
+        };
+      let track_width =
+        element_boxes
+        .get(bar.track)
+        .map(|el| el.width)
+        .unwrap_or(0);
+      edits
+        .0
+        .insert(VariableX::Width(Some(bar.offset)), track_width as f64 * start);
+      offsets.insert(bar.offset);
+
+      if bar.applied != bar.value {
+        let xs =
+          ProgressBar::new_dynamic_constraints(bar.track, bar.fill, bar.value);
+        x_constraints
+          .insert(bar.dynamic_constraints, xs)
+          .expect("Could not update progress bar fill constraints");
+        bar.applied = bar.value;
+      }
+    }
+
+    // Stop driving the offsets of progress bars that are gone
+    for offset in self.offsets.difference(&offsets) {
+      edits
+        .0
+        .remove(&VariableX::Width(Some(*offset)));
+    }
+    self.offsets = offsets;
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn progress_is_between_zero_and_one() {
+    assert_eq!(progress(0.25), 0.25);
+    assert_eq!(progress(-1.0), 0.0);
+    assert_eq!(progress(2.0), 1.0);
+    assert_eq!(progress(std::f64::NAN), 0.0);
+    assert_eq!(progress(std::f64::INFINITY), 1.0);
+
+    let mut bar =
+      ProgressBarBuilder::new()
+      .value(std::f64::NAN);
+    assert_eq!(bar.value, Some(0.0));
+    bar = bar.value(0.5);
+    assert_eq!(bar.value, Some(0.5));
+  }
+
+  #[test]
+  fn segment_goes_across_and_back() {
+    let far = 1.0 - SEGMENT;
+    assert_eq!(ProgressBar::segment_start(0.0), 0.0);
+    assert_eq!(ProgressBar::segment_start(0.25), far * 0.5);
+    assert_eq!(ProgressBar::segment_start(0.5), far);
+    assert_eq!(ProgressBar::segment_start(0.75), far * 0.5);
+    // The segment stays inside the track the whole way
+    assert!((0..100).all(|i| {
+      let start = ProgressBar::segment_start(i as f64 / 100.0);
+      start >= 0.0 && start + SEGMENT <= 1.0 + std::f64::EPSILON
+    }));
+  }
+}
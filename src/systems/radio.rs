@@ -0,0 +1,320 @@
+use specs::prelude::*;
+
+use super::super::picture::Picture;
+use super::super::components::*;
+use super::super::stack::VStack;
+use super::super::UI;
+use super::checkbox::*;
+use super::event::{push_event, Key, Keys, Mouse};
+use super::focus::{Focus, Focusable};
+
+
+/// Add a filled circle `d` pixels across at x, y.
+fn disc(picture: Picture, x: u32, y: u32, d: u32) -> Picture {
+  let r = d as f64 / 2.0;
+  (0 .. d)
+    .fold(picture, |pic, row| {
+      let dy = row as f64 + 0.5 - r;
+      let w = (2.0 * f64::max(0.0, r * r - dy * dy).sqrt()).round() as u32;
+      pic.fill_rect(x + (d - w) / 2, y + row, w, 1)
+    })
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+struct RadioOption {
+  ent: Entity,
+  circle: Entity,
+  dot: Entity,
+  label: Entity,
+  static_constraints: Entity,
+  /// Whether the mouse went down on the option and hasn't come up yet.
+  pressed: bool,
+  focused: bool
+}
+
+
+impl RadioOption {
+  fn circle(focused: bool) -> Picture {
+    let (r, g, b) =
+      outline_color(focused);
+    let pic =
+      disc(Picture::new().set_color(r, g, b, 255), 0, 0, INDICATOR_SIZE)
+      .set_color(255, 255, 255, 255);
+    disc(pic, 2, 2, INDICATOR_SIZE - 4)
+  }
+
+  fn dot() -> Picture {
+    disc(Picture::new().set_color(0x33, 0x33, 0x33, 255), 0, 0, INDICATOR_SIZE - 8)
+  }
+}
+
+
+/// A column of options, at most one of which is selected. The selected
+/// option's entity is marked Checked.
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct RadioGroup {
+  options: Vec<RadioOption>,
+  selected: Option<usize>
+}
+
+
+impl RadioGroup {
+  /// The index of the selected option.
+  pub fn selected(&self) -> Option<usize> {
+    self.selected
+  }
+
+  /// The entity of each option, in order.
+  pub fn options(&self) -> Vec<Entity> {
+    self
+      .options
+      .iter()
+      .map(|opt| opt.ent)
+      .collect()
+  }
+
+  /// Select an option, or nothing.
+  pub fn select(&mut self, may_index: Option<usize>) {
+    self.selected =
+      may_index
+      .filter(|index| *index < self.options.len());
+  }
+}
+
+
+pub struct RadioGroupBuilder {
+  texts: Vec<Text>,
+  selected: Option<usize>
+}
+
+
+impl RadioGroupBuilder {
+  pub fn new(options: &[&str]) -> RadioGroupBuilder {
+    RadioGroupBuilder {
+      texts:
+        options
+        .iter()
+        .map(|s| label_text(s))
+        .collect(),
+      selected: None
+    }
+  }
+
+  /// The option selected to begin with. Defaults to none.
+  pub fn selected(self, index: usize) -> Self {
+    let mut rb = self;
+    rb.selected = Some(index);
+    rb
+  }
+
+  fn build_option(ui: &mut UI, text: &Text) -> RadioOption {
+    let label_size =
+      ui.measure_text(text)
+      .unwrap_or_else(|e| {
+        error!("Could not measure radio option text {:?}: {}", text.text, e);
+        (0, 0)
+      });
+    let ent =
+      ElementBuilder::new()
+      .name("RadioGroup option")
+      .build(ui);
+    let circle =
+      ElementBuilder::new()
+      .picture(&RadioOption::circle(false))
+      .name("RadioGroup option circle")
+      .parent(ent)
+      .build(ui);
+    let dot =
+      ElementBuilder::new()
+      .picture(&RadioOption::dot())
+      .name("RadioGroup option dot")
+      .parent(ent)
+      .build(ui);
+    let label =
+      ElementBuilder::new()
+      .text(text)
+      .name("RadioGroup option label")
+      .parent(ent)
+      .build(ui);
+    let cs =
+      indicator_constraints(ent, circle, dot, 4, label, label_size);
+    let static_constraints =
+      ElementBuilder::new()
+      .name("RadioGroup option static constraints")
+      .x_constraints(cs.x)
+      .y_constraints(cs.y)
+      .z_constraints(cs.z)
+      .build(ui);
+    ui.update(ent, Some(Focusable));
+    ui.update(dot, Some(Invisible));
+    RadioOption {
+      ent,
+      circle,
+      dot,
+      label,
+      static_constraints,
+      pressed: false,
+      focused: false
+    }
+  }
+
+  pub fn build(self, ui: &mut UI) -> Entity {
+    let options:Vec<RadioOption> =
+      self
+      .texts
+      .iter()
+      .map(|text| RadioGroupBuilder::build_option(ui, text))
+      .collect();
+    let group =
+      VStack::new(options.iter().map(|opt| opt.ent).collect())
+      .spacing(LABEL_GAP as f64)
+      .build(ui);
+    ElementBuilder::new()
+      .name("RadioGroup")
+      .update(ui, group);
+
+    let mut radio_group =
+      RadioGroup {
+        options,
+        selected: None
+      };
+    radio_group
+      .select(self.selected);
+    ui.update(group, Some(radio_group));
+
+    group
+  }
+}
+
+
+/// Selects radio options when they are clicked (labels included) or focused
+/// and activated with Space or Enter. The arrow keys move the focus and the
+/// selection through a group.
+pub struct RadioGroupSystem;
+
+
+impl RadioGroupSystem {
+  pub fn new() -> RadioGroupSystem {
+    RadioGroupSystem
+  }
+}
+
+
+impl<'a> System<'a> for RadioGroupSystem {
+  type SystemData = (
+    Entities<'a>,
+    Write<'a, Focus>,
+    Read<'a, Keys>,
+    Read<'a, Mouse>,
+    WriteStorage<'a, Checked>,
+    WriteStorage<'a, Events>,
+    WriteStorage<'a, Invisible>,
+    WriteStorage<'a, Picture>,
+    WriteStorage<'a, RadioGroup>
+  );
+
+  fn run(
+    &mut self,
+    (entities, mut focus, keys, mouse, mut checked, mut events, mut invisibles, mut pictures, mut groups): Self::SystemData
+  ) {
+    for (ent, group) in (&entities, &mut groups).join() {
+      let mut may_select:Option<usize> = None;
+
+      group
+        .options
+        .iter_mut()
+        .enumerate()
+        .for_each(|(index, opt)| {
+          let ent =
+            opt.ent;
+          let has_event = |event: Event| {
+            events
+              .get(ent)
+              .map(|evs| evs.0.contains(&event))
+              .unwrap_or(false)
+          };
+          if has_event(Event::MouseDown) {
+            opt.pressed = true;
+          }
+          // Only a press and a release both on this option is a click
+          let was_clicked =
+            opt.pressed && has_event(Event::MouseUp);
+          if !mouse.left_btn_down {
+            opt.pressed = false;
+          }
+          let was_activated =
+            focus.0 == Some(opt.ent) && has_activation_key(&keys);
+          if was_clicked || was_activated {
+            may_select = Some(index);
+          }
+        });
+
+      // Arrow keys move through the group from the focused option
+      let may_focused =
+        group
+        .options
+        .iter()
+        .position(|opt| focus.0 == Some(opt.ent));
+      if let Some(focused) = may_focused {
+        let last =
+          group.options.len() - 1;
+        let moved =
+          keys
+          .0
+          .iter()
+          .fold(focused, |index, key| {
+            match key {
+              Key::Up | Key::Left => index.saturating_sub(1),
+              Key::Down | Key::Right => usize::min(last, index + 1),
+              _ => index
+            }
+          });
+        if moved != focused {
+          focus.0 = Some(group.options[moved].ent);
+          may_select = Some(moved);
+        }
+      }
+
+      if let Some(index) = may_select {
+        if group.selected != Some(index) {
+          group.selected = Some(index);
+          push_event(&mut events, ent, Event::Selected);
+        }
+      }
+
+      // Keep the options' Checked and dots in step with the selection, however
+      // it was made
+      let selected =
+        group.selected;
+      for (index, opt) in group.options.iter_mut().enumerate() {
+        let is_selected =
+          selected == Some(index);
+        if is_selected && !checked.contains(opt.ent) {
+          checked
+            .insert(opt.ent, Checked)
+            .expect("Could not check radio option");
+        } else if !is_selected && checked.contains(opt.ent) {
+          checked.remove(opt.ent);
+        }
+        if is_selected && invisibles.contains(opt.dot) {
+          invisibles.remove(opt.dot);
+        } else if !is_selected && !invisibles.contains(opt.dot) {
+          invisibles
+            .insert(opt.dot, Invisible)
+            .expect("Could not hide radio option dot");
+        }
+
+        let is_focused =
+          focus.0 == Some(opt.ent);
+        if opt.focused != is_focused {
+          pictures
+            .insert(opt.circle, RadioOption::circle(is_focused))
+            .expect("Could not update radio option circle");
+          opt.focused = is_focused;
+        }
+      }
+    }
+  }
+}
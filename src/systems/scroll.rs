@@ -0,0 +1,736 @@
+use specs::prelude::*;
+
+use std::time::Instant;
+
+use super::super::picture::Picture;
+use super::super::components::*;
+use super::super::UI;
+use super::event::{Mouse, MouseWheel};
+use super::layout::EditVariables;
+
+
+/// Pixels scrolled per click of the mouse wheel.
+const WHEEL_STEP: f64 = 40.0;
+/// How quickly kinetic scrolling slows down - the fraction of its speed lost
+/// each second.
+const FRICTION: f64 = 5.0;
+/// Kinetic scrolling stops below this many pixels per second.
+const MIN_SPEED: f64 = 10.0;
+/// The thickness of a scrollbar.
+const SCROLLBAR_SIZE: u32 = 8;
+/// Scrollbars are drawn this far above their scroll view.
+const SCROLLBAR_Z: i32 = 1000;
+/// Thumbs never get shorter than this fraction of their track.
+const MIN_THUMB: f64 = 0.05;
+
+
+/// How far content `content_len` long can scroll in a view `view_len` long.
+fn scroll_range(view_len: u32, content_len: u32) -> f64 {
+  f64::max(0.0, content_len as f64 - view_len as f64)
+}
+
+
+/// The offset and velocity after the wheel moves `delta` pixels. A kinetic
+/// view is given enough speed to glide that far instead of jumping there.
+fn wheel_scroll(offset: f64, velocity: f64, delta: f64, kinetic: bool) -> (f64, f64) {
+  if kinetic {
+    (offset, velocity + delta * FRICTION)
+  } else {
+    (offset + delta, velocity)
+  }
+}
+
+
+/// The offset and velocity of a kinetic view after `dt` seconds of gliding.
+fn glide(offset: f64, velocity: f64, dt: f64) -> (f64, f64) {
+  let offset =
+    offset + velocity * dt;
+  let velocity =
+    velocity * f64::max(0.0, 1.0 - FRICTION * dt);
+  if velocity.abs() < MIN_SPEED {
+    (offset, 0.0)
+  } else {
+    (offset, velocity)
+  }
+}
+
+
+/// The offset after a thumb that could move `travel` pixels along its track
+/// has been dragged `moved` pixels, or None if the thumb can't move.
+fn dragged_offset(offset_start: f64, moved: f64, max_offset: f64, travel: f64) -> Option<f64> {
+  if travel > 0.0 {
+    Some(offset_start + moved * max_offset / travel)
+  } else {
+    None
+  }
+}
+
+
+/// The offset kept inside the content.
+fn clamp_offset(offset: f64, max_offset: f64) -> f64 {
+  f64::min(max_offset, f64::max(0.0, offset))
+}
+
+
+/// The size of a thumb as a fraction of its track, and how far along the
+/// track it is, from 0 to 1.
+fn thumb_placement(view_len: u32, content_len: u32, offset: f64, max_offset: f64) -> (f64, f64) {
+  let ratio =
+    if content_len > 0 {
+      f64::max(MIN_THUMB, f64::min(1.0, view_len as f64 / content_len as f64))
+    } else {
+      1.0
+    };
+  let position =
+    if max_offset > 0.0 {
+      offset / max_offset
+    } else {
+      0.0
+    };
+  (ratio, position)
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Axis {
+  Horizontal,
+  Vertical
+}
+
+
+impl Axis {
+  fn index(&self) -> usize {
+    match self {
+      Axis::Horizontal => 0,
+      Axis::Vertical => 1
+    }
+  }
+
+  /// The start and length of the box along this axis.
+  fn span(&self, el: &ElementBox) -> (i32, u32) {
+    match self {
+      Axis::Horizontal => (el.x, el.width),
+      Axis::Vertical => (el.y, el.height)
+    }
+  }
+
+  fn mouse(&self, mouse: &Mouse) -> i32 {
+    match self {
+      Axis::Horizontal => mouse.x,
+      Axis::Vertical => mouse.y
+    }
+  }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+struct Scrollbar {
+  axis: Axis,
+  track: Entity,
+  thumb: Entity,
+  dynamic_constraints: Entity,
+  /// The thumb's size and position (as fractions of the track) that are
+  /// currently constrained.
+  applied: (f64, f64)
+}
+
+
+impl Scrollbar {
+  fn track() -> Picture {
+    Picture::new()
+      .set_color(0, 0, 0, 64)
+      .fill_rect(0, 0, 1, 1)
+  }
+
+  fn thumb() -> Picture {
+    Picture::new()
+      .set_color(255, 255, 255, 192)
+      .fill_rect(0, 0, 1, 1)
+  }
+
+  /// Size the thumb to `ratio` of the track, `position` of the way along it.
+  fn new_dynamic_constraints(
+    axis: Axis,
+    track: Entity,
+    thumb: Entity,
+    ratio: f64,
+    position: f64
+  ) -> (Constraints<VariableX>, Constraints<VariableY>) {
+    let travel = (1.0 - ratio) * position;
+    match axis {
+      Axis::Horizontal => (
+        Constraints(vec![
+          thumb.width().is(track.width() * ratio),
+          thumb.left().is(track.left() + track.width() * travel)
+        ]),
+        Constraints(vec![])
+      ),
+      Axis::Vertical => (
+        Constraints(vec![]),
+        Constraints(vec![
+          thumb.height().is(track.height() * ratio),
+          thumb.top().is(track.top() + track.height() * travel)
+        ])
+      )
+    }
+  }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+struct Drag {
+  axis: Axis,
+  mouse_start: i32,
+  offset_start: f64
+}
+
+
+/// A viewport onto content that can be bigger than it. The content is kept at
+/// the viewport's top left minus the scroll offset, which is given to the
+/// layout solvers as the `scroll_x` and `scroll_y` edit variables of the
+/// scroll view entity.
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct ScrollView {
+  content: Entity,
+  scrollbars: Vec<Scrollbar>,
+  static_constraints: Entity,
+  kinetic: bool,
+  offset: [f64; 2],
+  velocity: [f64; 2],
+  dragging: Option<Drag>
+}
+
+
+impl ScrollView {
+  /// The entity being scrolled.
+  pub fn content(&self) -> Entity {
+    self.content
+  }
+
+  /// How far the content is scrolled right and down.
+  pub fn offset(&self) -> (f64, f64) {
+    (self.offset[0], self.offset[1])
+  }
+
+  /// Jump to the given offset. It is kept inside the content by the
+  /// ScrollSystem.
+  pub fn scroll_to(&mut self, x: f64, y: f64) {
+    self.offset = [x, y];
+    self.velocity = [0.0, 0.0];
+  }
+
+  fn scrolls(&self, axis: Axis) -> bool {
+    self
+      .scrollbars
+      .iter()
+      .any(|bar| bar.axis == axis)
+  }
+}
+
+
+pub struct ScrollViewBuilder {
+  content: Entity,
+  horizontal: bool,
+  vertical: bool,
+  kinetic: bool
+}
+
+
+impl ScrollViewBuilder {
+  /// Scroll the given content entity, which should be sized but not
+  /// positioned - the scroll view positions it.
+  pub fn new(content: Entity) -> ScrollViewBuilder {
+    ScrollViewBuilder {
+      content,
+      horizontal: true,
+      vertical: true,
+      kinetic: false
+    }
+  }
+
+  /// Whether the content can scroll left and right. Defaults to true.
+  pub fn horizontal(self, horizontal: bool) -> Self {
+    let mut sb = self;
+    sb.horizontal = horizontal;
+    sb
+  }
+
+  /// Whether the content can scroll up and down. Defaults to true.
+  pub fn vertical(self, vertical: bool) -> Self {
+    let mut sb = self;
+    sb.vertical = vertical;
+    sb
+  }
+
+  /// Whether the mouse wheel flings the content and lets it slow down, rather
+  /// than moving it a fixed step. Defaults to false.
+  pub fn kinetic(self, kinetic: bool) -> Self {
+    let mut sb = self;
+    sb.kinetic = kinetic;
+    sb
+  }
+
+  fn build_scrollbar(ui: &mut UI, view: Entity, axis: Axis) -> Scrollbar {
+    let axis_name =
+      match axis {
+        Axis::Horizontal => "horizontal",
+        Axis::Vertical => "vertical"
+      };
+    let track =
+      ElementBuilder::new()
+      .picture(&Scrollbar::track())
+      .name(&format!("ScrollView {} track", axis_name))
+      .parent(view)
+      .build(ui);
+    let thumb =
+      ElementBuilder::new()
+      .picture(&Scrollbar::thumb())
+      .name(&format!("ScrollView {} thumb", axis_name))
+      .parent(view)
+      .build(ui);
+    let (xs, ys) =
+      Scrollbar::new_dynamic_constraints(axis, track, thumb, 1.0, 0.0);
+    let dynamic_constraints =
+      ElementBuilder::new()
+      .name(&format!("ScrollView {} thumb constraints", axis_name))
+      .x_constraints(xs.0)
+      .y_constraints(ys.0)
+      .build(ui);
+    Scrollbar {
+      axis,
+      track,
+      thumb,
+      dynamic_constraints,
+      applied: (1.0, 0.0)
+    }
+  }
+
+  pub fn build(self, ui: &mut UI) -> Entity {
+    let view =
+      ElementBuilder::new()
+      .name("ScrollView")
+      .build(ui);
+    ui.update(view, Some(ClipChildren));
+    let content =
+      self.content;
+    ElementBuilder::new()
+      .parent(view)
+      .update(ui, content);
+
+    let mut scrollbars = vec![];
+    let mut xs:Vec<Constraint<VariableX>> = vec![
+      if self.horizontal {
+        content.left().is(view.left() - view.scroll_x())
+      } else {
+        content.left().is(view.left())
+      }
+    ];
+    let mut ys:Vec<Constraint<VariableY>> = vec![
+      if self.vertical {
+        content.top().is(view.top() - view.scroll_y())
+      } else {
+        content.top().is(view.top())
+      }
+    ];
+    let mut zs:Vec<Constraint<VariableZ>> = vec![
+      content.z_index().is(view.z_index() + 1)
+    ];
+    // Where the scrollbars meet in the corner, they leave room for each other
+    let corner =
+      if self.horizontal && self.vertical { SCROLLBAR_SIZE } else { 0 };
+    if self.horizontal {
+      let bar =
+        ScrollViewBuilder::build_scrollbar(ui, view, Axis::Horizontal);
+      xs.extend(vec![
+        bar.track.left().is(view.left()),
+        bar.track.width().is(view.width() - corner)
+      ]);
+      ys.extend(vec![
+        bar.track.top().is(view.bottom() - SCROLLBAR_SIZE),
+        bar.track.height().is(SCROLLBAR_SIZE),
+        bar.thumb.top().is(bar.track.top()),
+        bar.thumb.height().is(bar.track.height())
+      ]);
+      zs.extend(vec![
+        bar.track.z_index().is(view.z_index() + SCROLLBAR_Z),
+        bar.thumb.z_index().is(view.z_index() + SCROLLBAR_Z + 1)
+      ]);
+      scrollbars.push(bar);
+    }
+    if self.vertical {
+      let bar =
+        ScrollViewBuilder::build_scrollbar(ui, view, Axis::Vertical);
+      xs.extend(vec![
+        bar.track.left().is(view.right() - SCROLLBAR_SIZE),
+        bar.track.width().is(SCROLLBAR_SIZE),
+        bar.thumb.left().is(bar.track.left()),
+        bar.thumb.width().is(bar.track.width())
+      ]);
+      ys.extend(vec![
+        bar.track.top().is(view.top()),
+        bar.track.height().is(view.height() - corner)
+      ]);
+      zs.extend(vec![
+        bar.track.z_index().is(view.z_index() + SCROLLBAR_Z),
+        bar.thumb.z_index().is(view.z_index() + SCROLLBAR_Z + 1)
+      ]);
+      scrollbars.push(bar);
+    }
+    let static_constraints =
+      ElementBuilder::new()
+      .name("ScrollView static constraints")
+      .x_constraints(xs)
+      .y_constraints(ys)
+      .z_constraints(zs)
+      .build(ui);
+
+    // Give the offsets to the solvers straight away, rather than leaving them
+    // free until the ScrollSystem first runs after the layout
+    if self.horizontal {
+      ui.world
+        .write_resource::<EditVariables<VariableX>>()
+        .0
+        .insert(VariableX::Scroll(Some(view)), 0.0);
+    }
+    if self.vertical {
+      ui.world
+        .write_resource::<EditVariables<VariableY>>()
+        .0
+        .insert(VariableY::Scroll(Some(view)), 0.0);
+    }
+
+    ui.update(
+      view,
+      Some(
+        ScrollView {
+          content,
+          scrollbars,
+          static_constraints,
+          kinetic: self.kinetic,
+          offset: [0.0, 0.0],
+          velocity: [0.0, 0.0],
+          dragging: None
+        }
+      )
+    );
+
+    view
+  }
+}
+
+
+/// Moves scroll views' content with the mouse wheel and scrollbar thumbs.
+/// Like the rest of the widgets it works from the boxes solved during the
+/// previous maintain, so scrolling shows up in the next layout.
+pub struct ScrollSystem {
+  last_run: Option<Instant>
+}
+
+
+impl ScrollSystem {
+  pub fn new() -> ScrollSystem {
+    ScrollSystem {
+      last_run: None
+    }
+  }
+}
+
+
+impl<'a> System<'a> for ScrollSystem {
+  type SystemData = (
+    Entities<'a>,
+    Read<'a, Mouse>,
+    Write<'a, MouseWheel>,
+    Write<'a, EditVariables<VariableX>>,
+    Write<'a, EditVariables<VariableY>>,
+    ReadStorage<'a, ClipChildren>,
+    WriteStorage<'a, Constraints<VariableX>>,
+    WriteStorage<'a, Constraints<VariableY>>,
+    ReadStorage<'a, ElementBox>,
+    ReadStorage<'a, Events>,
+    WriteStorage<'a, Invisible>,
+    ReadStorage<'a, Parent>,
+    WriteStorage<'a, ScrollView>
+  );
+
+  fn run(
+    &mut self,
+    (entities,
+     mouse,
+     mut wheel,
+     mut x_edits,
+     mut y_edits,
+     clips,
+     mut x_constraints,
+     mut y_constraints,
+     element_boxes,
+     events,
+     mut invisibles,
+     parents,
+     mut views
+    ): Self::SystemData
+  ) {
+    let now =
+      Instant::now();
+    let dt =
+      self
+      .last_run
+      .map(|last| now.duration_since(last).as_secs_f64())
+      .unwrap_or(0.0);
+    self.last_run =
+      Some(now);
+
+    // The wheel scrolls the topmost scroll view under the mouse
+    let wheel_delta =
+      [wheel.x as f64 * WHEEL_STEP, -wheel.y as f64 * WHEEL_STEP];
+    *wheel =
+      MouseWheel::default();
+    let may_wheeled:Option<Entity> =
+      if wheel_delta != [0.0, 0.0] {
+        (&entities, &views, &element_boxes)
+          .join()
+          .filter(|(ent, _, el)| {
+            el.contains(mouse.x, mouse.y)
+              && clip_box(*ent, &parents, &clips, &element_boxes)
+                .map(|clip| clip.contains(mouse.x, mouse.y))
+                .unwrap_or(true)
+          })
+          .max_by_key(|(_, _, el)| el.z)
+          .map(|(ent, _, _)| ent)
+      } else {
+        None
+      };
+
+    for (ent, view) in (&entities, &mut views).join() {
+      let el =
+        element_boxes
+        .get(ent)
+        .cloned()
+        .unwrap_or(ElementBox::new());
+      let content_el =
+        element_boxes
+        .get(view.content)
+        .cloned()
+        .unwrap_or(ElementBox::new());
+
+      // How far the content can scroll along each axis
+      let max_offset =
+        [Axis::Horizontal, Axis::Vertical]
+        .iter()
+        .map(|axis| {
+          if view.scrolls(*axis) {
+            let (_, view_len) = axis.span(&el);
+            let (_, content_len) = axis.span(&content_el);
+            scroll_range(view_len, content_len)
+          } else {
+            0.0
+          }
+        })
+        .collect::<Vec<_>>();
+
+      if may_wheeled == Some(ent) {
+        for i in 0..2 {
+          let (offset, velocity) =
+            wheel_scroll(view.offset[i], view.velocity[i], wheel_delta[i], view.kinetic);
+          view.offset[i] = offset;
+          view.velocity[i] = velocity;
+        }
+      }
+
+      if view.kinetic {
+        for i in 0..2 {
+          let (offset, velocity) =
+            glide(view.offset[i], view.velocity[i], dt);
+          view.offset[i] = offset;
+          view.velocity[i] = velocity;
+        }
+      }
+
+      // Start dragging a thumb when it's pressed
+      let offset =
+        view.offset;
+      let may_grabbed =
+        view
+        .scrollbars
+        .iter()
+        .find(|bar| {
+          events
+            .get(bar.thumb)
+            .map(|evs| evs.0.contains(&Event::MouseDown))
+            .unwrap_or(false)
+        })
+        .map(|bar| Drag {
+          axis: bar.axis,
+          mouse_start: bar.axis.mouse(&mouse),
+          offset_start: offset[bar.axis.index()]
+        });
+      if may_grabbed.is_some() {
+        view.dragging = may_grabbed;
+      }
+
+      if let Some(drag) = view.dragging.clone() {
+        if mouse.left_btn_down {
+          let may_bar =
+            view
+            .scrollbars
+            .iter()
+            .find(|bar| bar.axis == drag.axis);
+          if let Some(bar) = may_bar {
+            let span_of = |part: Entity| {
+              let (_, len) =
+                drag
+                .axis
+                .span(&element_boxes.get(part).cloned().unwrap_or(ElementBox::new()));
+              len as f64
+            };
+            // How far the thumb can move along its track
+            let travel =
+              span_of(bar.track) - span_of(bar.thumb);
+            let i = drag.axis.index();
+            let moved =
+              (drag.axis.mouse(&mouse) - drag.mouse_start) as f64;
+            if let Some(offset) = dragged_offset(drag.offset_start, moved, max_offset[i], travel) {
+              view.offset[i] = offset;
+              view.velocity[i] = 0.0;
+            }
+          }
+        } else {
+          view.dragging = None;
+        }
+      }
+
+      for i in 0..2 {
+        let clamped =
+          clamp_offset(view.offset[i], max_offset[i]);
+        if clamped != view.offset[i] {
+          view.offset[i] = clamped;
+          view.velocity[i] = 0.0;
+        }
+      }
+
+      if view.scrolls(Axis::Horizontal) {
+        x_edits.0.insert(VariableX::Scroll(Some(ent)), view.offset[0]);
+      }
+      if view.scrolls(Axis::Vertical) {
+        y_edits.0.insert(VariableY::Scroll(Some(ent)), view.offset[1]);
+      }
+
+      // Size and place the thumbs, hiding scrollbars with nothing to scroll
+      let offset =
+        view.offset;
+      for bar in view.scrollbars.iter_mut() {
+        let i = bar.axis.index();
+        let (_, view_len) = bar.axis.span(&el);
+        let (_, content_len) = bar.axis.span(&content_el);
+        let (ratio, position) =
+          thumb_placement(view_len, content_len, offset[i], max_offset[i]);
+        if bar.applied != (ratio, position) {
+          let (xs, ys) =
+            Scrollbar::new_dynamic_constraints(bar.axis, bar.track, bar.thumb, ratio, position);
+          match bar.axis {
+            Axis::Horizontal => {
+              x_constraints
+                .insert(bar.dynamic_constraints, xs)
+                .expect("Could not update scrollbar thumb constraints");
+            }
+            Axis::Vertical => {
+              y_constraints
+                .insert(bar.dynamic_constraints, ys)
+                .expect("Could not update scrollbar thumb constraints");
+            }
+          }
+          bar.applied = (ratio, position);
+        }
+
+        let has_nothing_to_scroll =
+          max_offset[i] == 0.0;
+        for part in vec![bar.track, bar.thumb] {
+          if has_nothing_to_scroll && !invisibles.contains(part) {
+            invisibles
+              .insert(part, Invisible)
+              .expect("Could not hide scrollbar");
+          } else if !has_nothing_to_scroll && invisibles.contains(part) {
+            invisibles
+              .remove(part);
+          }
+        }
+      }
+    }
+
+    // Stop driving the offsets of scroll views that are gone
+    x_edits
+      .0
+      .retain(|variable, _| match variable {
+        VariableX::Scroll(Some(ent)) => views.contains(*ent),
+        _ => true
+      });
+    y_edits
+      .0
+      .retain(|variable, _| match variable {
+        VariableY::Scroll(Some(ent)) => views.contains(*ent),
+        _ => true
+      });
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn content_scrolls_as_far_as_it_overhangs() {
+    assert_eq!(scroll_range(100, 250), 150.0);
+    assert_eq!(scroll_range(100, 100), 0.0);
+    assert_eq!(scroll_range(100, 50), 0.0);
+  }
+
+  #[test]
+  fn the_wheel_steps_or_flings() {
+    assert_eq!(wheel_scroll(10.0, 0.0, WHEEL_STEP, false), (10.0 + WHEEL_STEP, 0.0));
+    assert_eq!(wheel_scroll(10.0, 0.0, -WHEEL_STEP, false), (10.0 - WHEEL_STEP, 0.0));
+    assert_eq!(wheel_scroll(10.0, 5.0, WHEEL_STEP, true), (10.0, 5.0 + WHEEL_STEP * FRICTION));
+  }
+
+  #[test]
+  fn gliding_slows_down_and_stops() {
+    let (offset, velocity) = glide(0.0, 100.0, 0.1);
+    assert_eq!(offset, 10.0);
+    assert_eq!(velocity, 100.0 * (1.0 - FRICTION * 0.1));
+    // Long frames don't turn the content around
+    assert_eq!(glide(0.0, 100.0, 1.0), (100.0, 0.0));
+    // Slow content stops
+    assert_eq!(glide(0.0, MIN_SPEED, 0.01).1, 0.0);
+    assert_eq!(glide(5.0, 0.0, 0.1), (5.0, 0.0));
+  }
+
+  #[test]
+  fn dragging_a_thumb_scrolls_in_proportion() {
+    // The thumb moves 80 pixels to scroll 400
+    assert_eq!(dragged_offset(0.0, 40.0, 400.0, 80.0), Some(200.0));
+    assert_eq!(dragged_offset(100.0, -20.0, 400.0, 80.0), Some(0.0));
+    assert_eq!(dragged_offset(100.0, 20.0, 400.0, 0.0), None);
+    assert_eq!(dragged_offset(100.0, 20.0, 400.0, -5.0), None);
+  }
+
+  #[test]
+  fn offsets_stay_inside_the_content() {
+    assert_eq!(clamp_offset(-10.0, 100.0), 0.0);
+    assert_eq!(clamp_offset(50.0, 100.0), 50.0);
+    assert_eq!(clamp_offset(150.0, 100.0), 100.0);
+    assert_eq!(clamp_offset(50.0, 0.0), 0.0);
+  }
+
+  #[test]
+  fn thumbs_show_how_much_is_in_view() {
+    assert_eq!(thumb_placement(100, 400, 0.0, 300.0), (0.25, 0.0));
+    assert_eq!(thumb_placement(100, 400, 150.0, 300.0), (0.25, 0.5));
+    assert_eq!(thumb_placement(100, 400, 300.0, 300.0), (0.25, 1.0));
+    // Everything is in view
+    assert_eq!(thumb_placement(100, 50, 0.0, 0.0), (1.0, 0.0));
+    assert_eq!(thumb_placement(100, 0, 0.0, 0.0), (1.0, 0.0));
+    // Thumbs don't get too small to grab
+    assert_eq!(thumb_placement(1, 1000, 0.0, 999.0).0, MIN_THUMB);
+  }
+}
@@ -0,0 +1,480 @@
+use specs::prelude::*;
+
+use cassowary::strength::*;
+
+use super::super::picture::Picture;
+use super::super::components::*;
+use super::super::UI;
+use super::event::{push_event, Key, Keys, Mouse};
+use super::focus::{Focus, Focusable};
+
+
+/// The width and height of a slider's thumb.
+const THUMB_SIZE: u32 = 16;
+/// The thickness of a slider's track.
+const TRACK_THICKNESS: u32 = 4;
+/// How long a slider is unless it is given a size.
+const DEFAULT_LENGTH: u32 = 150;
+/// How many key presses it takes to go from min to max on a slider without
+/// a step.
+const KEY_STEPS: f64 = 20.0;
+
+
+/// The value snapped to the step and kept between min and max. A step of 0
+/// (or less) means no snapping, and values that aren't numbers become min.
+fn snap(min: f64, max: f64, step: f64, value: f64) -> f64 {
+  let stepped =
+    if step > 0.0 {
+      min + ((value - min) / step).round() * step
+    } else {
+      value
+    };
+  f64::min(max, f64::max(min, stepped))
+}
+
+
+/// How far along from min to max the value is, from 0 to 1. Values that
+/// aren't numbers are at 0.
+fn fraction(min: f64, max: f64, value: f64) -> f64 {
+  if max > min {
+    // f64::max ignores NaN
+    f64::min(1.0, f64::max(0.0, (value - min) / (max - min)))
+  } else {
+    0.0
+  }
+}
+
+
+#[derive(Clone, Component, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct Slider {
+  vertical: bool,
+  min: f64,
+  max: f64,
+  step: f64,
+  value: f64,
+  track: Entity,
+  fill: Entity,
+  thumb: Entity,
+  static_constraints: Entity,
+  dynamic_constraints: Entity,
+  /// The fraction of the track the thumb is currently constrained at.
+  applied: f64,
+  dragging: bool,
+  focused: bool
+}
+
+
+impl Slider {
+  pub fn value(&self) -> f64 {
+    self.value
+  }
+
+  pub fn min(&self) -> f64 {
+    self.min
+  }
+
+  pub fn max(&self) -> f64 {
+    self.max
+  }
+
+  pub fn step(&self) -> f64 {
+    self.step
+  }
+
+  /// Set the value, snapped to the step and kept between min and max. This
+  /// doesn't count as a ValueChanged event.
+  pub fn set_value(&mut self, value: f64) {
+    self.value =
+      snap(self.min, self.max, self.step, value);
+  }
+
+  fn track() -> Picture {
+    Picture::new()
+      .set_color(0, 0, 0, 128)
+      .fill_rect(0, 0, 1, 1)
+  }
+
+  fn fill() -> Picture {
+    Picture::new()
+      .set_color(0x33, 0x66, 0xff, 255)
+      .fill_rect(0, 0, 1, 1)
+  }
+
+  fn thumb(focused: bool) -> Picture {
+    let (r, g, b) =
+      if focused {
+        (0x33, 0x66, 0xff)
+      } else {
+        (0x33, 0x33, 0x33)
+      };
+    Picture::new()
+      .set_color(r, g, b, 255)
+      .fill_rect(0, 0, THUMB_SIZE, THUMB_SIZE)
+      .set_color(255, 255, 255, 255)
+      .fill_rect(2, 2, THUMB_SIZE - 4, THUMB_SIZE - 4)
+  }
+
+  /// Put the thumb `fraction` of the way along the track - left to right, or
+  /// bottom to top when vertical.
+  fn new_dynamic_constraints(
+    vertical: bool,
+    track: Entity,
+    thumb: Entity,
+    fraction: f64
+  ) -> (Constraints<VariableX>, Constraints<VariableY>) {
+    if vertical {
+      ( Constraints(vec![]),
+        Constraints(vec![
+          thumb.center_y().is(track.bottom() - track.height() * fraction)
+        ])
+      )
+    } else {
+      ( Constraints(vec![
+          thumb.center_x().is(track.left() + track.width() * fraction)
+        ]),
+        Constraints(vec![])
+      )
+    }
+  }
+}
+
+
+pub struct SliderBuilder {
+  vertical: bool,
+  min: f64,
+  max: f64,
+  step: f64,
+  value: f64
+}
+
+
+impl SliderBuilder {
+  /// A horizontal slider from min to max, starting at min.
+  pub fn new(min: f64, max: f64) -> SliderBuilder {
+    SliderBuilder {
+      vertical: false,
+      min,
+      max: f64::max(min, max),
+      step: 0.0,
+      value: min
+    }
+  }
+
+  /// Whether the slider runs bottom (min) to top (max) instead of left to
+  /// right. Defaults to false.
+  pub fn vertical(self, vertical: bool) -> Self {
+    let mut sb = self;
+    sb.vertical = vertical;
+    sb
+  }
+
+  /// Values snap to multiples of the step from min. Zero, the default, means
+  /// no snapping.
+  pub fn step(self, step: f64) -> Self {
+    let mut sb = self;
+    sb.step = f64::max(0.0, step);
+    sb
+  }
+
+  pub fn value(self, value: f64) -> Self {
+    let mut sb = self;
+    sb.value = value;
+    sb
+  }
+
+  pub fn build(self, ui: &mut UI) -> Entity {
+    let slider =
+      ElementBuilder::new()
+      .name("Slider")
+      .build(ui);
+    let track =
+      ElementBuilder::new()
+      .picture(&Slider::track())
+      .name("Slider track")
+      .parent(slider)
+      .build(ui);
+    let fill =
+      ElementBuilder::new()
+      .picture(&Slider::fill())
+      .name("Slider fill")
+      .parent(slider)
+      .build(ui);
+    let thumb =
+      ElementBuilder::new()
+      .picture(&Slider::thumb(false))
+      .name("Slider thumb")
+      .parent(slider)
+      .build(ui);
+
+    // The track is inset by half a thumb at each end so the thumb stays
+    // inside the slider at min and max
+    let inset =
+      THUMB_SIZE / 2;
+    let (xs, ys) =
+      if self.vertical {
+        (
+          vec![
+            track.center_x().is(slider.center_x()),
+            track.width().is(TRACK_THICKNESS),
+            fill.left().is(track.left()),
+            fill.width().is(track.width()),
+            thumb.center_x().is(track.center_x()),
+            thumb.width().is(THUMB_SIZE),
+            slider.width().is(THUMB_SIZE).with_strength(WEAK),
+            slider.width().is_ge(THUMB_SIZE)
+          ],
+          vec![
+            track.top().is(slider.top() + inset),
+            track.height().is(slider.height() - THUMB_SIZE),
+            fill.bottom().is(track.bottom()),
+            fill.top().is(thumb.center_y()),
+            thumb.height().is(THUMB_SIZE),
+            slider.height().is(DEFAULT_LENGTH).with_strength(WEAK)
+          ]
+        )
+      } else {
+        (
+          vec![
+            track.left().is(slider.left() + inset),
+            track.width().is(slider.width() - THUMB_SIZE),
+            fill.left().is(track.left()),
+            fill.right().is(thumb.center_x()),
+            thumb.width().is(THUMB_SIZE),
+            slider.width().is(DEFAULT_LENGTH).with_strength(WEAK)
+          ],
+          vec![
+            track.center_y().is(slider.center_y()),
+            track.height().is(TRACK_THICKNESS),
+            fill.top().is(track.top()),
+            fill.height().is(track.height()),
+            thumb.center_y().is(track.center_y()),
+            thumb.height().is(THUMB_SIZE),
+            slider.height().is(THUMB_SIZE).with_strength(WEAK),
+            slider.height().is_ge(THUMB_SIZE)
+          ]
+        )
+      };
+    let static_constraints =
+      ElementBuilder::new()
+      .name("Slider static constraints")
+      .x_constraints(xs)
+      .y_constraints(ys)
+      .z_constraints(vec![
+        track.z_index().is(slider.z_index()),
+        fill.z_index().is(slider.z_index() + 1),
+        thumb.z_index().is(slider.z_index() + 2)
+      ])
+      .build(ui);
+    let value =
+      snap(self.min, self.max, self.step, self.value);
+    let applied =
+      fraction(self.min, self.max, value);
+    let (xs, ys) =
+      Slider::new_dynamic_constraints(self.vertical, track, thumb, applied);
+    let dynamic_constraints =
+      ElementBuilder::new()
+      .name("Slider dynamic constraints")
+      .x_constraints(xs.0)
+      .y_constraints(ys.0)
+      .build(ui);
+
+    ui.update(slider, Some(Focusable));
+    ui.update(
+      slider,
+      Some(
+        Slider {
+          vertical: self.vertical,
+          min: self.min,
+          max: self.max,
+          step: self.step,
+          value,
+          track,
+          fill,
+          thumb,
+          static_constraints,
+          dynamic_constraints,
+          applied,
+          dragging: false,
+          focused: false
+        }
+      )
+    );
+
+    slider
+  }
+}
+
+
+/// Moves sliders' thumbs with the mouse (pressing anywhere on a slider jumps
+/// the thumb there and drags it) and with the arrow keys when focused.
+pub struct SliderSystem;
+
+
+impl SliderSystem {
+  pub fn new() -> SliderSystem {
+    SliderSystem
+  }
+}
+
+
+impl<'a> System<'a> for SliderSystem {
+  type SystemData = (
+    Entities<'a>,
+    Read<'a, Focus>,
+    Read<'a, Keys>,
+    Read<'a, Mouse>,
+    WriteStorage<'a, Constraints<VariableX>>,
+    WriteStorage<'a, Constraints<VariableY>>,
+    ReadStorage<'a, ElementBox>,
+    WriteStorage<'a, Events>,
+    WriteStorage<'a, Picture>,
+    WriteStorage<'a, Slider>
+  );
+
+  fn run(
+    &mut self,
+    (entities,
+     focus,
+     keys,
+     mouse,
+     mut x_constraints,
+     mut y_constraints,
+     element_boxes,
+     mut events,
+     mut pictures,
+     mut sliders
+    ): Self::SystemData
+  ) {
+    for (ent, slider) in (&entities, &mut sliders).join() {
+      let value =
+        slider.value;
+
+      let was_pressed =
+        events
+        .get(ent)
+        .map(|evs| evs.0.contains(&Event::MouseDown))
+        .unwrap_or(false);
+      if was_pressed {
+        slider.dragging = true;
+      }
+      if slider.dragging && !mouse.left_btn_down {
+        slider.dragging = false;
+      }
+      if slider.dragging {
+        let track =
+          element_boxes
+          .get(slider.track)
+          .cloned()
+          .unwrap_or(ElementBox::new());
+        let may_fraction =
+          if slider.vertical && track.height > 0 {
+            Some((track.bottom() - mouse.y) as f64 / track.height as f64)
+          } else if !slider.vertical && track.width > 0 {
+            Some((mouse.x - track.left()) as f64 / track.width as f64)
+          } else {
+            None
+          };
+        if let Some(fraction) = may_fraction {
+          let new_value =
+            slider.min + fraction * (slider.max - slider.min);
+          slider.set_value(new_value);
+        }
+      }
+
+      if focus.0 == Some(ent) {
+        let key_step =
+          if slider.step > 0.0 {
+            slider.step
+          } else {
+            (slider.max - slider.min) / KEY_STEPS
+          };
+        let steps:f64 =
+          keys
+          .0
+          .iter()
+          .map(|key| {
+            match key {
+              Key::Right | Key::Up => 1.0,
+              Key::Left | Key::Down => -1.0,
+              _ => 0.0
+            }
+          })
+          .sum();
+        if steps != 0.0 {
+          let new_value =
+            slider.value + steps * key_step;
+          slider.set_value(new_value);
+        }
+      }
+
+      if slider.value != value {
+        push_event(&mut events, ent, Event::ValueChanged);
+      }
+
+      let fraction =
+        fraction(slider.min, slider.max, slider.value);
+      if slider.applied != fraction {
+        let (xs, ys) =
+          Slider::new_dynamic_constraints(slider.vertical, slider.track, slider.thumb, fraction);
+        if slider.vertical {
+          y_constraints
+            .insert(slider.dynamic_constraints, ys)
+            .expect("Could not update slider thumb constraints");
+        } else {
+          x_constraints
+            .insert(slider.dynamic_constraints, xs)
+            .expect("Could not update slider thumb constraints");
+        }
+        slider.applied = fraction;
+      }
+
+      let is_focused =
+        focus.0 == Some(ent);
+      if slider.focused != is_focused {
+        pictures
+          .insert(slider.thumb, Slider::thumb(is_focused))
+          .expect("Could not update slider thumb");
+        slider.focused = is_focused;
+      }
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn snap_rounds_to_the_nearest_step() {
+    assert_eq!(snap(0.0, 10.0, 2.0, 4.9), 4.0);
+    assert_eq!(snap(0.0, 10.0, 2.0, 5.1), 6.0);
+    // Steps count from min
+    assert_eq!(snap(1.0, 10.0, 2.0, 4.2), 5.0);
+    assert_eq!(snap(0.0, 10.0, 0.0, 4.2), 4.2);
+    assert_eq!(snap(0.0, 10.0, -1.0, 4.2), 4.2);
+  }
+
+  #[test]
+  fn snap_keeps_values_in_range() {
+    assert_eq!(snap(0.0, 10.0, 0.0, -5.0), 0.0);
+    assert_eq!(snap(0.0, 10.0, 0.0, 15.0), 10.0);
+    assert_eq!(snap(0.0, 10.0, 3.0, 11.0), 10.0);
+    assert_eq!(snap(5.0, 5.0, 1.0, 7.0), 5.0);
+    assert_eq!(snap(5.0, 5.0, 0.0, 3.0), 5.0);
+    assert_eq!(snap(0.0, 10.0, 0.0, std::f64::NAN), 0.0);
+    assert_eq!(snap(0.0, 10.0, 2.0, std::f64::NAN), 0.0);
+  }
+
+  #[test]
+  fn fraction_is_between_zero_and_one() {
+    assert_eq!(fraction(0.0, 10.0, 2.5), 0.25);
+    assert_eq!(fraction(-10.0, 10.0, 0.0), 0.5);
+    assert_eq!(fraction(0.0, 10.0, -5.0), 0.0);
+    assert_eq!(fraction(0.0, 10.0, 15.0), 1.0);
+    assert_eq!(fraction(5.0, 5.0, 5.0), 0.0);
+    assert_eq!(fraction(10.0, 0.0, 5.0), 0.0);
+    assert_eq!(fraction(0.0, 10.0, std::f64::NAN), 0.0);
+  }
+}
@@ -0,0 +1,90 @@
+use specs::prelude::*;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::super::stats::UiStats;
+
+
+/// Wraps a system, logging how long each of its runs takes. The time is kept
+/// in a cell of the wrapper's own rather than in a resource, so timing a
+/// system doesn't stop the dispatcher from running it alongside others.
+pub struct Timed<S> {
+  name: String,
+  nanos: Arc<AtomicU64>,
+  system: S
+}
+
+
+impl<S> Timed<S> {
+  pub fn new(name: &str, system: S) -> Timed<S> {
+    Timed {
+      name: name.to_string(),
+      nanos: Arc::new(AtomicU64::new(0)),
+      system
+    }
+  }
+}
+
+
+impl<'a, S> System<'a> for Timed<S>
+where
+  S: System<'a>,
+  S::SystemData: SystemData<'a>
+{
+  type SystemData = S::SystemData;
+
+  fn setup(&mut self, world: &mut World) {
+    self
+      .system
+      .setup(world);
+  }
+
+  fn run(&mut self, data: Self::SystemData) {
+    let start =
+      Instant::now();
+    self
+      .system
+      .run(data);
+    let elapsed =
+      start.elapsed();
+    trace!(target: "berry::timing", "{} took {:?}", self.name, elapsed);
+    self
+      .nanos
+      .store(elapsed.as_nanos() as u64, Ordering::Relaxed);
+  }
+}
+
+
+/// The timings of a set of Timed systems, to be copied into the UiStats
+/// after each dispatch.
+#[derive(Default)]
+pub struct SystemTimes(Vec<(String, Arc<AtomicU64>)>);
+
+
+impl SystemTimes {
+  pub fn new() -> SystemTimes {
+    SystemTimes::default()
+  }
+
+  /// Wrap the system in a Timed whose runs are recorded here.
+  pub fn time<S>(&mut self, name: &str, system: S) -> Timed<S> {
+    let timed =
+      Timed::new(name, system);
+    self.0.push((name.to_string(), timed.nanos.clone()));
+    timed
+  }
+
+  /// Record the last run of each system in the stats.
+  pub fn record(&self, stats: &mut UiStats) {
+    self
+      .0
+      .iter()
+      .for_each(|(name, nanos)| {
+        let elapsed =
+          Duration::from_nanos(nanos.load(Ordering::Relaxed));
+        stats.set_system_time(name, elapsed);
+      });
+  }
+}
@@ -1,17 +1,38 @@
 use specs::prelude::*;
 
+use std::time::Instant;
+
 use super::WindowSize;
 use super::components::*;
-use super::systems::event::{EventSystem, Mouse};
+use super::debug::DebugOverlay;
+use super::systems::aspect_ratio::AspectRatioSystem;
+use super::systems::baseline::{BaselineSystem, FontMetrics};
+use super::systems::event::{EventSystem, Key, Keys, Mouse, MouseWheel};
 use super::systems::layout::*;
 use super::systems::shrinkwrap::{ContentSize, ShrinkwrapSystem};
+use super::systems::progress::ProgressSystem;
+use super::systems::slider::SliderSystem;
 use super::systems::button::ButtonSystem;
+use super::systems::checkbox::CheckboxSystem;
+use super::systems::focus::FocusSystem;
+use super::systems::radio::RadioGroupSystem;
+use super::systems::scroll::ScrollSystem;
+use super::systems::timed::SystemTimes;
 use super::rasterizer::{Rasterizer, DrawingSystemData};
+use super::fonts::SharedFontRegistry;
+use super::measure::TextMeasurer;
+use super::introspect::{constraints_on, ConstraintInfo, Introspect};
+use super::query::Query;
+use super::selector::Selector;
+use super::stack::Container;
+use super::stats::UiStats;
 
 
 pub struct UI<'a> {
   pub world: World,
-  dispatcher: Dispatcher<'a, 'a>
+  dispatcher: Dispatcher<'a, 'a>,
+  times: SystemTimes,
+  debug: bool
 }
 
 
@@ -22,22 +43,42 @@ impl<'a> UI<'a> {
 
     world
       .setup::<DrawingSystemData>();
+    world
+      .register::<Container>();
+    world
+      .register::<Parent>();
+    world
+      .insert(TextMeasurer::new());
+    world
+      .insert(UiStats::new());
 
+    let mut times =
+      SystemTimes::new();
     let mut dispatcher =
       DispatcherBuilder::new()
-      .with(ShrinkwrapSystem, "shrinkwrap", &[])
-      .with(LayoutSystem::<VariableX>::new(), "layout_x", &[])
-      .with(LayoutSystem::<VariableY>::new(), "layout_y", &[])
-      .with(LayoutSystem::<VariableZ>::new(), "layout_z", &[])
-      .with(EventSystem::new(), "event", &[])
-      .with(ButtonSystem::new(), "button", &[])
+      .with(times.time("shrinkwrap", ShrinkwrapSystem), "shrinkwrap", &[])
+      .with(times.time("baseline", BaselineSystem::new()), "baseline", &[])
+      .with(times.time("layout_y", LayoutSystem::<VariableY>::new()), "layout_y", &[])
+      .with(times.time("aspect_ratio", AspectRatioSystem::new()), "aspect_ratio", &[])
+      .with(times.time("layout_x", LayoutSystem::<VariableX>::new()), "layout_x", &[])
+      .with(times.time("layout_z", LayoutSystem::<VariableZ>::new()), "layout_z", &[])
+      .with(times.time("event", EventSystem::new()), "event", &[])
+      .with(times.time("focus", FocusSystem::new()), "focus", &[])
+      .with(times.time("button", ButtonSystem::new()), "button", &[])
+      .with(times.time("checkbox", CheckboxSystem::new()), "checkbox", &[])
+      .with(times.time("radio", RadioGroupSystem::new()), "radio", &[])
+      .with(times.time("slider", SliderSystem::new()), "slider", &[])
+      .with(times.time("progress", ProgressSystem::new()), "progress", &[])
+      .with(times.time("scroll", ScrollSystem::new()), "scroll", &[])
       .build();
     dispatcher
       .setup(&mut world);
 
     UI {
       world,
-      dispatcher
+      dispatcher,
+      times,
+      debug: false
     }
   }
 
@@ -49,6 +90,25 @@ impl<'a> UI<'a> {
     *mouse_rez = mouse;
   }
 
+  /// Add mouse wheel clicks, to be used by the next maintain.
+  pub fn update_wheel(&mut self, x: i32, y: i32) {
+    let mut wheel: Write<MouseWheel> =
+      self
+      .world
+      .system_data();
+    wheel.x += x;
+    wheel.y += y;
+  }
+
+  /// Add a key press, to be used by the next maintain.
+  pub fn update_key(&mut self, key: Key) {
+    let mut keys: Write<Keys> =
+      self
+      .world
+      .system_data();
+    keys.0.push(key);
+  }
+
   pub fn update<C:Component>(&mut self, ent: Entity, may_component:Option<C>) {
     let mut data:WriteStorage<C> =
       self
@@ -82,6 +142,13 @@ impl<'a> UI<'a> {
   }
 
   pub fn maintain(&mut self, rasterizer: &mut Rasterizer) {
+    let start =
+      Instant::now();
+
+    // Draw text with the fonts it was measured with
+    rasterizer
+      .share_font_registry(self.font_registry());
+
     // Update the size of the window so layout has something
     // to work with
     {
@@ -114,10 +181,26 @@ impl<'a> UI<'a> {
       .dispatcher
       .dispatch(&mut self.world);
 
+    // Key presses have been handled by the widgets
+    self
+      .world
+      .write_resource::<Keys>()
+      .0
+      .clear();
+
     self
       .world
       .maintain();
 
+    // The overlay reads the window size, so gather it before the drawing
+    // data borrows that mutably
+    let may_overlay =
+      if self.debug {
+        Some(DebugOverlay::from_world(&self.world))
+      } else {
+        None
+      };
+
     let data:DrawingSystemData =
       self
       .world
@@ -128,9 +211,21 @@ impl<'a> UI<'a> {
       .world
       .system_data();
 
+    let mut font_metrics: WriteStorage<FontMetrics> =
+      self
+      .world
+      .system_data();
+
+    let mut measurer: Write<TextMeasurer> =
+      self
+      .world
+      .system_data();
+
     // Run through pictures and text and rasterize them, updating their
     // entity's content size
-    (&data.0, &data.4)
+    let rasterization_start =
+      Instant::now();
+    (&data.0, &data.6)
       .join()
       .for_each(|(ent, pic)| {
         let (_, w, h) =
@@ -147,12 +242,32 @@ impl<'a> UI<'a> {
           .insert(ent, cs)
           .expect("Could not insert content size");
       });
-    (&data.0, &data.5)
+    (&data.0, &data.7)
       .join()
       .for_each(|(ent, text)| {
-        let (_, w, h) =
+        let (w, h) =
           rasterizer
-          .get_text(text);
+          .measure_text(&mut measurer, text);
+        // Shrink-to-fit text needs its smaller glyphs in the atlas before
+        // drawing
+        if text.overflow == TextOverflow::ShrinkToFit {
+          data.2
+            .get(ent)
+            .filter(|el| el.width > 0 && el.height > 0)
+            .into_iter()
+            .for_each(|el| {
+              let _ =
+                rasterizer
+                .fit_text(&mut measurer, text, el.width, el.height);
+            });
+        }
+        if let Ok(metrics) = measurer.metrics(text) {
+          if font_metrics.get(ent) != Some(&metrics) {
+            font_metrics
+              .insert(ent, metrics)
+              .expect("Could not insert font metrics");
+          }
+        }
         let mut cs =
           content_sizes
           .get(ent)
@@ -165,9 +280,99 @@ impl<'a> UI<'a> {
           .expect("Could not insert content size");
       });
 
+    let rasterization =
+      rasterization_start.elapsed();
+
     // Draw the things
+    let drawing_start =
+      Instant::now();
+    rasterizer
+      .run_sdl2_drawing(data, &mut measurer, may_overlay.as_ref());
+    let drawing =
+      drawing_start.elapsed();
     rasterizer
-      .run_sdl2_drawing(data);
+      .end_frame();
+
+    let total =
+      start.elapsed();
+    trace!(target: "berry::timing", "maintain took {:?}", total);
+
+    let entities =
+      self
+      .world
+      .entities()
+      .join()
+      .count();
+    let (x_constraints, y_constraints, z_constraints) =
+      (
+        self.world.read_resource::<LayoutStats<VariableX>>().constraints(),
+        self.world.read_resource::<LayoutStats<VariableY>>().constraints(),
+        self.world.read_resource::<LayoutStats<VariableZ>>().constraints()
+      );
+    let mut stats =
+      self
+      .world
+      .write_resource::<UiStats>();
+    self
+      .times
+      .record(&mut stats);
+    stats.rasterization = rasterization;
+    stats.drawing = drawing;
+    stats.total = total;
+    stats.cached_text_textures = rasterizer.text_cache.len();
+    stats.cached_picture_textures = rasterizer.picture_cache.len();
+    stats.text_cache = rasterizer.text_cache.stats();
+    stats.picture_cache = rasterizer.picture_cache.stats();
+    stats.glyphs = rasterizer.atlas.glyph_count();
+    stats.glyph_pages = rasterizer.atlas.page_count();
+    stats.glyph_bytes = rasterizer.atlas.bytes();
+    stats.entities = entities;
+    stats.x_constraints = x_constraints;
+    stats.y_constraints = y_constraints;
+    stats.z_constraints = z_constraints;
+  }
+
+  /// The size of the given text, measured from its font file. Widgets can
+  /// use this to size themselves before anything has been rasterized.
+  pub fn measure_text(&self, text: &Text) -> Result<(u32, u32), String> {
+    self
+      .world
+      .write_resource::<TextMeasurer>()
+      .measure(text)
+  }
+
+  /// The font families used to measure and draw text. Register families
+  /// and fallbacks here.
+  pub fn font_registry(&self) -> SharedFontRegistry {
+    self
+      .world
+      .read_resource::<TextMeasurer>()
+      .registry
+      .clone()
+  }
+
+  /// Timings and counts from the last `maintain`.
+  pub fn stats(&self) -> UiStats {
+    let stats =
+      self
+      .world
+      .read_resource::<UiStats>();
+    (*stats).clone()
+  }
+
+  /// Turn the layout debug overlay on or off. While on, every element is
+  /// drawn with its outline, name, solved box and z, and the element under
+  /// the mouse is highlighted along with a list of its constraints.
+  pub fn set_debug(&mut self, debug: bool) {
+    self.debug = debug;
+  }
+
+  pub fn toggle_debug(&mut self) {
+    self.debug = !self.debug;
+  }
+
+  pub fn is_debugging(&self) -> bool {
+    self.debug
   }
 
   pub fn get_size(&self, ent: Entity) -> Option<(u32, u32)> {
@@ -194,6 +399,38 @@ impl<'a> UI<'a> {
       })
   }
 
+  /// List the constraints that affect any of the variables in the given
+  /// expression, eg `ui.constraints_on(pic.width())`.
+  pub fn constraints_on<T: Introspect>(&self, expr: Expression<T>) -> Vec<ConstraintInfo<T>> {
+    constraints_on(&self.world, &expr)
+  }
+
+  /// Start a query over the laid out entities.
+  pub fn query(&self) -> Query<'_> {
+    Query::new(&self.world)
+  }
+
+  /// Find the first entity with the given name.
+  pub fn find(&self, name: &str) -> Option<Entity> {
+    self
+      .query()
+      .named(name)
+      .first()
+      .map(|(ent, _)| ent)
+  }
+
+  /// Find the entities matching a selector like `"menu > Button[text='Start']"`.
+  pub fn select(&self, selector: &str) -> Result<std::vec::IntoIter<(Entity, ElementBox)>, String> {
+    let selector =
+      Selector::parse(selector)?;
+    Ok(
+      self
+        .query()
+        .matching(selector)
+        .iter()
+    )
+  }
+
   pub fn stage(&self) -> Stage {
     Stage
   }